@@ -12,41 +12,32 @@ pub fn plan_grid(proposal_id: Serde<::planning::ProposalID>, n: usize, spacing:
     let system = unsafe { &mut *SYSTEM };
     let world = &mut system.world();
 
-    let plan_manager = ::planning::PlanManagerID::global_first(world);
-
     use ::transport::transport_planning::RoadIntent;
-    use ::planning::{GestureID, GestureIntent};
+    use ::planning::generation::{GeneratorSettings, StreetLayout};
+    use ::util::random::{thread_rng, Rng};
     use ::descartes::P2;
 
-    for x in 0..n {
-        let id = GestureID::new();
-        let p1 = P2::new(x as f32 * spacing.0, 0.0);
-        let p2 = P2::new(x as f32 * spacing.0, n as f32 * spacing.0);
-        plan_manager.start_new_gesture(
-            proposal_id.0,
-            ::kay::MachineID(0),
-            id,
-            GestureIntent::Road(RoadIntent::new(3, 3)),
-            p1,
-            world,
-        );
-        plan_manager.add_control_point(proposal_id.0, id, p2, true, true, world);
-    }
+    let extent = n as f32 * spacing.0;
+    let settings = GeneratorSettings {
+        center: P2::new(extent / 2.0, extent / 2.0),
+        road_intent: RoadIntent::new(3, 3),
+        land_uses: ::compact::CVec::new(),
+        ..GeneratorSettings::new(
+            StreetLayout::Grid {
+                n_blocks: n as u32,
+                block_size: spacing.0,
+            },
+            thread_rng().gen(),
+        )
+    };
 
-    for y in 0..n {
-        let id = GestureID::new();
-        let p1 = P2::new(0.0, y as f32 * spacing.0);
-        let p2 = P2::new(n as f32 * spacing.0, y as f32 * spacing.0);
-        plan_manager.start_new_gesture(
-            proposal_id.0,
-            ::kay::MachineID(0),
-            id,
-            GestureIntent::Road(RoadIntent::new(3, 3)),
-            p1,
-            world,
-        );
-        plan_manager.add_control_point(proposal_id.0, id, p2, true, true, world);
-    }
+    ::planning::PlanManagerID::global_first(world).add_generated_plan(
+        proposal_id.0,
+        ::planning_browser::BrowserPlanningUIID::local_first(world).into(),
+        settings,
+        ::planning::StepID::new(),
+        world,
+    );
 }
 
 #[cfg_attr(
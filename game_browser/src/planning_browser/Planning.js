@@ -36,6 +36,33 @@ export const initialState = {
     },
     proposals: {
     },
+    // gestures dropped by the last rebase or preventing the last merge, per proposal
+    conflicts: {},
+    // why the preview or implementation of a proposal failed, per proposal
+    errors: {},
+    // expected lane length, affected buildings, cost etc. of the current preview, per proposal
+    estimates: {},
+    // draft/submitted/approved/implemented, with the votes so far and the quorum, per proposal
+    statuses: {},
+    // names of the checkpoints of the master plan, oldest first
+    checkpoints: [],
+    // the last requested comparison of two checkpoints
+    checkpointDiff: null,
+    rolledBackTo: null,
+    // ids of open and implemented proposals, as last requested
+    proposalIds: null,
+    // the last exported proposal, file is null if it didn't exist
+    exportedProposal: null,
+    // prototypes under construction and how far along they are
+    constructionProgress: [],
+    // implementations whose construction hasn't fully started yet, started first to last
+    constructionQueue: [],
+    // why the last cancellation of queued construction didn't happen
+    constructionCancelRefused: null,
+    // money of the city and how much of it waiting construction needs
+    treasury: { balance: 0, requested: 0 },
+    // the last action this machine wasn't allowed to do
+    permissionDenied: null,
     currentProposal: null,
     hoveredControlPoint: {},
     canvasMode: {
@@ -1,10 +1,20 @@
 use stdweb::serde::Serde;
-use kay::{World, Actor, External, ActorSystem, TypedID};
-use compact::{CHashMap};
+use kay::{World, MachineID, Actor, External, ActorSystem, TypedID, Fate};
+use compact::{CHashMap, CVec, CString, COption};
 use std::collections::HashMap;
 use michelangelo::{MeshGrouper};
 use planning::{ProposalID, Proposal, PrototypeID, PlanHistory, PlanResult,
-PlanHistoryUpdate, ProposalUpdate, PlanResultUpdate, ActionGroups};
+PlanHistoryUpdate, ProposalUpdate, PlanResultUpdate, ActionGroups, GestureID,
+PlanError};
+use planning::estimate::ProposalEstimate;
+use planning::permissions::PlanningAction;
+use planning::voting::{ProposalStatus, Ballot};
+use planning::checkpoints::PlanDiff;
+use planning::exchange::{ProposalFile, ProposalFileRecipient, ProposalFileRecipientID,
+ProposalListRecipient, ProposalListRecipientID};
+use economy::resources::ResourceAmount;
+use construction::ui::{ConstructionUI, PrototypeProgress};
+use construction::queue::{ConstructionGroupID, QueuedGroupInfo};
 use ::land_use::zone_planning::{LandUse, LAND_USES};
 use planning::ui::{PlanningUI, PlanningUIID};
 use browser_utils::{updated_groups_to_js, to_js_mesh, FrameListener, FrameListenerID};
@@ -28,6 +38,7 @@ pub fn move_gesture_point(
     let world = &mut system.world();
     ::planning::PlanManagerID::global_first(world).move_control_point(
         proposal_id.0,
+        BrowserPlanningUIID::local_first(world).into(),
         gesture_id.0,
         point_idx,
         new_position.0,
@@ -50,7 +61,7 @@ pub fn start_new_gesture(
     let world = &mut system.world();
     ::planning::PlanManagerID::global_first(world).start_new_gesture(
         proposal_id.0,
-        ::kay::MachineID(0),
+        BrowserPlanningUIID::local_first(world).into(),
         gesture_id.0,
         intent.0,
         start.0,
@@ -73,6 +84,7 @@ pub fn add_control_point(
     let world = &mut system.world();
     ::planning::PlanManagerID::global_first(world).add_control_point(
         proposal_id.0,
+        BrowserPlanningUIID::local_first(world).into(),
         gesture_id.0,
         new_point.0,
         add_to_end,
@@ -88,7 +100,8 @@ pub fn add_control_point(
 pub fn finish_gesture() {
     let system = unsafe { &mut *SYSTEM };
     let world = &mut system.world();
-    ::planning::PlanManagerID::global_first(world).finish_gesture(::kay::MachineID(0), world)
+    ::planning::PlanManagerID::global_first(world)
+        .finish_gesture(BrowserPlanningUIID::local_first(world).into(), world)
 }
 
 #[cfg_attr(
@@ -98,7 +111,11 @@ pub fn finish_gesture() {
 pub fn undo(proposal_id: Serde<::planning::ProposalID>) {
     let system = unsafe { &mut *SYSTEM };
     let world = &mut system.world();
-    ::planning::PlanManagerID::global_first(world).undo(proposal_id.0, world)
+    ::planning::PlanManagerID::global_first(world).undo(
+        proposal_id.0,
+        BrowserPlanningUIID::local_first(world).into(),
+        world,
+    )
 }
 
 #[cfg_attr(
@@ -108,7 +125,11 @@ pub fn undo(proposal_id: Serde<::planning::ProposalID>) {
 pub fn redo(proposal_id: Serde<::planning::ProposalID>) {
     let system = unsafe { &mut *SYSTEM };
     let world = &mut system.world();
-    ::planning::PlanManagerID::global_first(world).redo(proposal_id.0, world)
+    ::planning::PlanManagerID::global_first(world).redo(
+        proposal_id.0,
+        BrowserPlanningUIID::local_first(world).into(),
+        world,
+    )
 }
 
 #[cfg_attr(
@@ -118,7 +139,245 @@ pub fn redo(proposal_id: Serde<::planning::ProposalID>) {
 pub fn implement_proposal(proposal_id: Serde<::planning::ProposalID>) {
     let system = unsafe { &mut *SYSTEM };
     let world = &mut system.world();
-    ::planning::PlanManagerID::global_first(world).implement(proposal_id.0, world);
+    ::planning::PlanManagerID::global_first(world).implement(
+        proposal_id.0,
+        BrowserPlanningUIID::local_first(world).into(),
+        world,
+    );
+}
+
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    js_export
+)]
+pub fn rebase_proposal(proposal_id: Serde<::planning::ProposalID>) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::planning::PlanManagerID::global_first(world).rebase_proposal(
+        proposal_id.0,
+        BrowserPlanningUIID::local_first(world).into(),
+        world,
+    );
+}
+
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    js_export
+)]
+pub fn merge_proposals(
+    proposal_id: Serde<::planning::ProposalID>,
+    other_id: Serde<::planning::ProposalID>,
+) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::planning::PlanManagerID::global_first(world).merge_proposals(
+        proposal_id.0,
+        other_id.0,
+        BrowserPlanningUIID::local_first(world).into(),
+        world,
+    );
+}
+
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    js_export
+)]
+pub fn new_proposal() {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::planning::PlanManagerID::global_first(world).create_proposal(
+        ::planning::ProposalID::new(),
+        BrowserPlanningUIID::local_first(world).into(),
+        world,
+    );
+}
+
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    js_export
+)]
+pub fn share_proposal(proposal_id: Serde<::planning::ProposalID>, shared: bool) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::planning::PlanManagerID::global_first(world).share_proposal(
+        proposal_id.0,
+        BrowserPlanningUIID::local_first(world).into(),
+        shared,
+        world,
+    );
+}
+
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    js_export
+)]
+pub fn submit_proposal(proposal_id: Serde<::planning::ProposalID>) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::planning::PlanManagerID::global_first(world).submit_proposal(
+        proposal_id.0,
+        BrowserPlanningUIID::local_first(world).into(),
+        world,
+    );
+}
+
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    js_export
+)]
+pub fn withdraw_proposal(proposal_id: Serde<::planning::ProposalID>) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::planning::PlanManagerID::global_first(world).withdraw_proposal(
+        proposal_id.0,
+        BrowserPlanningUIID::local_first(world).into(),
+        world,
+    );
+}
+
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    js_export
+)]
+pub fn cast_vote(proposal_id: Serde<::planning::ProposalID>, approve: bool) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::planning::PlanManagerID::global_first(world).cast_vote(
+        proposal_id.0,
+        BrowserPlanningUIID::local_first(world).into(),
+        approve,
+        world,
+    );
+}
+
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    js_export
+)]
+pub fn create_checkpoint(name: String) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    let mut c_name = CString::new();
+    c_name.push_str(&name);
+    ::planning::PlanManagerID::global_first(world).create_checkpoint(
+        c_name,
+        BrowserPlanningUIID::local_first(world).into(),
+        world,
+    );
+}
+
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    js_export
+)]
+pub fn rollback_to_checkpoint(name: String) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    let mut c_name = CString::new();
+    c_name.push_str(&name);
+    ::planning::PlanManagerID::global_first(world).rollback_to_checkpoint(
+        c_name,
+        BrowserPlanningUIID::local_first(world).into(),
+        world,
+    );
+}
+
+// an empty name compares against the current master plan
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    js_export
+)]
+pub fn diff_checkpoints(from: String, to: String) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    let mut c_from = CString::new();
+    c_from.push_str(&from);
+    let mut c_to = CString::new();
+    c_to.push_str(&to);
+    ::planning::PlanManagerID::global_first(world).diff_checkpoints(
+        c_from,
+        c_to,
+        BrowserPlanningUIID::local_first(world).into(),
+        world,
+    );
+}
+
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    js_export
+)]
+pub fn prioritize_construction_group(group_id: Serde<ConstructionGroupID>) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::construction::ConstructionID::global_first(world).prioritize_group(
+        group_id.0,
+        BrowserPlanningUIID::local_first(world).into(),
+        world,
+    );
+}
+
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    js_export
+)]
+pub fn cancel_construction_group(group_id: Serde<ConstructionGroupID>) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::construction::ConstructionID::global_first(world).cancel_group(
+        group_id.0,
+        BrowserPlanningUIID::local_first(world).into(),
+        world,
+    );
+}
+
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    js_export
+)]
+pub fn export_proposal(proposal_id: Serde<::planning::ProposalID>) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    let machine = COption(Some(world.local_machine_id()));
+    ::planning::PlanManagerID::global_first(world).export_proposal(
+        proposal_id.0,
+        machine,
+        BrowserPlanningUIID::local_first(world).into(),
+        world,
+    );
+}
+
+// takes the contents of a plan file, the new proposal shows up with the next plans update
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    js_export
+)]
+pub fn import_proposal(file: String) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    match ProposalFile::from_json(&file) {
+        Ok(file) => ::planning::PlanManagerID::global_first(world).import_proposal(
+            ::planning::ProposalID::new(),
+            BrowserPlanningUIID::local_first(world).into(),
+            file,
+            world,
+        ),
+        Err(err) => js!{ console.error("Couldn't import proposal", @{err}) },
+    }
+}
+
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    js_export
+)]
+pub fn list_proposals() {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    let machine = COption(Some(world.local_machine_id()));
+    ::planning::PlanManagerID::global_first(world).list_proposals(
+        machine,
+        BrowserPlanningUIID::local_first(world).into(),
+        world,
+    );
 }
 
 #[derive(Compact, Clone)]
@@ -312,9 +571,10 @@ impl PlanningUI for BrowserPlanningUI {
 
     fn on_proposal_preview_update(
         &mut self,
-        _proposal_id: ProposalID,
+        proposal_id: ProposalID,
         result_update: &PlanResultUpdate,
         new_actions: &ActionGroups,
+        estimate: &ProposalEstimate,
         _world: &mut World,
     ) {
         use ::planning::PrototypeKind;
@@ -565,6 +825,268 @@ SwitchLanePrototype, IntersectionPrototype};
         self.result_preview.apply_update(result_update);
         self.actions_preview = new_actions.clone();
         self.awaiting_preview_update = false;
+
+        js! {
+            window.cbReactApp.setState(oldState => update(oldState, {
+                planning: {
+                    errors: {
+                        [@{Serde(proposal_id)}]: {"$set": null}
+                    },
+                    estimates: {
+                        [@{Serde(proposal_id)}]: {"$set": @{Serde(estimate)}}
+                    }
+                }
+            }));
+        }
+    }
+
+    fn on_proposal_rebased(
+        &mut self,
+        proposal_id: ProposalID,
+        conflicts: &CVec<GestureID>,
+        _world: &mut World,
+    ) {
+        js! {
+            window.cbReactApp.setState(oldState => update(oldState, {
+                planning: {
+                    conflicts: {
+                        [@{Serde(proposal_id)}]: {"$set": @{Serde(conflicts)}}
+                    }
+                }
+            }));
+        }
+    }
+
+    fn on_proposals_merged(
+        &mut self,
+        proposal_id: ProposalID,
+        _other_id: ProposalID,
+        conflicts: &CVec<GestureID>,
+        _world: &mut World,
+    ) {
+        js! {
+            window.cbReactApp.setState(oldState => update(oldState, {
+                planning: {
+                    conflicts: {
+                        [@{Serde(proposal_id)}]: {"$set": @{Serde(conflicts)}}
+                    }
+                }
+            }));
+        }
+    }
+
+    fn on_plan_error(&mut self, proposal_id: ProposalID, error: &PlanError, _world: &mut World) {
+        js! {
+            window.cbReactApp.setState(oldState => update(oldState, {
+                planning: {
+                    errors: {
+                        [@{Serde(proposal_id)}]: {"$set": @{Serde(error)}}
+                    }
+                }
+            }));
+        }
+
+        self.awaiting_preview_update = false;
+    }
+
+    fn on_permission_denied(
+        &mut self,
+        proposal_id: ProposalID,
+        machine: MachineID,
+        action: PlanningAction,
+        world: &mut World,
+    ) {
+        // denials are broadcast to everyone, only show our own
+        if machine != world.local_machine_id() {
+            return;
+        }
+
+        js! {
+            window.cbReactApp.setState(oldState => update(oldState, {
+                planning: {
+                    permissionDenied: {"$set": {
+                        proposalId: @{Serde(proposal_id)},
+                        action: @{Serde(action)}
+                    }}
+                }
+            }));
+        }
+
+        self.awaiting_preview_update = false;
+    }
+
+    fn on_proposal_status_changed(
+        &mut self,
+        proposal_id: ProposalID,
+        status: ProposalStatus,
+        ballot: &Ballot,
+        quorum: u32,
+        _world: &mut World,
+    ) {
+        js! {
+            window.cbReactApp.setState(oldState => update(oldState, {
+                planning: {
+                    statuses: {
+                        [@{Serde(proposal_id)}]: {"$set": {
+                            status: @{Serde(status)},
+                            ballot: @{Serde(ballot)},
+                            quorum: @{quorum}
+                        }}
+                    }
+                }
+            }));
+        }
+    }
+
+    fn on_checkpoints_update(&mut self, names: &CVec<CString>, _world: &mut World) {
+        let names = names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        js! {
+            window.cbReactApp.setState(oldState => update(oldState, {
+                planning: {
+                    checkpoints: {"$set": @{names}}
+                }
+            }));
+        }
+    }
+
+    fn on_checkpoint_diff(
+        &mut self,
+        from: &CString,
+        to: &CString,
+        diff: &PlanDiff,
+        _world: &mut World,
+    ) {
+        js! {
+            window.cbReactApp.setState(oldState => update(oldState, {
+                planning: {
+                    checkpointDiff: {"$set": {
+                        from: @{from.to_string()},
+                        to: @{to.to_string()},
+                        diff: @{Serde(diff)}
+                    }}
+                }
+            }));
+        }
+    }
+
+    fn on_rolled_back(&mut self, checkpoint: &CString, _world: &mut World) {
+        js! {
+            window.cbReactApp.setState(oldState => update(oldState, {
+                planning: {
+                    rolledBackTo: {"$set": @{checkpoint.to_string()}}
+                }
+            }));
+        }
+    }
+
+    fn on_treasury_update(
+        &mut self,
+        balance: ResourceAmount,
+        requested: ResourceAmount,
+        _world: &mut World,
+    ) {
+        js! {
+            window.cbReactApp.setState(oldState => update(oldState, {
+                planning: {
+                    treasury: {"$set": {balance: @{balance}, requested: @{requested}}}
+                }
+            }));
+        }
+    }
+}
+
+impl ProposalFileRecipient for BrowserPlanningUI {
+    fn on_proposal_exported(
+        &mut self,
+        proposal_id: ProposalID,
+        maybe_file: &COption<ProposalFile>,
+        _world: &mut World,
+    ) -> Fate {
+        js! {
+            window.cbReactApp.setState(oldState => update(oldState, {
+                planning: {
+                    exportedProposal: {"$set": {
+                        id: @{Serde(proposal_id)},
+                        file: @{Serde(maybe_file.as_ref())}
+                    }}
+                }
+            }));
+        }
+
+        Fate::Live
+    }
+}
+
+impl ProposalListRecipient for BrowserPlanningUI {
+    fn on_proposal_list(
+        &mut self,
+        open: &CVec<ProposalID>,
+        implemented: &CVec<ProposalID>,
+        _world: &mut World,
+    ) {
+        js! {
+            window.cbReactApp.setState(oldState => update(oldState, {
+                planning: {
+                    proposalIds: {"$set": {
+                        open: @{Serde(open.iter().collect::<Vec<_>>())},
+                        implemented: @{Serde(implemented.iter().collect::<Vec<_>>())}
+                    }}
+                }
+            }));
+        }
+    }
+}
+
+impl ConstructionUI for BrowserPlanningUI {
+    fn on_construction_progress(&mut self, progress: &CVec<PrototypeProgress>, _world: &mut World) {
+        js! {
+            window.cbReactApp.setState(oldState => update(oldState, {
+                planning: {
+                    constructionProgress: {"$set": @{Serde(progress)}}
+                }
+            }));
+        }
+    }
+
+    fn on_construction_finished(&mut self, prototype_id: PrototypeID, _world: &mut World) {
+        js! {
+            const finishedId = @{Serde(prototype_id)};
+            window.cbReactApp.setState(oldState => update(oldState, {
+                planning: {
+                    constructionProgress: {"$apply": progress =>
+                        progress.filter(p => p.prototype_id !== finishedId)
+                    }
+                }
+            }));
+        }
+    }
+
+    fn on_construction_queue(&mut self, groups: &CVec<QueuedGroupInfo>, _world: &mut World) {
+        js! {
+            window.cbReactApp.setState(oldState => update(oldState, {
+                planning: {
+                    constructionQueue: {"$set": @{Serde(groups)}}
+                }
+            }));
+        }
+    }
+
+    fn on_construction_cancel_refused(
+        &mut self,
+        group_id: ConstructionGroupID,
+        reason: &CString,
+        _world: &mut World,
+    ) {
+        js! {
+            window.cbReactApp.setState(oldState => update(oldState, {
+                planning: {
+                    constructionCancelRefused: {"$set": {
+                        groupId: @{Serde(group_id)},
+                        reason: @{reason.to_string()}
+                    }}
+                }
+            }));
+        }
     }
 }
 
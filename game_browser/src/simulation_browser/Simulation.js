@@ -5,7 +5,8 @@ import update from 'immutability-helper';
 export const initialState = {
     ticks: 0,
     time: [0, 0],
-    speed: 1
+    speed: 1,
+    paused: false
 }
 
 export function render(state, setState) {
@@ -14,12 +15,15 @@ export function render(state, setState) {
         <span className="sim-time-colon">:</span>
         {(state.simulation.time[1] + "").padStart(2, "0")}
         <Slider className="sim-speed"
-            value={state.simulation.speed == 0 ? 0 : Math.log2(state.simulation.speed) + 1}
+            value={(state.simulation.paused || state.simulation.speed == 0) ? 0 : Math.log2(state.simulation.speed) + 1}
             min={0} max={6}
             marks={{ 0: "||", 1: "1x", 3: "4x", 6: "32x" }}
             onChange={newSpeedLog => {
                 const newSpeed = newSpeedLog == 0 ? 0 : Math.pow(2, newSpeedLog - 1);
                 cbRustBrowser.set_sim_speed(newSpeed);
+                if (newSpeed > 0 && state.simulation.paused) {
+                    cbRustBrowser.resume_sim();
+                }
                 setState(oldState => update(oldState, { simulation: { speed: { $set: newSpeed } } }));
             }}
             tipFormatter={speed => speed ? `Speed: ${Math.pow(2, speed - 1)}x` : "Pause"}
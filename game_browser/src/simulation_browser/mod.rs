@@ -15,6 +15,36 @@ pub fn set_sim_speed(new_speed: u16) {
     ::simulation::SimulationID::global_first(world).set_speed(new_speed, world);
 }
 
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    js_export
+)]
+pub fn pause_sim() {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::simulation::SimulationID::global_first(world).pause(world);
+}
+
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    js_export
+)]
+pub fn resume_sim() {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::simulation::SimulationID::global_first(world).resume(world);
+}
+
+#[cfg_attr(
+    all(target_arch = "wasm32", target_os = "unknown"),
+    js_export
+)]
+pub fn step_sim(n_ticks: u32) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::simulation::SimulationID::global_first(world).step(n_ticks, world);
+}
+
 #[derive(Compact, Clone)]
 pub struct BrowserSimulationUI {
     id: BrowserSimulationUIID,
@@ -39,6 +69,7 @@ impl SimulationUI for BrowserSimulationUI {
         &mut self,
         current_instant: ::simulation::Instant,
         speed: u16,
+        paused: bool,
         _world: &mut World,
     ) {
         js! {
@@ -48,7 +79,8 @@ impl SimulationUI for BrowserSimulationUI {
                     time: {"$set": @{
                         Serde(::simulation::TimeOfDay::from(current_instant).hours_minutes())
                     }},
-                    speed: {"$set": @{speed}}
+                    speed: {"$set": @{speed}},
+                    paused: {"$set": @{paused}}
                 }
             }))
         }
@@ -0,0 +1,118 @@
+// Compares the sleeper heap against the sorted list the simulation used before,
+// run with `cargo run --release --example benchmark_sleepers -- <n-sleepers>`
+
+extern crate citybound_common;
+extern crate compact;
+
+use compact::CVec;
+use std::time::{Instant as RealInstant, Duration as RealDuration};
+use citybound_common::util::random::{seed, Rng};
+use citybound_common::simulation::{Instant, Ticks, heap_push, heap_pop};
+
+const DEFAULT_N_SLEEPERS: usize = 10_000;
+const N_TICKS: usize = 100_000;
+
+// A synthetic stand-in for households: every sleeper goes back to sleep
+// for a random decision pause each time it is woken up
+const MAX_DECISION_PAUSE: usize = 2000;
+
+type BenchmarkWakeUp = (Instant, u32);
+
+trait BenchmarkQueue {
+    fn schedule(&mut self, at: Instant, sleeper: u32);
+    fn pop_due(&mut self, current_instant: Instant) -> Option<u32>;
+}
+
+// The sorted list the simulation used before the heap
+struct SortedList(CVec<BenchmarkWakeUp>);
+
+impl BenchmarkQueue for SortedList {
+    fn schedule(&mut self, at: Instant, sleeper: u32) {
+        let maybe_idx = self.0.binary_search_by_key(&at.iticks(), |&(t, _)| -(t.iticks()));
+        let insert_idx = match maybe_idx {
+            Ok(idx) | Err(idx) => idx,
+        };
+        self.0.insert(insert_idx, (at, sleeper));
+    }
+
+    fn pop_due(&mut self, current_instant: Instant) -> Option<u32> {
+        if self.0.last().map(|&(at, _)| at < current_instant).unwrap_or(false) {
+            self.0.pop().map(|(_, sleeper)| sleeper)
+        } else {
+            None
+        }
+    }
+}
+
+struct Heap(CVec<BenchmarkWakeUp>);
+
+impl BenchmarkQueue for Heap {
+    fn schedule(&mut self, at: Instant, sleeper: u32) {
+        heap_push(&mut self.0, (at, sleeper), |&(at, _)| at);
+    }
+
+    fn pop_due(&mut self, current_instant: Instant) -> Option<u32> {
+        if self.0.first().map(|&(at, _)| at < current_instant).unwrap_or(false) {
+            heap_pop(&mut self.0, |&(at, _)| at).map(|(_, sleeper)| sleeper)
+        } else {
+            None
+        }
+    }
+}
+
+fn run<Q: BenchmarkQueue>(
+    queue: &mut Q,
+    n_sleepers: usize,
+    n_ticks: usize,
+) -> (RealDuration, usize) {
+    let mut rng = seed(n_sleepers);
+    let started = RealInstant::now();
+    let mut n_wake_ups = 0;
+
+    for sleeper in 0..n_sleepers {
+        queue.schedule(Instant::new(0), sleeper as u32);
+    }
+
+    for tick in 0..n_ticks {
+        let current_instant = Instant::new(tick);
+        while let Some(sleeper) = queue.pop_due(current_instant) {
+            n_wake_ups += 1;
+            let pause = Ticks(rng.gen_range(1, MAX_DECISION_PAUSE as u32));
+            queue.schedule(current_instant + pause, sleeper);
+        }
+    }
+
+    (started.elapsed(), n_wake_ups)
+}
+
+fn as_millis(duration: RealDuration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + f64::from(duration.subsec_nanos()) / 1_000_000.0
+}
+
+fn main() {
+    let n_sleepers = ::std::env::args()
+        .nth(1)
+        .map(|n| n.parse().expect("Expected a number of sleepers"))
+        .unwrap_or(DEFAULT_N_SLEEPERS);
+    let n_ticks = N_TICKS;
+
+    println!(
+        "Benchmarking sleepers: {} sleepers over {} ticks",
+        n_sleepers, n_ticks
+    );
+
+    let (sorted_time, sorted_wake_ups) =
+        run(&mut SortedList(CVec::new()), n_sleepers, n_ticks);
+    println!(
+        "  sorted list: {:>10.2}ms ({} wake-ups)",
+        as_millis(sorted_time),
+        sorted_wake_ups
+    );
+
+    let (heap_time, heap_wake_ups) = run(&mut Heap(CVec::new()), n_sleepers, n_ticks);
+    println!(
+        "  heap:        {:>10.2}ms ({} wake-ups)",
+        as_millis(heap_time),
+        heap_wake_ups
+    );
+}
@@ -0,0 +1,120 @@
+use kay::{World, ActorSystem};
+use compact::{CVec, CString};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use planning::Action;
+use simulation::Instant;
+use super::Construction;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum ConstructionStage {
+    Started,
+    Finished,
+}
+
+// A construct, morph or destruct action starting or finishing
+#[derive(Compact, Clone, Debug, Serialize, Deserialize)]
+pub struct ConstructionEvent {
+    pub instant: Instant,
+    pub action: Action,
+    pub stage: ConstructionStage,
+}
+
+pub trait ConstructionEventObserver {
+    // Called at most once per tick, with all events of that tick
+    fn on_construction_events(&mut self, events: &CVec<ConstructionEvent>, world: &mut World);
+}
+
+impl Construction {
+    pub fn subscribe(&mut self, observer: ConstructionEventObserverID, _: &mut World) {
+        if !self.observers.contains(&observer) {
+            self.observers.push(observer);
+        }
+    }
+
+    pub fn unsubscribe(&mut self, observer: ConstructionEventObserverID, _: &mut World) {
+        self.observers.retain(|other| *other != observer);
+    }
+
+    pub(crate) fn record_event(
+        &mut self,
+        instant: Instant,
+        action: &Action,
+        stage: ConstructionStage,
+    ) {
+        self.unreported_events.push(ConstructionEvent {
+            instant,
+            action: action.clone(),
+            stage,
+        });
+    }
+
+    pub(crate) fn report_events(&mut self, world: &mut World) {
+        if self.unreported_events.is_empty() {
+            return;
+        }
+
+        for observer in &self.observers {
+            observer.on_construction_events(self.unreported_events.clone(), world);
+        }
+
+        self.unreported_events.clear();
+    }
+}
+
+// Appends all construction events to a file, one JSON object per line
+#[derive(Compact, Clone)]
+pub struct ConstructionLog {
+    id: ConstructionLogID,
+    path: CString,
+}
+
+impl ConstructionLog {
+    pub fn spawn(id: ConstructionLogID, path: &CString, _: &mut World) -> ConstructionLog {
+        if let Err(err) = File::create(&**path) {
+            println!("Couldn't start construction log {}: {}", &**path, err);
+        }
+
+        ConstructionLog {
+            id,
+            path: path.clone(),
+        }
+    }
+}
+
+impl ConstructionEventObserver for ConstructionLog {
+    fn on_construction_events(&mut self, events: &CVec<ConstructionEvent>, _: &mut World) {
+        let written = OpenOptions::new()
+            .append(true)
+            .open(&*self.path)
+            .and_then(|mut file| {
+                for event in events {
+                    writeln!(
+                        file,
+                        "{}",
+                        ::serde_json::to_string(event)
+                            .expect("Should be able to serialize construction event")
+                    )?;
+                }
+                Ok(())
+            });
+
+        if let Err(err) = written {
+            println!("Couldn't write to construction log {}: {}", &*self.path, err);
+        }
+    }
+}
+
+pub fn setup(system: &mut ActorSystem) {
+    system.register::<ConstructionLog>();
+    auto_setup(system);
+}
+
+pub fn spawn_log(world: &mut World, path: &str) -> ConstructionLogID {
+    let mut c_path = CString::new();
+    c_path.push_str(path);
+    ConstructionLogID::spawn(c_path, world)
+}
+
+pub mod kay_auto;
+pub use self::kay_auto::*;
@@ -1,11 +1,31 @@
-use kay::{World, Fate, ActorSystem};
-use compact::{CVec, CHashMap};
-use planning::{PrototypeID, Prototype, PrototypeKind, Action, ActionGroups};
-use simulation::{Simulatable, SimulatableID, Instant};
+use kay::{World, Fate, ActorSystem, MachineID, TypedID};
+use compact::{CVec, COption, CHashMap};
+use planning::{PrototypeID, Prototype, PrototypeKind, Action, ActionGroups, ProposalID,
+PlanManagerID};
+use planning::exchange::geojson::GeoJSONExporterID;
+use land_use::buildings::BuildingID;
+use simulation::{Simulatable, SimulatableID, Instant, Duration, Ticks, TICKS_PER_SIM_MINUTE};
+use economy::resources::ResourceAmount;
+use governance::treasury::{TreasuryID, FundingRecipient, FundingRecipientID};
+use persistence::{SaveGameWriterID, HouseholdState, ConstructionState};
+
+pub mod ui;
+use self::ui::{ConstructionUIID, PrototypeProgress};
+
+pub mod scheduling;
+use self::scheduling::{Footprint, Job, JobID, Funding, jobs_for, assign_crews, DEFAULT_MAX_CREWS};
+
+pub mod queue;
+use self::queue::{QueuedGroup, ConstructionGroupID};
+
+pub mod events;
+use self::events::{ConstructionEvent, ConstructionEventObserverID, ConstructionStage};
 
 pub trait Constructable {
     fn morph(&mut self, new_prototype: &Prototype, report_to: ConstructionID, world: &mut World);
     fn destruct(&mut self, report_to: ConstructionID, world: &mut World) -> Fate;
+    // Until this is called, constructables exist but can't be used yet
+    fn finish_construction(&mut self, world: &mut World);
 }
 
 impl Prototype {
@@ -18,6 +38,36 @@ impl Prototype {
         }
     }
 
+    // How long building this takes, depending on the amount of work
+    pub fn construction_time(&self) -> Duration {
+        match self.kind {
+            PrototypeKind::Road(ref road_prototype) => road_prototype.construction_time(),
+            PrototypeKind::Lot(ref lot_prototype) => lot_prototype.construction_time(),
+        }
+    }
+
+    pub fn destruction_time(&self) -> Duration {
+        match self.kind {
+            PrototypeKind::Road(ref road_prototype) => road_prototype.destruction_time(),
+            PrototypeKind::Lot(ref lot_prototype) => lot_prototype.destruction_time(),
+        }
+    }
+
+    // What the city pays for it
+    pub fn construction_cost(&self) -> ResourceAmount {
+        match self.kind {
+            PrototypeKind::Road(ref road_prototype) => road_prototype.construction_cost(),
+            PrototypeKind::Lot(ref lot_prototype) => lot_prototype.construction_cost(),
+        }
+    }
+
+    pub fn destruction_cost(&self) -> ResourceAmount {
+        match self.kind {
+            PrototypeKind::Road(ref road_prototype) => road_prototype.destruction_cost(),
+            PrototypeKind::Lot(ref lot_prototype) => lot_prototype.destruction_cost(),
+        }
+    }
+
     pub fn morphable_from(&self, other: &Self) -> bool {
         match (&self.kind, &other.kind) {
             (&PrototypeKind::Road(ref self_road), &PrototypeKind::Road(ref other_road)) => {
@@ -31,13 +81,63 @@ impl Prototype {
     }
 }
 
+#[derive(Copy, Clone)]
+struct BuiltPrototype {
+    destruction_time: Duration,
+    destruction_cost: ResourceAmount,
+    footprint: Footprint,
+}
+
+#[derive(Compact, Clone)]
+struct ActionInProgress {
+    action: Action,
+    footprint: Footprint,
+    // the prototype that exists (or is gone) once the action is done
+    prototype_id: PrototypeID,
+    started: Instant,
+    duration: Duration,
+    // constructables that still have to report back
+    pending: CVec<ConstructableID>,
+    // constructables that become usable once the action is done
+    to_finish: CVec<ConstructableID>,
+    immediately: bool,
+}
+
+impl ActionInProgress {
+    fn progress(&self, now: Instant) -> f32 {
+        let total = Ticks::from(self.duration).0;
+        if total == 0 {
+            1.0
+        } else {
+            ((now.ticks() - self.started.ticks()) as f32 / total as f32).min(1.0)
+        }
+    }
+
+    fn is_done(&self, now: Instant) -> bool {
+        self.pending.is_empty() && now >= self.started + self.duration
+    }
+}
+
 #[derive(Compact, Clone)]
 pub struct Construction {
     id: ConstructionID,
     constructed: CHashMap<PrototypeID, CVec<ConstructableID>>,
-    pending_constructables: CVec<ConstructableID>,
-    queued_action_groups: ActionGroups,
+    // remembered when constructing, since the prototype itself is gone by then
+    built: CHashMap<PrototypeID, BuiltPrototype>,
+    in_progress: CHashMap<JobID, ActionInProgress>,
+    queued_jobs: CVec<Job>,
+    next_job_id: u32,
+    // in the order in which their jobs are started
+    groups: CVec<QueuedGroup>,
+    next_group_id: u32,
+    // how many jobs can be worked on at the same time
+    max_crews: u32,
+    observers: CVec<ConstructionEventObserverID>,
+    // collected during a tick and sent to all observers at its end
+    unreported_events: CVec<ConstructionEvent>,
     new_prototypes: CHashMap<PrototypeID, Prototype>,
+    // moved back into their buildings once those are rebuilt after loading a city
+    households_to_restore: CHashMap<PrototypeID, CVec<HouseholdState>>,
 }
 
 impl Construction {
@@ -45,31 +145,57 @@ impl Construction {
         Construction {
             id,
             constructed: CHashMap::new(),
-            pending_constructables: CVec::new(),
-            queued_action_groups: ActionGroups(CVec::new()),
+            built: CHashMap::new(),
+            in_progress: CHashMap::new(),
+            queued_jobs: CVec::new(),
+            next_job_id: 0,
+            groups: CVec::new(),
+            next_group_id: 0,
+            max_crews: DEFAULT_MAX_CREWS,
+            observers: CVec::new(),
+            unreported_events: CVec::new(),
             new_prototypes: CHashMap::new(),
+            households_to_restore: CHashMap::new(),
         }
     }
 
+    pub fn set_max_crews(&mut self, max_crews: u32, _world: &mut World) {
+        self.max_crews = max_crews.max(1);
+    }
+
     pub fn action_done(&mut self, id: ConstructableID, _world: &mut World) {
-        self.pending_constructables
-            .retain(|pending_constructable| *pending_constructable != id);
+        for action in self.in_progress.values_mut() {
+            action
+                .pending
+                .retain(|pending_constructable| *pending_constructable != id);
+        }
     }
 
-    fn start_action(&mut self, action: &Action, world: &mut World) {
-        let new_pending_constructables = match *action {
+    fn start_job(&mut self, job: Job, started: Instant, world: &mut World) {
+        let (prototype_id, duration, to_finish, pending) = match job.action {
             Action::Construct(prototype_id) => {
-                print!("C ");
                 let new_prototype = self
                     .new_prototypes
                     .remove(prototype_id)
                     .expect("Should have prototype to be constructed");
                 let ids = new_prototype.construct(self.id, world);
                 self.constructed.insert(prototype_id, ids.clone());
-                ids
+                self.built.insert(
+                    prototype_id,
+                    BuiltPrototype {
+                        destruction_time: new_prototype.destruction_time(),
+                        destruction_cost: new_prototype.destruction_cost(),
+                        footprint: new_prototype.footprint(),
+                    },
+                );
+                (
+                    prototype_id,
+                    new_prototype.construction_time(),
+                    ids.clone(),
+                    ids,
+                )
             }
             Action::Morph(old_protoype_id, new_prototype_id) => {
-                print!("M ");
                 let ids = self
                     .constructed
                     .remove(old_protoype_id)
@@ -82,10 +208,19 @@ impl Construction {
                     id.morph(new_prototype.clone(), self.id, world);
                 }
                 self.constructed.insert(new_prototype_id, ids.clone());
-                ids
+                self.built.remove(old_protoype_id);
+                self.built.insert(
+                    new_prototype_id,
+                    BuiltPrototype {
+                        destruction_time: new_prototype.destruction_time(),
+                        destruction_cost: new_prototype.destruction_cost(),
+                        footprint: new_prototype.footprint(),
+                    },
+                );
+                // morphing only adjusts what already exists
+                (new_prototype_id, Duration(0), CVec::new(), ids)
             }
             Action::Destruct(prototype_id) => {
-                print!("D ");
                 let ids = self
                     .constructed
                     .remove(prototype_id)
@@ -93,47 +228,351 @@ impl Construction {
                 for id in &ids {
                     id.destruct(self.id, world);
                 }
-                ids
+                let duration = self
+                    .built
+                    .remove(prototype_id)
+                    .map(|built| built.destruction_time)
+                    .unwrap_or(Duration(0));
+                (prototype_id, duration, CVec::new(), ids)
             }
         };
 
-        self.pending_constructables
-            .extend(new_pending_constructables);
+        self.record_event(started, &job.action, ConstructionStage::Started);
+        self.in_progress.insert(
+            job.id,
+            ActionInProgress {
+                action: job.action,
+                footprint: job.footprint,
+                prototype_id,
+                started,
+                duration: if job.immediately {
+                    Duration(0)
+                } else {
+                    duration
+                },
+                pending,
+                to_finish,
+                immediately: job.immediately,
+            },
+        );
+    }
+
+    fn finish_done_jobs(&mut self, now: Instant, world: &mut World) {
+        let done = self
+            .in_progress
+            .pairs()
+            .filter(|&(_, action)| action.is_done(now))
+            .map(|(job_id, _)| *job_id)
+            .collect::<Vec<_>>();
+
+        for job_id in done {
+            let action = self.in_progress.remove(job_id).expect("just found it");
+            for id in &action.to_finish {
+                id.finish_construction(world);
+            }
+            if let Some(states) = self.households_to_restore.remove(action.prototype_id) {
+                for id in &action.to_finish {
+                    BuildingID::from_raw(id.as_raw()).restore_households(states.clone(), world);
+                }
+            }
+            self.record_event(now, &action.action, ConstructionStage::Finished);
+            for job in self.queued_jobs.iter_mut() {
+                job.waiting_for.retain(|other_job_id| *other_job_id != job_id);
+            }
+            ConstructionUIID::global_broadcast(world)
+                .on_construction_finished(action.prototype_id, world);
+        }
     }
 
+    // Ready jobs get a crew, which first waits for the treasury to pay for the job.
+    // Restoring a city needs neither crews nor money
+    fn start_ready_jobs(&mut self, now: Instant, world: &mut World) {
+        let n_busy_crews = self
+            .in_progress
+            .values()
+            .filter(|action| !action.immediately)
+            .count()
+            + self
+                .queued_jobs
+                .iter()
+                .filter(|job| job.funding != Funding::Needed)
+                .count();
+        let n_free_crews = (self.max_crews as usize).saturating_sub(n_busy_crews);
+
+        let (to_start, to_fund) = {
+            let queued_jobs = &self.queued_jobs;
+            let ready_jobs = self
+                .groups
+                .iter()
+                .filter(|group| !group.on_hold)
+                .flat_map(|group| queued_jobs.iter().filter(move |job| job.group == group.id))
+                .filter(|job| job.waiting_for.is_empty());
+
+            assign_crews(ready_jobs, n_free_crews)
+        };
+
+        for job_id in to_fund {
+            let cost = {
+                let job = self
+                    .queued_jobs
+                    .iter()
+                    .find(|job| job.id == job_id)
+                    .expect("just found it");
+                self.cost_of(&job.action)
+            };
+
+            if cost > 0.0 {
+                if let Some(job) = self.queued_jobs.iter_mut().find(|job| job.id == job_id) {
+                    job.funding = Funding::Requested(cost);
+                }
+                TreasuryID::global_first(world).request_funds(
+                    job_id.0,
+                    cost,
+                    self.id.into(),
+                    world,
+                );
+            } else {
+                self.start_queued_job(job_id, now, world);
+            }
+        }
+
+        for job_id in to_start {
+            self.start_queued_job(job_id, now, world);
+        }
+
+        self.drop_started_groups(world);
+    }
+
+    fn start_queued_job(&mut self, job_id: JobID, now: Instant, world: &mut World) {
+        let position = self
+            .queued_jobs
+            .iter()
+            .position(|job| job.id == job_id)
+            .expect("Should have queued job");
+        let job = self.queued_jobs.remove(position);
+        let group_id = job.group;
+        self.start_job(job, now, world);
+        self.mark_started(group_id, world);
+    }
+
+    fn cost_of(&self, action: &Action) -> ResourceAmount {
+        match *action {
+            Action::Construct(prototype_id) => self
+                .new_prototypes
+                .get(prototype_id)
+                .map(|prototype| prototype.construction_cost())
+                .unwrap_or(0.0),
+            // morphing only adjusts what already exists
+            Action::Morph(..) => 0.0,
+            Action::Destruct(prototype_id) => self
+                .built
+                .get(prototype_id)
+                .map(|built| built.destruction_cost)
+                .unwrap_or(0.0),
+        }
+    }
+
+    fn footprint_of(&self, action: &Action) -> Footprint {
+        let prototype_footprint = |prototype_id: PrototypeID| {
+            self.new_prototypes
+                .get(prototype_id)
+                .map(|prototype| prototype.footprint())
+        };
+
+        match *action {
+            Action::Construct(prototype_id) | Action::Morph(_, prototype_id) => {
+                prototype_footprint(prototype_id)
+            }
+            Action::Destruct(prototype_id) => self
+                .built
+                .get(prototype_id)
+                .map(|built| built.footprint)
+                .or_else(|| prototype_footprint(prototype_id)),
+        }.unwrap_or_else(Footprint::everywhere)
+    }
+
+    fn report_progress(&self, now: Instant, world: &mut World) {
+        let progress = self
+            .in_progress
+            .values()
+            .map(|action| PrototypeProgress {
+                prototype_id: action.prototype_id,
+                progress: action.progress(now),
+            }).collect::<Vec<_>>();
+
+        ConstructionUIID::global_broadcast(world).on_construction_progress(progress.into(), world);
+    }
+
+    // Every action becomes a job that waits for all earlier jobs on the same site
+    // or prototypes, see jobs_for
     pub fn implement(
         &mut self,
         actions_to_implement: &ActionGroups,
         new_prototypes: &CVec<Prototype>,
-        _world: &mut World,
+        proposal_id: &COption<ProposalID>,
+        immediately: bool,
+        world: &mut World,
     ) {
-        self.queued_action_groups
-            .0
-            .extend(actions_to_implement.0.clone());
         for new_prototype in new_prototypes {
             self.new_prototypes
                 .insert(new_prototype.id, new_prototype.clone());
         }
+
+        let group_id = ConstructionGroupID(self.next_group_id);
+        self.next_group_id += 1;
+
+        let new_jobs = {
+            let earlier_jobs = self
+                .queued_jobs
+                .iter()
+                .map(|job| (job.id, &job.action, &job.footprint))
+                .chain(
+                    self.in_progress
+                        .pairs()
+                        .map(|(job_id, other)| (*job_id, &other.action, &other.footprint)),
+                ).collect::<Vec<_>>();
+
+            jobs_for(
+                actions_to_implement,
+                |action| self.footprint_of(action),
+                &earlier_jobs,
+                self.next_job_id,
+                group_id,
+                immediately,
+            )
+        };
+        self.next_job_id += new_jobs.len() as u32;
+
+        if !new_jobs.is_empty() {
+            self.queued_jobs.extend(new_jobs);
+            self.groups.push(QueuedGroup {
+                id: group_id,
+                proposal_id: proposal_id.clone(),
+                started: false,
+                on_hold: false,
+            });
+            self.report_queue(world);
+        }
     }
-}
 
-impl Simulatable for Construction {
-    fn tick(&mut self, _dt: f32, _current_instant: Instant, world: &mut World) {
-        if self.pending_constructables.is_empty() {
-            if !self.queued_action_groups.0.is_empty() {
-                println!("Starting construction group:");
-                let next_action_group = self.queued_action_groups.0.remove(0);
-                for action in next_action_group.0 {
-                    self.start_action(&action, world);
+    // For estimates: asks the buildings of the given (occupied lot) prototypes
+    // how many households would have to move out
+    pub fn report_displaced_households(
+        &mut self,
+        building_prototype_ids: &CVec<PrototypeID>,
+        requester: PlanManagerID,
+        machine: MachineID,
+        world: &mut World,
+    ) {
+        for prototype_id in building_prototype_ids {
+            if let Some(ids) = self.constructed.get(*prototype_id) {
+                for id in ids {
+                    BuildingID::from_raw(id.as_raw()).report_households(
+                        requester,
+                        machine,
+                        *prototype_id,
+                        world,
+                    );
                 }
-                println!("\nFinished construction group:");
+            } else {
+                requester.on_displaced_households(machine, *prototype_id, 0, world);
             }
+        }
+    }
+
+    pub fn report_buildings_for_export(
+        &mut self,
+        building_prototype_ids: &CVec<PrototypeID>,
+        exporter: GeoJSONExporterID,
+        world: &mut World,
+    ) {
+        for prototype_id in building_prototype_ids {
+            if let Some(ids) = self.constructed.get(*prototype_id) {
+                for id in ids {
+                    BuildingID::from_raw(id.as_raw()).export_to(exporter, *prototype_id, world);
+                }
+            } else {
+                exporter.on_building_exported(*prototype_id, COption(None), world);
+            }
+        }
+    }
+
+    pub fn report_buildings_for_save(
+        &mut self,
+        building_prototype_ids: &CVec<PrototypeID>,
+        writer: SaveGameWriterID,
+        world: &mut World,
+    ) {
+        for prototype_id in building_prototype_ids {
+            if let Some(ids) = self.constructed.get(*prototype_id) {
+                for id in ids {
+                    BuildingID::from_raw(id.as_raw()).save_households_to(
+                        writer,
+                        *prototype_id,
+                        world,
+                    );
+                }
+            } else {
+                writer.on_building_saved(*prototype_id, 0, world);
+            }
+        }
+    }
+
+    pub fn save_to(&mut self, writer: SaveGameWriterID, world: &mut World) {
+        writer.on_construction_state(
+            ConstructionState {
+                next_job_id: self.next_job_id,
+            },
+            world,
+        );
+    }
+
+    pub fn restore(&mut self, state: &ConstructionState, _: &mut World) {
+        self.next_job_id = self.next_job_id.max(state.next_job_id);
+    }
+
+    pub fn restore_households(&mut self, states: &CVec<HouseholdState>, _: &mut World) {
+        for state in states {
+            let mut states_for_home = self
+                .households_to_restore
+                .get(state.home)
+                .cloned()
+                .unwrap_or_else(CVec::new);
+            states_for_home.push(state.clone());
+            self.households_to_restore.insert(state.home, states_for_home);
+        }
+    }
+}
+
+impl FundingRecipient for Construction {
+    fn on_funds_granted(&mut self, request_id: u32, amount: ResourceAmount, world: &mut World) {
+        let maybe_job = self.queued_jobs.iter_mut().find(|job| {
+            job.id == JobID(request_id) && match job.funding {
+                Funding::Requested(_) => true,
+                Funding::Needed | Funding::Granted(_) => false,
+            }
+        });
+
+        if let Some(job) = maybe_job {
+            job.funding = Funding::Granted(amount);
         } else {
-            println!(
-                "Construction pending: {} - queued groups: {}",
-                self.pending_constructables.len(),
-                self.queued_action_groups.0.len()
-            );
+            // the job was cancelled in the meantime
+            TreasuryID::global_first(world).deposit(amount, world);
+        }
+    }
+}
+
+impl Simulatable for Construction {
+    fn tick(&mut self, _dt: f32, current_instant: Instant, world: &mut World) {
+        let _measuring = ::util::profiling::measure("Construction::tick");
+        self.finish_done_jobs(current_instant, world);
+        self.start_ready_jobs(current_instant, world);
+
+        self.report_events(world);
+
+        let is_busy = !self.in_progress.is_empty() || !self.queued_jobs.is_empty();
+        if is_busy && current_instant.ticks() % TICKS_PER_SIM_MINUTE as usize == 0 {
+            self.report_progress(current_instant, world);
         }
     }
 }
@@ -141,10 +580,13 @@ impl Simulatable for Construction {
 pub fn setup(system: &mut ActorSystem) {
     system.register::<Construction>();
     auto_setup(system);
+    queue::auto_setup(system);
+    events::setup(system);
+    ui::auto_setup(system);
 }
 
-pub fn spawn(world: &mut World) {
-    ConstructionID::spawn(world);
+pub fn spawn(world: &mut World) -> ConstructionID {
+    ConstructionID::spawn(world)
 }
 
 mod kay_auto;
@@ -0,0 +1,272 @@
+use kay::{World, TypedID};
+use compact::{CVec, COption, CString};
+use planning::{PrototypeID, Prototype, Action, ActionGroups, ProposalID, PlanManagerID};
+use planning::ui::PlanningUIID;
+use journal::{JournalID, JournaledCommand};
+use governance::treasury::TreasuryID;
+use super::Construction;
+use super::scheduling::Funding;
+use super::ui::ConstructionUIID;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct ConstructionGroupID(pub u32);
+
+// The jobs of one implementation, which are started in the order of their groups
+#[derive(Compact, Clone)]
+pub struct QueuedGroup {
+    pub id: ConstructionGroupID,
+    // only groups that implement a proposal can be cancelled
+    pub proposal_id: COption<ProposalID>,
+    pub started: bool,
+    // while a cancellation is being decided, no more jobs of the group are started
+    pub on_hold: bool,
+}
+
+#[derive(Compact, Clone, Debug, Serialize, Deserialize)]
+pub struct QueuedGroupInfo {
+    pub group_id: ConstructionGroupID,
+    pub proposal_id: COption<ProposalID>,
+    pub started: bool,
+    // prototypes touched by the jobs that haven't started yet
+    pub prototypes: CVec<PrototypeID>,
+}
+
+impl Construction {
+    pub(crate) fn queue_info(&self) -> CVec<QueuedGroupInfo> {
+        self.groups
+            .iter()
+            .map(|group| QueuedGroupInfo {
+                group_id: group.id,
+                proposal_id: group.proposal_id.clone(),
+                started: group.started,
+                prototypes: self
+                    .queued_jobs
+                    .iter()
+                    .filter(|job| job.group == group.id)
+                    .flat_map(|job| job.action.involved_prototypes())
+                    .collect(),
+            }).collect()
+    }
+
+    pub(crate) fn report_queue(&self, world: &mut World) {
+        ConstructionUIID::global_broadcast(world).on_construction_queue(self.queue_info(), world);
+    }
+
+    // Forgets groups that have no more jobs waiting to be started
+    pub(crate) fn drop_started_groups(&mut self, world: &mut World) {
+        let n_groups = self.groups.len();
+        {
+            let queued_jobs = &self.queued_jobs;
+            self.groups
+                .retain(|group| queued_jobs.iter().any(|job| job.group == group.id));
+        }
+        if self.groups.len() != n_groups {
+            self.report_queue(world);
+        }
+    }
+
+    // The first time a job of the group starts, it can't be cancelled anymore
+    pub(crate) fn mark_started(&mut self, group_id: ConstructionGroupID, world: &mut World) {
+        let maybe_proposal_id = {
+            let group = match self.groups.iter_mut().find(|group| group.id == group_id) {
+                Some(group) => group,
+                None => return,
+            };
+            if group.started {
+                return;
+            }
+            group.started = true;
+            group.proposal_id.clone()
+        };
+
+        if let COption(Some(proposal_id)) = maybe_proposal_id {
+            PlanManagerID::global_first(world).on_construction_started(proposal_id, world);
+        }
+    }
+
+    pub fn get_queued_groups(&mut self, ui: ConstructionUIID, world: &mut World) {
+        ui.on_construction_queue(self.queue_info(), world);
+    }
+
+    // Asks the plan manager whether the machine may, which then moves the group to the front
+    pub fn prioritize_group(
+        &mut self,
+        group_id: ConstructionGroupID,
+        ui: PlanningUIID,
+        world: &mut World,
+    ) {
+        let proposal_id = match self.groups.iter().find(|group| group.id == group_id) {
+            Some(group) => group.proposal_id.clone(),
+            None => {
+                println!("Tried to prioritize unknown construction group {:?}", group_id);
+                return;
+            }
+        };
+
+        PlanManagerID::global_first(world).prioritize_implementation(
+            group_id,
+            proposal_id,
+            ui,
+            world,
+        );
+    }
+
+    // Its jobs are started before those of all other groups,
+    // but still wait for earlier jobs on the same site
+    pub fn move_group_to_front(
+        &mut self,
+        group_id: ConstructionGroupID,
+        ui: PlanningUIID,
+        world: &mut World,
+    ) {
+        let position = match self.groups.iter().position(|group| group.id == group_id) {
+            Some(position) => position,
+            None => return,
+        };
+
+        JournalID::local_first(world).record(
+            JournaledCommand::PrioritizeConstructionGroup(group_id, ui),
+            world,
+        );
+
+        let group = self.groups.remove(position);
+        self.groups = Some(group)
+            .into_iter()
+            .chain(self.groups.iter().cloned())
+            .collect();
+        self.report_queue(world);
+    }
+
+    fn refuse_cancel(&self, group_id: ConstructionGroupID, reason: &str, world: &mut World) {
+        let mut reason_string = CString::new();
+        reason_string.push_str(reason);
+        ConstructionUIID::global_broadcast(world).on_construction_cancel_refused(
+            group_id,
+            reason_string,
+            world,
+        );
+    }
+
+    // Puts the group on hold and asks the plan manager to take the implemented
+    // proposal back out of the master plan, which then drops or resumes the group
+    pub fn cancel_group(
+        &mut self,
+        group_id: ConstructionGroupID,
+        ui: PlanningUIID,
+        world: &mut World,
+    ) {
+        let (proposal_id, started) = match self.groups.iter().find(|group| group.id == group_id) {
+            Some(group) => (group.proposal_id.clone(), group.started),
+            None => {
+                self.refuse_cancel(group_id, "No such construction is queued", world);
+                return;
+            }
+        };
+
+        let proposal_id = match proposal_id {
+            COption(Some(proposal_id)) if !started => proposal_id,
+            COption(Some(_)) => {
+                self.refuse_cancel(group_id, "Construction has already started", world);
+                return;
+            }
+            COption(None) => {
+                self.refuse_cancel(group_id, "Only implemented proposals can be cancelled", world);
+                return;
+            }
+        };
+
+        let has_dependents = self
+            .queued_jobs
+            .iter()
+            .filter(|job| job.group != group_id)
+            .any(|job| {
+                job.waiting_for.iter().any(|job_id| {
+                    self.queued_jobs
+                        .iter()
+                        .any(|other| other.id == *job_id && other.group == group_id)
+                })
+            });
+
+        if has_dependents {
+            self.refuse_cancel(group_id, "Later construction builds upon it", world);
+            return;
+        }
+
+        JournalID::local_first(world).record(
+            JournaledCommand::CancelConstructionGroup(group_id, ui),
+            world,
+        );
+
+        if let Some(group) = self.groups.iter_mut().find(|group| group.id == group_id) {
+            group.on_hold = true;
+        }
+
+        PlanManagerID::global_first(world).revert_implementation(
+            proposal_id,
+            group_id,
+            ui,
+            world,
+        );
+    }
+
+    pub fn resume_group(
+        &mut self,
+        group_id: ConstructionGroupID,
+        reason: &CString,
+        world: &mut World,
+    ) {
+        if let Some(group) = self.groups.iter_mut().find(|group| group.id == group_id) {
+            group.on_hold = false;
+        }
+        self.refuse_cancel(group_id, reason, world);
+    }
+
+    // Called once the master plan doesn't contain the group's proposal anymore.
+    // The corrections bring what is built in line with the new master plan
+    pub fn drop_group(
+        &mut self,
+        group_id: ConstructionGroupID,
+        corrections: &ActionGroups,
+        new_prototypes: &CVec<Prototype>,
+        world: &mut World,
+    ) {
+        let dropped_jobs = self
+            .queued_jobs
+            .iter()
+            .filter(|job| job.group == group_id)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        self.queued_jobs.retain(|job| job.group != group_id);
+        self.groups.retain(|group| group.id != group_id);
+
+        for job in dropped_jobs {
+            match job.action {
+                Action::Construct(prototype_id) | Action::Morph(_, prototype_id) => {
+                    self.new_prototypes.remove(prototype_id);
+                }
+                Action::Destruct(_) => {}
+            }
+
+            // if a request is granted before it is withdrawn, we refund it once it arrives
+            match job.funding {
+                Funding::Needed => {}
+                Funding::Requested(_) => TreasuryID::global_first(world).withdraw_request(
+                    job.id.0,
+                    self.id.into(),
+                    world,
+                ),
+                Funding::Granted(amount) => TreasuryID::global_first(world).deposit(amount, world),
+            }
+        }
+
+        if corrections.0.iter().any(|group| !group.0.is_empty()) {
+            self.implement(corrections, new_prototypes, &COption(None), false, world);
+        }
+
+        self.report_queue(world);
+    }
+}
+
+pub mod kay_auto;
+pub use self::kay_auto::*;
@@ -0,0 +1,356 @@
+use compact::CVec;
+use descartes::{P2, Area};
+use planning::{PrototypeID, Prototype, PrototypeKind, Action, ActionGroups};
+use transport::transport_planning::RoadPrototype;
+use super::queue::ConstructionGroupID;
+use economy::resources::ResourceAmount;
+
+// Crews need some room around what they are building
+const CONSTRUCTION_SITE_MARGIN: f32 = 5.0;
+
+pub const DEFAULT_MAX_CREWS: u32 = 4;
+
+// The axis-aligned bounds of a construction site
+#[derive(Copy, Clone, Debug)]
+pub struct Footprint {
+    min: P2,
+    max: P2,
+}
+
+impl Footprint {
+    pub fn around<'a, I: IntoIterator<Item = &'a P2>>(points: I) -> Footprint {
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (
+            ::std::f32::INFINITY,
+            ::std::f32::INFINITY,
+            ::std::f32::NEG_INFINITY,
+            ::std::f32::NEG_INFINITY,
+        );
+
+        for point in points {
+            min_x = min_x.min(point.x);
+            min_y = min_y.min(point.y);
+            max_x = max_x.max(point.x);
+            max_y = max_y.max(point.y);
+        }
+
+        Footprint {
+            min: P2::new(
+                min_x - CONSTRUCTION_SITE_MARGIN,
+                min_y - CONSTRUCTION_SITE_MARGIN,
+            ),
+            max: P2::new(
+                max_x + CONSTRUCTION_SITE_MARGIN,
+                max_y + CONSTRUCTION_SITE_MARGIN,
+            ),
+        }
+    }
+
+    // Used when we don't know where something is, so it is ordered after everything else
+    pub fn everywhere() -> Footprint {
+        Footprint {
+            min: P2::new(::std::f32::NEG_INFINITY, ::std::f32::NEG_INFINITY),
+            max: P2::new(::std::f32::INFINITY, ::std::f32::INFINITY),
+        }
+    }
+
+    pub fn overlaps(&self, other: &Footprint) -> bool {
+        self.min.x <= other.max.x
+            && other.min.x <= self.max.x
+            && self.min.y <= other.max.y
+            && other.min.y <= self.max.y
+    }
+}
+
+fn area_footprint(area: &Area) -> Footprint {
+    Footprint::around(
+        area.primitives
+            .iter()
+            .flat_map(|primitive| primitive.boundary.path().points.iter()),
+    )
+}
+
+impl Prototype {
+    pub fn footprint(&self) -> Footprint {
+        match self.kind {
+            PrototypeKind::Road(RoadPrototype::Lane(ref lane)) => Footprint::around(&lane.0.points),
+            PrototypeKind::Road(RoadPrototype::SwitchLane(ref switch_lane)) => {
+                Footprint::around(&switch_lane.0.points)
+            }
+            PrototypeKind::Road(RoadPrototype::Intersection(ref intersection)) => {
+                area_footprint(&intersection.area)
+            }
+            PrototypeKind::Road(RoadPrototype::PavedArea(ref area)) => area_footprint(area),
+            PrototypeKind::Lot(ref lot_prototype) => area_footprint(&lot_prototype.lot.area),
+        }
+    }
+}
+
+impl Action {
+    pub fn involved_prototypes(&self) -> Vec<PrototypeID> {
+        match *self {
+            Action::Construct(prototype_id) | Action::Destruct(prototype_id) => vec![prototype_id],
+            Action::Morph(old_prototype_id, new_prototype_id) => {
+                vec![old_prototype_id, new_prototype_id]
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct JobID(pub u32);
+
+// Jobs are paid for by the treasury right before they start
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Funding {
+    Needed,
+    Requested(ResourceAmount),
+    Granted(ResourceAmount),
+}
+
+// One action, done by one construction crew
+#[derive(Compact, Clone)]
+pub struct Job {
+    pub id: JobID,
+    pub group: ConstructionGroupID,
+    pub action: Action,
+    pub footprint: Footprint,
+    // earlier jobs on the same site or prototypes, which have to be finished first
+    pub waiting_for: CVec<JobID>,
+    // used when restoring a city, which should be usable right away
+    pub immediately: bool,
+    pub funding: Funding,
+}
+
+// Two jobs have to happen in order if they touch the same place or prototypes
+pub fn conflict(
+    action: &Action,
+    footprint: &Footprint,
+    other: &Action,
+    other_footprint: &Footprint,
+) -> bool {
+    footprint.overlaps(other_footprint) || {
+        let involved = action.involved_prototypes();
+        other
+            .involved_prototypes()
+            .iter()
+            .any(|prototype_id| involved.contains(prototype_id))
+    }
+}
+
+// Every action becomes a job that waits for all earlier conflicting jobs,
+// including those of earlier action groups. Actions in the same group are independent
+pub fn jobs_for<F: Fn(&Action) -> Footprint>(
+    action_groups: &ActionGroups,
+    footprint_of: F,
+    earlier_jobs: &[(JobID, &Action, &Footprint)],
+    first_job_id: u32,
+    group: ConstructionGroupID,
+    immediately: bool,
+) -> Vec<Job> {
+    let mut new_jobs: Vec<(usize, Job)> = Vec::new();
+
+    for (group_index, action_group) in action_groups.0.iter().enumerate() {
+        for action in &action_group.0 {
+            let footprint = footprint_of(action);
+
+            let waiting_for = earlier_jobs
+                .iter()
+                .cloned()
+                .chain(
+                    new_jobs
+                        .iter()
+                        .filter(|&&(other_group_index, _)| other_group_index < group_index)
+                        .map(|&(_, ref job)| (job.id, &job.action, &job.footprint)),
+                ).filter(|&(_, other_action, other_footprint)| {
+                    conflict(action, &footprint, other_action, other_footprint)
+                }).map(|(job_id, _, _)| job_id)
+                .collect::<Vec<_>>();
+
+            let id = JobID(first_job_id + new_jobs.len() as u32);
+
+            new_jobs.push((
+                group_index,
+                Job {
+                    id,
+                    group,
+                    action: action.clone(),
+                    footprint,
+                    waiting_for: waiting_for.into(),
+                    immediately,
+                    funding: Funding::Needed,
+                },
+            ));
+        }
+    }
+
+    new_jobs.into_iter().map(|(_, job)| job).collect()
+}
+
+// Of the jobs that aren't waiting for anything anymore, returns those that can start
+// right away and those that get one of the free crews, but have to be paid for first
+pub fn assign_crews<'a, I: IntoIterator<Item = &'a Job>>(
+    ready_jobs: I,
+    n_free_crews: usize,
+) -> (Vec<JobID>, Vec<JobID>) {
+    let mut n_free_crews = n_free_crews;
+    let mut to_start = Vec::new();
+    let mut to_fund = Vec::new();
+
+    for job in ready_jobs {
+        if job.immediately {
+            to_start.push(job.id);
+        } else {
+            match job.funding {
+                Funding::Granted(_) => to_start.push(job.id),
+                Funding::Requested(_) => {}
+                Funding::Needed => {
+                    if n_free_crews > 0 {
+                        n_free_crews -= 1;
+                        to_fund.push(job.id);
+                    }
+                }
+            }
+        }
+    }
+
+    (to_start, to_fund)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use planning::IndependentActions;
+
+    fn site(x: f32, y: f32) -> Footprint {
+        Footprint::around(&[P2::new(x, y), P2::new(x + 10.0, y + 10.0)])
+    }
+
+    fn prototype(n: u32) -> PrototypeID {
+        PrototypeID::from_influences(n)
+    }
+
+    fn action_groups(groups: Vec<Vec<Action>>) -> ActionGroups {
+        ActionGroups(
+            groups
+                .into_iter()
+                .map(|actions| IndependentActions(actions.into()))
+                .collect::<Vec<_>>()
+                .into(),
+        )
+    }
+
+    fn job(id: u32, funding: Funding, immediately: bool) -> Job {
+        Job {
+            id: JobID(id),
+            group: ConstructionGroupID(0),
+            action: Action::Construct(prototype(id)),
+            footprint: site(0.0, 0.0),
+            waiting_for: CVec::new(),
+            immediately,
+            funding,
+        }
+    }
+
+    #[test]
+    fn footprints_overlap_including_their_margin() {
+        assert!(site(0.0, 0.0).overlaps(&site(5.0, 5.0)));
+        // the sites are 10 apart, but crews need room on both sides
+        assert!(site(0.0, 0.0).overlaps(&site(20.0, 0.0)));
+        assert!(!site(0.0, 0.0).overlaps(&site(100.0, 0.0)));
+        assert!(!site(0.0, 0.0).overlaps(&site(0.0, 100.0)));
+        assert!(Footprint::everywhere().overlaps(&site(1000.0, 1000.0)));
+    }
+
+    #[test]
+    fn actions_conflict_on_the_same_site_or_prototype() {
+        let (a, b) = (prototype(1), prototype(2));
+        let far_away = site(100.0, 0.0);
+
+        assert!(!conflict(
+            &Action::Construct(a),
+            &site(0.0, 0.0),
+            &Action::Construct(b),
+            &far_away
+        ));
+        assert!(conflict(
+            &Action::Construct(a),
+            &site(0.0, 0.0),
+            &Action::Construct(b),
+            &site(5.0, 0.0)
+        ));
+        // morphs involve both the old and the new prototype
+        assert!(conflict(
+            &Action::Morph(a, b),
+            &site(0.0, 0.0),
+            &Action::Destruct(a),
+            &far_away
+        ));
+    }
+
+    #[test]
+    fn disjoint_sites_run_in_parallel() {
+        let (a, b, c) = (prototype(1), prototype(2), prototype(3));
+        let footprint_of = |action: &Action| match *action {
+            Action::Construct(prototype_id) if prototype_id == a => site(0.0, 0.0),
+            Action::Construct(prototype_id) if prototype_id == b => site(100.0, 0.0),
+            _ => site(0.0, 100.0),
+        };
+        let groups = action_groups(vec![
+            vec![Action::Construct(a)],
+            vec![],
+            vec![Action::Construct(b), Action::Construct(c)],
+        ]);
+
+        let jobs = jobs_for(&groups, footprint_of, &[], 0, ConstructionGroupID(0), false);
+
+        assert_eq!(jobs.len(), 3);
+        assert!(jobs.iter().all(|job| job.waiting_for.is_empty()));
+    }
+
+    #[test]
+    fn destruction_comes_before_construction_on_the_same_site() {
+        let (old, new, earlier) = (prototype(1), prototype(2), prototype(3));
+        let groups = action_groups(vec![
+            vec![Action::Destruct(old)],
+            vec![],
+            vec![Action::Construct(new)],
+        ]);
+        let earlier_action = Action::Construct(earlier);
+        let earlier_footprint = site(5.0, 5.0);
+
+        let jobs = jobs_for(
+            &groups,
+            |_| site(0.0, 0.0),
+            &[(JobID(7), &earlier_action, &earlier_footprint)],
+            8,
+            ConstructionGroupID(1),
+            false,
+        );
+
+        assert_eq!(jobs[0].id, JobID(8));
+        assert_eq!(&jobs[0].waiting_for[..], &[JobID(7)][..]);
+        assert_eq!(jobs[1].id, JobID(9));
+        assert_eq!(&jobs[1].waiting_for[..], &[JobID(7), JobID(8)][..]);
+    }
+
+    #[test]
+    fn only_free_crews_take_on_new_jobs() {
+        let jobs = vec![
+            job(0, Funding::Needed, false),
+            job(1, Funding::Requested(10.0), false),
+            job(2, Funding::Granted(10.0), false),
+            job(3, Funding::Needed, false),
+            job(4, Funding::Needed, false),
+            job(5, Funding::Needed, true),
+        ];
+
+        let (to_start, to_fund) = assign_crews(&jobs, 2);
+
+        // restoring and already paid for jobs already have a crew
+        assert_eq!(to_start, vec![JobID(2), JobID(5)]);
+        assert_eq!(to_fund, vec![JobID(0), JobID(3)]);
+
+        let (_, to_fund) = assign_crews(&jobs, 0);
+        assert!(to_fund.is_empty());
+    }
+}
@@ -0,0 +1,29 @@
+use kay::World;
+use compact::{CVec, CString};
+use planning::PrototypeID;
+use super::queue::{ConstructionGroupID, QueuedGroupInfo};
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct PrototypeProgress {
+    pub prototype_id: PrototypeID,
+    // from 0.0 (just started) to 1.0 (about to be finished)
+    pub progress: f32,
+}
+
+pub trait ConstructionUI {
+    fn on_construction_progress(&mut self, progress: &CVec<PrototypeProgress>, _world: &mut World);
+
+    fn on_construction_finished(&mut self, prototype_id: PrototypeID, _world: &mut World);
+
+    fn on_construction_queue(&mut self, groups: &CVec<QueuedGroupInfo>, _world: &mut World);
+
+    fn on_construction_cancel_refused(
+        &mut self,
+        group_id: ConstructionGroupID,
+        reason: &CString,
+        _world: &mut World,
+    );
+}
+
+pub mod kay_auto;
+pub use self::kay_auto::*;
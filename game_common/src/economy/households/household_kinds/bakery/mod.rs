@@ -6,6 +6,7 @@ use economy::market::{Deal, EvaluationRequester, EvaluationRequesterID, Evaluate
 use land_use::buildings::BuildingID;
 
 use economy::households::{Household, HouseholdID, HouseholdCore, MemberIdx, Offer};
+use economy::immigration_and_development::HouseholdTypeToSpawn;
 
 #[derive(Compact, Clone)]
 pub struct Bakery {
@@ -126,16 +127,22 @@ impl Household for Bakery {
         "Bakery".to_owned()
     }
 
+    fn household_type(&self) -> HouseholdTypeToSpawn {
+        HouseholdTypeToSpawn::Bakery
+    }
+
     fn member_name(&self, member: MemberIdx) -> String {
         format!("Baker {}", member.0 + 1)
     }
 }
 
-use simulation::{Simulatable, SimulatableID, Sleeper, SleeperID, Instant, TICKS_PER_SIM_SECOND};
+use simulation::{Simulatable, SimulatableID, Sleeper, SleeperID, WakeUpTag, Instant,
+TICKS_PER_SIM_SECOND};
 const UPDATE_EVERY_N_SECS: u32 = 4;
 
 impl Simulatable for Bakery {
     fn tick(&mut self, _dt: f32, current_instant: Instant, world: &mut World) {
+        let _measuring = ::util::profiling::measure("Bakery::tick");
         if (current_instant.ticks() + self.id.as_raw().instance_id as usize)
             % (UPDATE_EVERY_N_SECS * TICKS_PER_SIM_SECOND) as usize
             == 0
@@ -146,7 +153,8 @@ impl Simulatable for Bakery {
 }
 
 impl Sleeper for Bakery {
-    fn wake(&mut self, current_instant: Instant, world: &mut World) {
+    fn wake(&mut self, current_instant: Instant, _: WakeUpTag, world: &mut World) {
+        let _measuring = ::util::profiling::measure("Bakery::wake");
         self.update_core(current_instant, world);
     }
 }
@@ -6,6 +6,7 @@ use economy::market::{Deal, EvaluationRequester, EvaluationRequesterID, Evaluate
 use land_use::buildings::BuildingID;
 
 use economy::households::{Household, HouseholdID, HouseholdCore, MemberIdx, Offer};
+use economy::immigration_and_development::HouseholdTypeToSpawn;
 
 #[derive(Compact, Clone)]
 pub struct CowFarm {
@@ -136,16 +137,22 @@ impl Household for CowFarm {
         "Cow Farm".to_owned()
     }
 
+    fn household_type(&self) -> HouseholdTypeToSpawn {
+        HouseholdTypeToSpawn::CowFarm
+    }
+
     fn member_name(&self, member: MemberIdx) -> String {
         format!("Farmer {}", member.0 + 1)
     }
 }
 
-use simulation::{Simulatable, SimulatableID, Sleeper, SleeperID, Instant, TICKS_PER_SIM_SECOND};
+use simulation::{Simulatable, SimulatableID, Sleeper, SleeperID, WakeUpTag, Instant,
+TICKS_PER_SIM_SECOND};
 const UPDATE_EVERY_N_SECS: u32 = 4;
 
 impl Simulatable for CowFarm {
     fn tick(&mut self, _dt: f32, current_instant: Instant, world: &mut World) {
+        let _measuring = ::util::profiling::measure("CowFarm::tick");
         if (current_instant.ticks() + self.id.as_raw().instance_id as usize)
             % (UPDATE_EVERY_N_SECS * TICKS_PER_SIM_SECOND) as usize
             == 0
@@ -156,7 +163,8 @@ impl Simulatable for CowFarm {
 }
 
 impl Sleeper for CowFarm {
-    fn wake(&mut self, current_instant: Instant, world: &mut World) {
+    fn wake(&mut self, current_instant: Instant, _: WakeUpTag, world: &mut World) {
+        let _measuring = ::util::profiling::measure("CowFarm::wake");
         self.update_core(current_instant, world);
     }
 }
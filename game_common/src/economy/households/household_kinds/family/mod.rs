@@ -15,6 +15,7 @@ use self::names::{family_name, member_name};
 
 use economy::households::{Household, HouseholdID, HouseholdCore,
 MemberIdx, Offer, OfferID, OfferIdx};
+use economy::immigration_and_development::HouseholdTypeToSpawn;
 
 #[derive(Compact, Clone)]
 pub struct Family {
@@ -59,10 +60,11 @@ impl Family {
     }
 }
 
-use simulation::{Sleeper, SleeperID};
+use simulation::{Sleeper, SleeperID, WakeUpTag};
 
 impl Sleeper for Family {
-    fn wake(&mut self, current_instant: Instant, world: &mut World) {
+    fn wake(&mut self, current_instant: Instant, _: WakeUpTag, world: &mut World) {
+        let _measuring = ::util::profiling::measure("Family::wake");
         self.update_core(current_instant, world);
     }
 }
@@ -213,6 +215,10 @@ impl Household for Family {
         format!("The {} Family", family_name(self.id))
     }
 
+    fn household_type(&self) -> HouseholdTypeToSpawn {
+        HouseholdTypeToSpawn::Family
+    }
+
     fn member_name(&self, member: MemberIdx) -> String {
         member_name(self.id, member)
     }
@@ -220,6 +226,7 @@ impl Household for Family {
 
 impl Simulatable for Family {
     fn tick(&mut self, _dt: f32, current_instant: Instant, world: &mut World) {
+        let _measuring = ::util::profiling::measure("Family::tick");
         self.on_tick(current_instant, world);
     }
 }
@@ -6,6 +6,7 @@ use economy::market::{Deal, EvaluationRequester, EvaluationRequesterID, Evaluate
 use land_use::buildings::BuildingID;
 
 use economy::households::{Household, HouseholdID, HouseholdCore, MemberIdx, Offer};
+use economy::immigration_and_development::HouseholdTypeToSpawn;
 
 #[derive(Compact, Clone)]
 pub struct Mill {
@@ -113,16 +114,22 @@ impl Household for Mill {
         "Mill".to_owned()
     }
 
+    fn household_type(&self) -> HouseholdTypeToSpawn {
+        HouseholdTypeToSpawn::Mill
+    }
+
     fn member_name(&self, member: MemberIdx) -> String {
         format!("Miller {}", member.0 + 1)
     }
 }
 
-use simulation::{Simulatable, SimulatableID, Sleeper, SleeperID, Instant, TICKS_PER_SIM_SECOND};
+use simulation::{Simulatable, SimulatableID, Sleeper, SleeperID, WakeUpTag, Instant,
+TICKS_PER_SIM_SECOND};
 const UPDATE_EVERY_N_SECS: u32 = 4;
 
 impl Simulatable for Mill {
     fn tick(&mut self, _dt: f32, current_instant: Instant, world: &mut World) {
+        let _measuring = ::util::profiling::measure("Mill::tick");
         if (current_instant.ticks() + self.id.as_raw().instance_id as usize)
             % (UPDATE_EVERY_N_SECS * TICKS_PER_SIM_SECOND) as usize
             == 0
@@ -133,7 +140,8 @@ impl Simulatable for Mill {
 }
 
 impl Sleeper for Mill {
-    fn wake(&mut self, current_instant: Instant, world: &mut World) {
+    fn wake(&mut self, current_instant: Instant, _: WakeUpTag, world: &mut World) {
+        let _measuring = ::util::profiling::measure("Mill::wake");
         self.update_core(current_instant, world);
     }
 }
@@ -9,6 +9,7 @@ use transport::pathfinding::RoughLocationID;
 use transport::pathfinding::trip::{TripListener, TripListenerID, TripID, TripResult};
 
 use economy::households::{Household, HouseholdID, HouseholdCore, MemberIdx, Offer};
+use economy::immigration_and_development::HouseholdTypeToSpawn;
 
 #[derive(Compact, Clone)]
 pub struct NeighboringTownTrade {
@@ -235,6 +236,10 @@ impl Household for NeighboringTownTrade {
         "Neighboring Town".to_owned()
     }
 
+    fn household_type(&self) -> HouseholdTypeToSpawn {
+        HouseholdTypeToSpawn::NeighboringTownTrade
+    }
+
     fn member_name(&self, member: MemberIdx) -> String {
         format!("Neighboring Town Worker {}", member.0 + 1)
     }
@@ -244,10 +249,11 @@ impl Household for NeighboringTownTrade {
     }
 }
 
-use simulation::{Sleeper, SleeperID};
+use simulation::{Sleeper, SleeperID, WakeUpTag};
 
 impl Sleeper for NeighboringTownTrade {
-    fn wake(&mut self, current_instant: Instant, world: &mut World) {
+    fn wake(&mut self, current_instant: Instant, _: WakeUpTag, world: &mut World) {
+        let _measuring = ::util::profiling::measure("NeighboringTownTrade::wake");
         self.update_core(current_instant, world);
     }
 }
@@ -292,6 +298,7 @@ impl TripListener for NeighboringTownTrade {
 
 impl Simulatable for NeighboringTownTrade {
     fn tick(&mut self, _dt: f32, current_instant: Instant, world: &mut World) {
+        let _measuring = ::util::profiling::measure("NeighboringTownTrade::tick");
         self.on_tick(current_instant, world);
     }
 }
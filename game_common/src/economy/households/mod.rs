@@ -4,6 +4,7 @@ use simulation::{Duration, TimeOfDay, Instant, Ticks, SimulationID, TICKS_PER_SI
 Simulatable};
 use util::async_counter::AsyncCounter;
 use util::random::{seed, Rng};
+use util::numeric_id::numeric_id;
 use ordered_float::OrderedFloat;
 
 pub mod tasks;
@@ -31,6 +32,9 @@ TripCostEstimatorID, EvaluatedSearchResult};
 use super::resources::{Resource, ResourceAmount, ResourceMap, Entry, Inventory};
 use transport::pathfinding::{RoughLocationID, RoughLocation};
 use transport::pathfinding::trip::{TripListener, TripID, TripResult, TripFate};
+use planning::PrototypeID;
+use persistence::{SaveGameWriterID, HouseholdState};
+use super::immigration_and_development::HouseholdTypeToSpawn;
 use self::tasks::{Task, TaskState, TaskEndSchedulerID};
 pub use self::offers::{Offer, OfferIdx, OfferID};
 
@@ -63,6 +67,7 @@ pub trait Household:
 
     fn household_name(&self) -> String;
     fn member_name(&self, member: MemberIdx) -> String;
+    fn household_type(&self) -> HouseholdTypeToSpawn;
 
     fn receive_deal(&mut self, deal: &Deal, member: MemberIdx, _: &mut World) {
         let core = self.core_mut();
@@ -912,6 +917,50 @@ pub trait Household:
     fn get_ui_info(&mut self, requester: ui::HouseholdUIID, world: &mut World) {
         requester.on_household_ui_info(self.id_as(), self.core().clone(), world);
     }
+
+    // Broadcast to all households, only the one with this number answers
+    fn get_ui_info_if_numbered(
+        &mut self,
+        number: u64,
+        requester: ui::HouseholdUIID,
+        world: &mut World,
+    ) {
+        let id: HouseholdID = self.id_as();
+        if numeric_id(id.as_raw()) == number {
+            self.get_ui_info(requester, world);
+        }
+    }
+
+    fn save_to(&mut self, writer: SaveGameWriterID, home: PrototypeID, world: &mut World) {
+        writer.on_household_state(
+            HouseholdState {
+                home,
+                household_type: self.household_type(),
+                resources: self.core().resources.clone(),
+                member_resources: self.core().member_resources.clone(),
+            },
+            world,
+        );
+    }
+
+    // Only resources are restored: tasks, trips and used offers refer to actors
+    // of the previous run, so restored households start out idle at home
+    fn restore_resources(&mut self, state: &HouseholdState, _: &mut World) {
+        let household_type = self.household_type();
+        let core = self.core_mut();
+        core.resources = state.resources.clone();
+
+        if core.member_resources.len() == state.member_resources.len() {
+            core.member_resources = state.member_resources.clone();
+        } else {
+            println!(
+                "Couldn't restore the resources of {} saved members of a {:?} with {} members",
+                state.member_resources.len(),
+                household_type,
+                core.member_resources.len()
+            );
+        }
+    }
 }
 
 #[derive(Compact, Clone)]
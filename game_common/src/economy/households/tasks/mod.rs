@@ -72,6 +72,7 @@ impl TaskEndScheduler {
 
 impl Simulatable for TaskEndScheduler {
     fn tick(&mut self, _dt: f32, current_instant: Instant, world: &mut World) {
+        let _measuring = ::util::profiling::measure("TaskEndScheduler::tick");
         while self
             .task_ends
             .last()
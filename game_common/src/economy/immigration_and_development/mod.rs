@@ -1,10 +1,10 @@
 use kay::{World, ActorSystem, TypedID};
 use compact::COption;
 use land_use::buildings::{UnitType, BuildingID, UnitIdx};
-use simulation::{Sleeper, SleeperID, Instant, SimulationID, Duration};
+use simulation::{Sleeper, SleeperID, WakeUpTag, Instant, SimulationID, Duration};
 use util::random::{seed, Rng};
 
-use economy::households::household_kinds;
+use economy::households::{household_kinds, HouseholdID};
 use self::household_kinds::family::FamilyID;
 use self::household_kinds::grocery_shop::GroceryShopID;
 use self::household_kinds::cow_farm::CowFarmID;
@@ -21,7 +21,7 @@ use planning::{PlanManagerID, Proposal, PrototypeID, Plan, GestureID, Gesture, G
 // TODO: somehow get rid of this horrible duplication by having something like
 // a pointer to an abstract Household trait...
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum HouseholdTypeToSpawn {
     Family,
     GroceryShop,
@@ -59,6 +59,36 @@ pub fn building_style_for(household_type: HouseholdTypeToSpawn) -> BuildingStyle
     }
 }
 
+pub fn move_into(
+    household_type: HouseholdTypeToSpawn,
+    building_id: BuildingID,
+    simulation: SimulationID,
+    world: &mut World,
+) -> HouseholdID {
+    match household_type {
+        HouseholdTypeToSpawn::Family => {
+            FamilyID::move_into(3, building_id, simulation, world).into()
+        }
+        HouseholdTypeToSpawn::GroceryShop => {
+            GroceryShopID::move_into(building_id, simulation, world).into()
+        }
+        HouseholdTypeToSpawn::GrainFarm => {
+            GrainFarmID::move_into(building_id, simulation, world).into()
+        }
+        HouseholdTypeToSpawn::CowFarm => {
+            CowFarmID::move_into(building_id, simulation, world).into()
+        }
+        HouseholdTypeToSpawn::VegetableFarm => {
+            VegetableFarmID::move_into(building_id, simulation, world).into()
+        }
+        HouseholdTypeToSpawn::Mill => MillID::move_into(building_id, simulation, world).into(),
+        HouseholdTypeToSpawn::Bakery => BakeryID::move_into(building_id, simulation, world).into(),
+        HouseholdTypeToSpawn::NeighboringTownTrade => {
+            NeighboringTownTradeID::move_into(building_id, simulation, world).into()
+        }
+    }
+}
+
 #[derive(Compact, Clone)]
 pub struct ImmigrationManager {
     id: ImmigrationManagerID,
@@ -92,7 +122,8 @@ pub enum ImmigrationManagerState {
 }
 
 impl Sleeper for ImmigrationManager {
-    fn wake(&mut self, current_instant: Instant, world: &mut World) {
+    fn wake(&mut self, current_instant: Instant, _: WakeUpTag, world: &mut World) {
+        let _measuring = ::util::profiling::measure("ImmigrationManager::wake");
         self.state = match self.state {
             ImmigrationManagerState::Idle => {
                 let family_share = 1.0;
@@ -175,33 +206,8 @@ impl ImmigrationManager {
             ImmigrationManagerState::FindingBuilding(household_type_to_spawn) => {
                 println!("Moving in");
 
-                let household_id = match household_type_to_spawn {
-                    HouseholdTypeToSpawn::Family => {
-                        FamilyID::move_into(3, building_id, self.simulation, world).into()
-                    }
-                    HouseholdTypeToSpawn::GroceryShop => {
-                        GroceryShopID::move_into(building_id, self.simulation, world).into()
-                    }
-                    HouseholdTypeToSpawn::GrainFarm => {
-                        GrainFarmID::move_into(building_id, self.simulation, world).into()
-                    }
-                    HouseholdTypeToSpawn::CowFarm => {
-                        CowFarmID::move_into(building_id, self.simulation, world).into()
-                    }
-                    HouseholdTypeToSpawn::VegetableFarm => {
-                        VegetableFarmID::move_into(building_id, self.simulation, world).into()
-                    }
-                    HouseholdTypeToSpawn::Mill => {
-                        MillID::move_into(building_id, self.simulation, world).into()
-                    }
-                    HouseholdTypeToSpawn::Bakery => {
-                        BakeryID::move_into(building_id, self.simulation, world).into()
-                    }
-                    HouseholdTypeToSpawn::NeighboringTownTrade => {
-                        NeighboringTownTradeID::move_into(building_id, self.simulation, world)
-                            .into()
-                    }
-                };
+                let household_id =
+                    move_into(household_type_to_spawn, building_id, self.simulation, world);
 
                 building_id.add_household(household_id, unit_idx, world);
 
@@ -254,14 +260,15 @@ impl DevelopmentManager {
         if let Some(building_to_develop) = *self.building_to_develop {
             if building_to_develop == building_intent.building_style {
                 println!("Adding to plan {:?}", building_intent.building_style);
+                let plan = Plan::from_gestures(Some((
+                    GestureID::new(),
+                    Gesture::new(
+                        vec![building_intent.lot.center_point()].into(),
+                        GestureIntent::Building(building_intent.clone()),
+                    ),
+                )));
                 self.plan_manager.implement_artificial_proposal(
-                    Proposal::from_plan(Plan::from_gestures(Some((
-                        GestureID::new(),
-                        Gesture::new(
-                            vec![building_intent.lot.center_point()].into(),
-                            GestureIntent::Building(building_intent.clone()),
-                        ),
-                    )))),
+                    Proposal::from_plan(plan, self.id.as_raw().machine),
                     vec![based_on].into(),
                     world,
                 );
@@ -272,7 +279,8 @@ impl DevelopmentManager {
 }
 
 impl Sleeper for DevelopmentManager {
-    fn wake(&mut self, _: Instant, _world: &mut World) {
+    fn wake(&mut self, _: Instant, _: WakeUpTag, _world: &mut World) {
+        let _measuring = ::util::profiling::measure("DevelopmentManager::wake");
         self.building_to_develop = COption(None);
     }
 }
@@ -0,0 +1,16 @@
+use kay::{ActorSystem, World};
+use economy::resources::ResourceAmount;
+
+pub mod treasury;
+
+pub fn setup(system: &mut ActorSystem) {
+    treasury::setup(system);
+}
+
+pub fn spawn(
+    world: &mut World,
+    initial_funds: ResourceAmount,
+    income_per_hour: ResourceAmount,
+) -> treasury::TreasuryID {
+    treasury::spawn(world, initial_funds, income_per_hour)
+}
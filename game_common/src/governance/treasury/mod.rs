@@ -0,0 +1,218 @@
+use kay::{ActorSystem, World, TypedID};
+use compact::CVec;
+use simulation::{Instant, Simulatable, SimulatableID, TICKS_PER_SIM_MINUTE};
+use economy::resources::{Inventory, Resource, ResourceAmount};
+use planning::ui::PlanningUIID;
+use persistence::{SaveGameWriterID, TreasuryState};
+
+pub const DEFAULT_INITIAL_FUNDS: ResourceAmount = 10_000_000.0;
+// Stands in for taxes, until the city collects any
+pub const DEFAULT_INCOME_PER_HOUR: ResourceAmount = 100_000.0;
+
+const TICKS_PER_SIM_HOUR: usize = 60 * TICKS_PER_SIM_MINUTE as usize;
+
+pub trait FundingRecipient {
+    fn on_funds_granted(&mut self, request_id: u32, amount: ResourceAmount, world: &mut World);
+}
+
+#[derive(Compact, Clone, Serialize, Deserialize)]
+pub struct FundingRequest {
+    request_id: u32,
+    amount: ResourceAmount,
+    recipient: FundingRecipientID,
+}
+
+// The city's money, which public works like construction are paid from
+#[derive(Compact, Clone)]
+pub struct Treasury {
+    id: TreasuryID,
+    inventory: Inventory,
+    income_per_hour: ResourceAmount,
+    // granted first come, first served, as soon as there is enough money
+    pending_requests: CVec<FundingRequest>,
+}
+
+impl Treasury {
+    pub fn spawn(
+        id: TreasuryID,
+        initial_funds: ResourceAmount,
+        income_per_hour: ResourceAmount,
+        _: &mut World,
+    ) -> Treasury {
+        let mut inventory = Inventory::new();
+        inventory.insert(Resource::Money, initial_funds);
+
+        Treasury {
+            id,
+            inventory,
+            income_per_hour,
+            pending_requests: CVec::new(),
+        }
+    }
+
+    fn balance(&self) -> ResourceAmount {
+        *self.inventory.get(Resource::Money).unwrap_or(&0.0)
+    }
+
+    fn requested(&self) -> ResourceAmount {
+        self.pending_requests
+            .iter()
+            .map(|request| request.amount)
+            .sum()
+    }
+
+    fn report_balance(&self, world: &mut World) {
+        PlanningUIID::global_broadcast(world).on_treasury_update(
+            self.balance(),
+            self.requested(),
+            world,
+        );
+    }
+
+    fn grant_pending_requests(&mut self, world: &mut World) {
+        let n_granted = n_grantable(
+            self.pending_requests.iter().map(|request| request.amount),
+            self.balance(),
+        );
+
+        for _ in 0..n_granted {
+            let request = self.pending_requests.remove(0);
+            *self.inventory.mut_entry_or(Resource::Money, 0.0) -= request.amount;
+            request
+                .recipient
+                .on_funds_granted(request.request_id, request.amount, world);
+        }
+    }
+
+    pub fn set_income(&mut self, income_per_hour: ResourceAmount, _: &mut World) {
+        self.income_per_hour = income_per_hour;
+    }
+
+    pub fn deposit(&mut self, amount: ResourceAmount, world: &mut World) {
+        *self.inventory.mut_entry_or(Resource::Money, 0.0) += amount;
+        self.grant_pending_requests(world);
+        self.report_balance(world);
+    }
+
+    pub fn request_funds(
+        &mut self,
+        request_id: u32,
+        amount: ResourceAmount,
+        recipient: FundingRecipientID,
+        world: &mut World,
+    ) {
+        self.pending_requests.push(FundingRequest {
+            request_id,
+            amount,
+            recipient,
+        });
+        self.grant_pending_requests(world);
+        self.report_balance(world);
+    }
+
+    // Only works while the request is still waiting for money
+    pub fn withdraw_request(
+        &mut self,
+        request_id: u32,
+        recipient: FundingRecipientID,
+        world: &mut World,
+    ) {
+        self.pending_requests.retain(|request| {
+            request.request_id != request_id || request.recipient != recipient
+        });
+        // a big request might have blocked smaller ones behind it
+        self.grant_pending_requests(world);
+        self.report_balance(world);
+    }
+
+    pub fn get_balance(&mut self, ui: PlanningUIID, world: &mut World) {
+        ui.on_treasury_update(self.balance(), self.requested(), world);
+    }
+
+    pub fn save_to(&mut self, writer: SaveGameWriterID, world: &mut World) {
+        writer.on_treasury_state(
+            TreasuryState {
+                inventory: self.inventory.clone(),
+                pending_requests: self.pending_requests.clone(),
+            },
+            world,
+        );
+    }
+
+    // Pending requests of this run are kept, since their recipients are still waiting,
+    // but come after the saved ones, which were made earlier
+    pub fn restore(&mut self, state: &TreasuryState, world: &mut World) {
+        self.inventory = state.inventory.clone();
+        let requests_of_this_run =
+            ::std::mem::replace(&mut self.pending_requests, state.pending_requests.clone());
+        self.pending_requests.extend(requests_of_this_run);
+        self.grant_pending_requests(world);
+        self.report_balance(world);
+    }
+}
+
+// How many requests from the front of the queue the balance covers.
+// Later requests never skip ahead, even if they would fit
+fn n_grantable<I: IntoIterator<Item = ResourceAmount>>(
+    amounts: I,
+    balance: ResourceAmount,
+) -> usize {
+    let mut remaining = balance;
+    amounts
+        .into_iter()
+        .take_while(|amount| {
+            if *amount <= remaining {
+                remaining -= *amount;
+                true
+            } else {
+                false
+            }
+        }).count()
+}
+
+impl Simulatable for Treasury {
+    fn tick(&mut self, _dt: f32, current_instant: Instant, world: &mut World) {
+        let _measuring = ::util::profiling::measure("Treasury::tick");
+        if current_instant.ticks() % TICKS_PER_SIM_HOUR == 0 && self.income_per_hour != 0.0 {
+            let income = self.income_per_hour;
+            self.deposit(income, world);
+        }
+    }
+}
+
+pub fn setup(system: &mut ActorSystem) {
+    system.register::<Treasury>();
+    auto_setup(system);
+}
+
+pub fn spawn(
+    world: &mut World,
+    initial_funds: ResourceAmount,
+    income_per_hour: ResourceAmount,
+) -> TreasuryID {
+    TreasuryID::spawn(initial_funds, income_per_hour, world)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_are_granted_in_order_while_the_balance_lasts() {
+        assert_eq!(n_grantable(vec![30.0, 50.0, 40.0], 100.0), 2);
+        assert_eq!(n_grantable(vec![30.0, 70.0], 100.0), 2);
+        assert_eq!(n_grantable(vec![], 100.0), 0);
+        assert_eq!(n_grantable(vec![10.0], 0.0), 0);
+    }
+
+    #[test]
+    fn big_requests_block_smaller_later_ones() {
+        assert_eq!(n_grantable(vec![30.0, 80.0, 10.0], 100.0), 1);
+        assert_eq!(n_grantable(vec![500.0, 10.0], 100.0), 0);
+        // until they are withdrawn
+        assert_eq!(n_grantable(vec![10.0], 100.0), 1);
+    }
+}
+
+pub mod kay_auto;
+pub use self::kay_auto::*;
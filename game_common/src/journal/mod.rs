@@ -0,0 +1,363 @@
+use kay::{ActorSystem, World, TypedID};
+use compact::{CVec, COption, CString};
+use descartes::P2;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+
+use simulation::{Instant, Simulatable, SimulatableID, SimulationID};
+use planning::{PlanManagerID, ProposalID, GestureID, GestureIntent, StepID};
+use planning::exchange::ProposalFile;
+use planning::generation::GeneratorSettings;
+use planning::ui::PlanningUIID;
+use construction::ConstructionID;
+use construction::queue::ConstructionGroupID;
+
+pub const JOURNAL_VERSION: u32 = 10;
+
+// Every message that changes the world on behalf of a player,
+// with everything needed to send it again. Permissions are checked against the
+// machine of the recorded planning UI, so replays are decided the same way
+#[derive(Compact, Clone, Debug, Serialize, Deserialize)]
+pub enum JournaledCommand {
+    StartNewGesture(ProposalID, PlanningUIID, GestureID, GestureIntent, P2),
+    FinishGesture(PlanningUIID),
+    AddControlPoint(ProposalID, PlanningUIID, GestureID, P2, bool, bool),
+    MoveControlPoint(ProposalID, PlanningUIID, GestureID, u32, P2, bool),
+    SetIntent(ProposalID, PlanningUIID, GestureID, GestureIntent, bool),
+    Undo(ProposalID, PlanningUIID),
+    Redo(ProposalID, PlanningUIID),
+    Implement(ProposalID, PlanningUIID),
+    ImportProposal(ProposalID, PlanningUIID, ProposalFile),
+    RebaseProposal(ProposalID, PlanningUIID),
+    MergeProposals(ProposalID, ProposalID, PlanningUIID),
+    AddGeneratedPlan(ProposalID, PlanningUIID, GeneratorSettings, StepID),
+    CreateProposal(ProposalID, PlanningUIID),
+    ShareProposal(ProposalID, PlanningUIID, bool),
+    SubmitProposal(ProposalID, PlanningUIID),
+    WithdrawProposal(ProposalID, PlanningUIID),
+    // with the quorum at the time of the vote
+    CastVote(ProposalID, PlanningUIID, bool, u32),
+    CreateCheckpoint(CString, PlanningUIID),
+    RollbackToCheckpoint(CString, PlanningUIID),
+    CancelConstructionGroup(ConstructionGroupID, PlanningUIID),
+    PrioritizeConstructionGroup(ConstructionGroupID, PlanningUIID),
+    SetSpeed(u16),
+    Pause,
+    Resume,
+    RunUntil(Instant),
+}
+
+impl JournaledCommand {
+    pub fn apply(&self, world: &mut World) {
+        let plan_manager = PlanManagerID::global_first(world);
+
+        match *self {
+            JournaledCommand::StartNewGesture(
+                proposal_id,
+                ui,
+                gesture_id,
+                ref intent,
+                start,
+            ) => plan_manager.start_new_gesture(
+                proposal_id,
+                ui,
+                gesture_id,
+                intent.clone(),
+                start,
+                world,
+            ),
+            JournaledCommand::FinishGesture(ui) => plan_manager.finish_gesture(ui, world),
+            JournaledCommand::AddControlPoint(
+                proposal_id,
+                ui,
+                gesture_id,
+                new_point,
+                add_to_end,
+                commit,
+            ) => plan_manager.add_control_point(
+                proposal_id,
+                ui,
+                gesture_id,
+                new_point,
+                add_to_end,
+                commit,
+                world,
+            ),
+            JournaledCommand::MoveControlPoint(
+                proposal_id,
+                ui,
+                gesture_id,
+                point_index,
+                new_position,
+                is_move_finished,
+            ) => plan_manager.move_control_point(
+                proposal_id,
+                ui,
+                gesture_id,
+                point_index,
+                new_position,
+                is_move_finished,
+                world,
+            ),
+            JournaledCommand::SetIntent(
+                proposal_id,
+                ui,
+                gesture_id,
+                ref new_intent,
+                is_move_finished,
+            ) => plan_manager.set_intent(
+                proposal_id,
+                ui,
+                gesture_id,
+                new_intent.clone(),
+                is_move_finished,
+                world,
+            ),
+            JournaledCommand::Undo(proposal_id, ui) => {
+                plan_manager.undo(proposal_id, ui, world)
+            }
+            JournaledCommand::Redo(proposal_id, ui) => {
+                plan_manager.redo(proposal_id, ui, world)
+            }
+            JournaledCommand::Implement(proposal_id, ui) => {
+                plan_manager.implement(proposal_id, ui, world)
+            }
+            JournaledCommand::ImportProposal(proposal_id, ui, ref file) => {
+                plan_manager.import_proposal(proposal_id, ui, file.clone(), world)
+            }
+            JournaledCommand::RebaseProposal(proposal_id, ui) => {
+                plan_manager.rebase_proposal(proposal_id, ui, world)
+            }
+            JournaledCommand::MergeProposals(proposal_id, other_id, ui) => {
+                plan_manager.merge_proposals(proposal_id, other_id, ui, world)
+            }
+            JournaledCommand::AddGeneratedPlan(proposal_id, ui, ref settings, step_id) => {
+                plan_manager.add_generated_plan(proposal_id, ui, settings.clone(), step_id, world)
+            }
+            JournaledCommand::CreateProposal(proposal_id, ui) => {
+                plan_manager.create_proposal(proposal_id, ui, world)
+            }
+            JournaledCommand::ShareProposal(proposal_id, ui, shared) => {
+                plan_manager.share_proposal(proposal_id, ui, shared, world)
+            }
+            JournaledCommand::SubmitProposal(proposal_id, ui) => {
+                plan_manager.submit_proposal(proposal_id, ui, world)
+            }
+            JournaledCommand::WithdrawProposal(proposal_id, ui) => {
+                plan_manager.withdraw_proposal(proposal_id, ui, world)
+            }
+            JournaledCommand::CastVote(proposal_id, ui, approve, quorum) => {
+                plan_manager.cast_replayed_vote(proposal_id, ui, approve, quorum, world)
+            }
+            JournaledCommand::CreateCheckpoint(ref name, ui) => {
+                plan_manager.create_checkpoint(name.clone(), ui, world)
+            }
+            JournaledCommand::RollbackToCheckpoint(ref name, ui) => {
+                plan_manager.rollback_to_checkpoint(name.clone(), ui, world)
+            }
+            JournaledCommand::CancelConstructionGroup(group_id, ui) => {
+                ConstructionID::global_first(world).cancel_group(group_id, ui, world)
+            }
+            JournaledCommand::PrioritizeConstructionGroup(group_id, ui) => {
+                ConstructionID::global_first(world).prioritize_group(group_id, ui, world)
+            }
+            JournaledCommand::SetSpeed(speed) => {
+                SimulationID::global_first(world).set_speed(speed, world)
+            }
+            JournaledCommand::Pause => SimulationID::global_first(world).pause(world),
+            JournaledCommand::Resume => SimulationID::global_first(world).resume(world),
+            JournaledCommand::RunUntil(until) => {
+                SimulationID::global_first(world).run_until(until, world)
+            }
+        }
+    }
+}
+
+#[derive(Compact, Clone, Debug, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub instant: Instant,
+    pub command: JournaledCommand,
+}
+
+// The first line of a journal file, followed by one JournalEntry per line
+#[derive(Serialize, Deserialize)]
+pub struct JournalHeader {
+    pub version: u32,
+    pub initial_proposal: ProposalID,
+}
+
+pub struct JournalFile {
+    pub header: JournalHeader,
+    pub entries: Vec<JournalEntry>,
+}
+
+impl JournalFile {
+    pub fn read_from(path: &str) -> Result<JournalFile, String> {
+        let file = File::open(path).map_err(|err| err.to_string())?;
+        let mut lines = BufReader::new(file).lines();
+
+        let header_line = lines
+            .next()
+            .ok_or_else(|| "Journal is empty".to_owned())?
+            .map_err(|err| err.to_string())?;
+        let header: JournalHeader =
+            ::serde_json::from_str(&header_line).map_err(|err| err.to_string())?;
+
+        if header.version != JOURNAL_VERSION {
+            return Err(format!(
+                "Journal has version {}, but this version of Citybound reads version {}",
+                header.version, JOURNAL_VERSION
+            ));
+        }
+
+        let mut entries = Vec::new();
+        for line in lines {
+            let line = line.map_err(|err| err.to_string())?;
+            if !line.is_empty() {
+                entries.push(::serde_json::from_str(&line).map_err(|err| err.to_string())?);
+            }
+        }
+
+        Ok(JournalFile { header, entries })
+    }
+}
+
+#[derive(Compact, Clone)]
+pub struct Journal {
+    id: JournalID,
+    current_instant: Instant,
+    path: COption<CString>,
+    // sorted latest-first, so the next entry to replay is at the end
+    to_replay: CVec<JournalEntry>,
+    // The actors handling replayed commands would record them again,
+    // so while replaying, replayed entries are copied over directly and nothing else is
+    // recorded. Commands of players would make the replay diverge anyway
+    replaying: bool,
+}
+
+impl Journal {
+    pub fn spawn(
+        id: JournalID,
+        path: &COption<CString>,
+        initial_proposal: ProposalID,
+        to_replay: &CVec<JournalEntry>,
+        _: &mut World,
+    ) -> Journal {
+        if let Some(ref path) = **path {
+            let header = JournalHeader {
+                version: JOURNAL_VERSION,
+                initial_proposal,
+            };
+
+            let written = File::create(&**path).and_then(|mut file| {
+                writeln!(
+                    file,
+                    "{}",
+                    ::serde_json::to_string(&header).expect("Should be able to serialize header")
+                )
+            });
+
+            if let Err(err) = written {
+                println!("Couldn't start journal {}: {}", &**path, err);
+            }
+        }
+
+        let mut to_replay = to_replay.clone();
+        to_replay.reverse();
+        let replaying = !to_replay.is_empty();
+
+        Journal {
+            id,
+            current_instant: Instant::new(0),
+            path: path.clone(),
+            to_replay,
+            replaying,
+        }
+    }
+
+    // Entries are written right away, so that nothing is lost
+    // while paused, when shutting down or when the simulation crashes
+    pub fn record(&mut self, command: &JournaledCommand, _: &mut World) {
+        if self.replaying {
+            return;
+        }
+
+        let entry = JournalEntry {
+            instant: self.current_instant,
+            command: command.clone(),
+        };
+        ::util::crash_report::note_command(&entry);
+        self.write(&entry);
+    }
+
+    // Sent by the main loop after all messages of a frame were handled,
+    // including the handling of commands replayed during that frame
+    pub fn end_frame(&mut self, _: &mut World) {
+        self.replaying = !self.to_replay.is_empty();
+    }
+
+    fn write(&self, entry: &JournalEntry) {
+        if let Some(ref path) = *self.path {
+            let written = OpenOptions::new()
+                .append(true)
+                .open(&**path)
+                .and_then(|mut file| {
+                    writeln!(
+                        file,
+                        "{}",
+                        ::serde_json::to_string(entry)
+                            .expect("Should be able to serialize journal entry")
+                    )
+                });
+
+            if let Err(err) = written {
+                println!("Couldn't write to journal {}: {}", &**path, err);
+            }
+        }
+    }
+}
+
+impl Simulatable for Journal {
+    fn tick(&mut self, _dt: f32, current_instant: Instant, world: &mut World) {
+        let _measuring = ::util::profiling::measure("Journal::tick");
+        self.current_instant = current_instant;
+
+        while self
+            .to_replay
+            .last()
+            .map(|entry| entry.instant <= current_instant)
+            .unwrap_or(false)
+        {
+            let entry = self
+                .to_replay
+                .pop()
+                .expect("just checked that there are entries to replay");
+            ::util::crash_report::note_command(&entry);
+            self.write(&entry);
+            entry.command.apply(world);
+        }
+    }
+}
+
+pub fn setup(system: &mut ActorSystem) {
+    system.register::<Journal>();
+    auto_setup(system);
+}
+
+pub fn spawn(
+    world: &mut World,
+    path: Option<&str>,
+    initial_proposal: ProposalID,
+    to_replay: Vec<JournalEntry>,
+) -> JournalID {
+    let path = path.map(|path| {
+        let mut c_path = CString::new();
+        c_path.push_str(path);
+        c_path
+    });
+
+    JournalID::spawn(COption(path), initial_proposal, to_replay.into(), world)
+}
+
+mod kay_auto;
+pub use self::kay_auto::*;
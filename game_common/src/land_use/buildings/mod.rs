@@ -1,20 +1,23 @@
-use kay::{ActorSystem, World, Actor, Fate, TypedID};
+use kay::{ActorSystem, World, Actor, Fate, TypedID, MachineID};
 use compact::{CVec, COption};
 use descartes::P2;
 
 use transport::lane::LaneID;
 use simulation::{Ticks, SimulationID};
 use construction::{ConstructionID, Constructable, ConstructableID};
-use planning::{Prototype, PrototypeKind};
+use planning::{Prototype, PrototypeKind, PrototypeID, PlanManagerID};
+use planning::exchange::geojson::{GeoJSONExporterID, ExportedBuilding};
 
 pub mod rendering;
 pub mod architecture;
 
 use economy::households::HouseholdID;
 use transport::pathfinding::PreciseLocation;
-use economy::immigration_and_development::ImmigrationManagerID;
+use economy::immigration_and_development::{ImmigrationManagerID, move_into, unit_type_for};
+use persistence::{SaveGameWriterID, HouseholdState};
 use land_use::zone_planning::{Lot, LandUse};
 use super::ui::{LandUseUIID};
+use util::numeric_id::numeric_id;
 
 #[derive(Copy, Clone)]
 pub struct Unit(Option<HouseholdID>, UnitType);
@@ -63,7 +66,8 @@ pub struct Building {
     pub location: Option<PreciseLocation>,
     style: BuildingStyle,
     being_destroyed_for: COption<ConstructionID>,
-    started_reconnect: bool,
+    // households can only move in once construction is finished
+    finished: bool,
 }
 
 //use stagemaster::geometry::add_debug_line;
@@ -74,9 +78,10 @@ impl Building {
 
         rendering::on_add(id, lot, style, world);
 
-        SimulationID::local_first(world).wake_up_in(
+        SimulationID::local_first(world).wake_up_tagged_in(
             Ticks::from(Duration::from_minutes(10)),
             id.into(),
+            TRY_RECONNECT,
             world,
         );
 
@@ -87,7 +92,7 @@ impl Building {
             location: None,
             style,
             being_destroyed_for: COption(None),
-            started_reconnect: false,
+            finished: false,
         }
     }
 
@@ -97,6 +102,10 @@ impl Building {
         requester: ImmigrationManagerID,
         world: &mut World,
     ) {
+        if !self.finished {
+            return;
+        }
+
         println!(
             "{:?} got offer request for {:?}",
             self.style, required_unit_type
@@ -153,6 +162,93 @@ impl Building {
     pub fn get_ui_info(&mut self, requester: LandUseUIID, world: &mut World) {
         requester.on_building_ui_info(self.id, self.style, self.all_households().into(), world);
     }
+
+    // Broadcast to all buildings, only the one with this number answers
+    pub fn get_ui_info_if_numbered(
+        &mut self,
+        number: u64,
+        requester: LandUseUIID,
+        world: &mut World,
+    ) {
+        if numeric_id(self.id.as_raw()) == number {
+            self.get_ui_info(requester, world);
+        }
+    }
+
+    pub fn report_households(
+        &mut self,
+        requester: PlanManagerID,
+        machine: MachineID,
+        as_prototype: PrototypeID,
+        world: &mut World,
+    ) {
+        let n_households = self.all_households().len() as u32;
+        requester.on_displaced_households(machine, as_prototype, n_households, world);
+    }
+
+    pub fn export_to(
+        &mut self,
+        exporter: GeoJSONExporterID,
+        as_prototype: PrototypeID,
+        world: &mut World,
+    ) {
+        exporter.on_building_exported(
+            as_prototype,
+            COption(Some(ExportedBuilding {
+                id: self.id,
+                style: self.style,
+                lot: self.lot.clone(),
+                n_households: self.all_households().len() as u32,
+                n_units: self.units.len() as u32,
+            })),
+            world,
+        );
+    }
+
+    // Households that are already moving out are not saved
+    pub fn save_households_to(
+        &mut self,
+        writer: SaveGameWriterID,
+        as_prototype: PrototypeID,
+        world: &mut World,
+    ) {
+        let households = if self.being_destroyed_for.is_some() {
+            Vec::new()
+        } else {
+            self.all_households()
+        };
+
+        writer.on_building_saved(as_prototype, households.len() as u32, world);
+
+        for household in households {
+            household.save_to(writer, as_prototype, world);
+        }
+    }
+
+    pub fn restore_households(&mut self, states: &CVec<HouseholdState>, world: &mut World) {
+        for state in states {
+            let required_unit_type = unit_type_for(state.household_type);
+            let maybe_idx = self.units.iter().position(|&Unit(household, unit_type)| {
+                household.is_none() && unit_type == required_unit_type
+            });
+
+            if let Some(idx) = maybe_idx {
+                let household = move_into(
+                    state.household_type,
+                    self.id,
+                    SimulationID::local_first(world),
+                    world,
+                );
+                household.restore_resources(state.clone(), world);
+                self.units[idx].0 = Some(household);
+            } else {
+                println!(
+                    "{:?} has no free unit for restored {:?}",
+                    self.style, state.household_type
+                );
+            }
+        }
+    }
 }
 
 impl Constructable for Building {
@@ -180,10 +276,18 @@ impl Constructable for Building {
             Fate::Live
         }
     }
+
+    fn finish_construction(&mut self, _: &mut World) {
+        self.finished = true;
+    }
 }
 
 use transport::pathfinding::{Location, Attachee, AttacheeID};
-use simulation::{Sleeper, SleeperID, Duration};
+use simulation::{Sleeper, SleeperID, Duration, WakeUpTag};
+
+// The two things a building wakes up for
+const TRY_RECONNECT: WakeUpTag = 1;
+const CHECK_CONNECTED: WakeUpTag = 2;
 
 impl Attachee for Building {
     fn location_changed(
@@ -199,9 +303,10 @@ impl Attachee for Building {
                 .location = new;
         } else {
             self.location = None;
-            SimulationID::local_first(world).wake_up_in(
+            SimulationID::local_first(world).wake_up_tagged_in(
                 Ticks::from(Duration::from_minutes(10)),
                 self.id_as(),
+                TRY_RECONNECT,
                 world,
             );
         }
@@ -209,26 +314,23 @@ impl Attachee for Building {
 }
 
 impl Sleeper for Building {
-    fn wake(&mut self, _instant: Instant, world: &mut World) {
-        if self.started_reconnect {
-            if self.location.is_none() {
-                // TODO: do we still need to destroy here?
-            } else {
-                self.started_reconnect = false;
-            }
-        } else {
+    fn wake(&mut self, _instant: Instant, tag: WakeUpTag, world: &mut World) {
+        let _measuring = ::util::profiling::measure("Building::wake");
+        // lanes only connect buildings once they are built and part of routing,
+        // so keep trying until one of them does
+        if tag == TRY_RECONNECT || (tag == CHECK_CONNECTED && self.location.is_none()) {
             println!("Trying to connect building {:?}", self.id);
             LaneID::global_broadcast(world).try_reconnect_building(
                 self.id,
                 self.lot.best_road_connection().0,
                 world,
             );
-            SimulationID::local_first(world).wake_up_in(
+            SimulationID::local_first(world).wake_up_tagged_in(
                 Ticks::from(Duration::from_minutes(10)),
                 self.id_as(),
+                CHECK_CONNECTED,
                 world,
             );
-            self.started_reconnect = true;
         }
     }
 }
@@ -6,6 +6,28 @@ use land_use::vacant_lots::VacantLotID;
 use land_use::buildings::BuildingID;
 use construction::{ConstructionID, ConstructableID};
 use planning::PrototypeID;
+use land_use::buildings::BuildingStyle;
+use simulation::Duration;
+use economy::resources::ResourceAmount;
+
+pub const MINUTES_PER_BUILDING_LOT_SQUARE_METER: usize = 1;
+pub const HOURS_PER_DESTROYED_BUILDING: usize = 12;
+
+// Buildings are paid for by their owners, the city only prepares vacant lots
+// and compensates owners of buildings it tears down
+pub const COST_PER_VACANT_LOT_SQUARE_METER: ResourceAmount = 2.0;
+pub const COST_PER_DESTROYED_BUILDING: ResourceAmount = 10_000.0;
+
+// Base time, lot size adds to it
+fn building_hours(style: BuildingStyle) -> usize {
+    match style {
+        BuildingStyle::FamilyHouse => 48,
+        BuildingStyle::GroceryShop | BuildingStyle::Bakery => 72,
+        BuildingStyle::Mill => 120,
+        BuildingStyle::Field => 24,
+        BuildingStyle::NeighboringTownConnection => 0,
+    }
+}
 
 impl LotPrototype {
     pub fn construct(
@@ -24,6 +46,44 @@ impl LotPrototype {
         vec![id].into()
     }
 
+    // Zoning a vacant lot is just paperwork
+    pub fn construction_time(&self) -> Duration {
+        match self.occupancy {
+            LotOccupancy::Vacant => Duration(0),
+            // already exists outside of the city
+            LotOccupancy::Occupied(BuildingStyle::NeighboringTownConnection) => Duration(0),
+            LotOccupancy::Occupied(building_style) => {
+                Duration::from_hours(building_hours(building_style))
+                    + Duration::from_minutes(
+                        self.lot.size() as usize * MINUTES_PER_BUILDING_LOT_SQUARE_METER,
+                    )
+            }
+        }
+    }
+
+    pub fn destruction_time(&self) -> Duration {
+        match self.occupancy {
+            LotOccupancy::Vacant => Duration(0),
+            LotOccupancy::Occupied(_) => Duration::from_hours(HOURS_PER_DESTROYED_BUILDING),
+        }
+    }
+
+    pub fn construction_cost(&self) -> ResourceAmount {
+        match self.occupancy {
+            LotOccupancy::Vacant => self.lot.size() * COST_PER_VACANT_LOT_SQUARE_METER,
+            LotOccupancy::Occupied(_) => 0.0,
+        }
+    }
+
+    pub fn destruction_cost(&self) -> ResourceAmount {
+        match self.occupancy {
+            LotOccupancy::Vacant => 0.0,
+            // already exists outside of the city
+            LotOccupancy::Occupied(BuildingStyle::NeighboringTownConnection) => 0.0,
+            LotOccupancy::Occupied(_) => COST_PER_DESTROYED_BUILDING,
+        }
+    }
+
     pub fn morphable_from(&self, other: &LotPrototype) -> bool {
         // TODO: improve this
         (self.occupancy != LotOccupancy::Vacant)
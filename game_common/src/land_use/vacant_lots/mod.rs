@@ -271,6 +271,8 @@ impl Constructable for VacantLot {
         report_to.action_done(self.id.into(), world);
         Fate::Die
     }
+
+    fn finish_construction(&mut self, _: &mut World) {}
 }
 
 pub fn setup(system: &mut ActorSystem) {
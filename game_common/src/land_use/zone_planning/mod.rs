@@ -1,13 +1,13 @@
 use compact::CVec;
 use descartes::{P2, V2, Area, ClosedLinePath, LinePath, PointContainer,
-AreaError, WithUniqueOrthogonal, Segment};
+WithUniqueOrthogonal, Segment};
 use land_use::buildings::BuildingStyle;
 use ordered_float::OrderedFloat;
 
 use transport::transport_planning::{RoadPrototype, LanePrototype};
 
 use planning::{PlanHistory, VersionedGesture, PlanResult, Prototype, PrototypeID,
-PrototypeKind, GestureIntent};
+PrototypeKind, GestureIntent, PlanError};
 
 #[derive(Compact, Clone, Debug, Serialize, Deserialize)]
 pub enum ZoneIntent {
@@ -60,6 +60,23 @@ impl Lot {
         )
     }
 
+    // Ground area in square meters, using the shoelace formula on the outlines
+    pub fn size(&self) -> f32 {
+        self.area
+            .primitives
+            .iter()
+            .map(|primitive| {
+                let points = &primitive.boundary.path().points;
+                points
+                    .iter()
+                    .zip(points.iter().cycle().skip(1))
+                    .map(|(a, b)| a.x * b.y - b.x * a.y)
+                    .sum::<f32>()
+                    .abs()
+                    / 2.0
+            }).sum()
+    }
+
     pub fn best_road_connection(&self) -> (P2, V2) {
         let longest_boundary = self
             .road_boundaries
@@ -109,7 +126,7 @@ pub enum LotOccupancy {
 pub fn calculate_prototypes(
     history: &PlanHistory,
     current_result: &PlanResult,
-) -> Result<Vec<Prototype>, AreaError> {
+) -> Result<Vec<Prototype>, PlanError> {
     let paved_area_areas = current_result
         .prototypes
         .values()
@@ -127,8 +144,8 @@ pub fn calculate_prototypes(
 
     let building_prototypes = history
         .gestures
-        .values()
-        .map(|VersionedGesture(gesture, gesture_step_id)| {
+        .pairs()
+        .map(|(gesture_id, VersionedGesture(gesture, gesture_step_id))| {
             if let GestureIntent::Building(BuildingIntent {
                 ref lot,
                 building_style,
@@ -143,7 +160,12 @@ pub fn calculate_prototypes(
                             (
                                 true,
                                 split
-                                    .a_minus_b()?
+                                    .a_minus_b()
+                                    .map_err(|err| {
+                                        PlanError::from(err)
+                                            .caused_by_gesture(*gesture_id)
+                                            .caused_by_prototype(*paved_id)
+                                    })?
                                     .disjoint()
                                     .into_iter()
                                     .find(|piece| piece.contains(lot.center_point())),
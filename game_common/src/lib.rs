@@ -13,6 +13,8 @@ extern crate fnv;
 extern crate roaring;
 extern crate backtrace;
 extern crate uuid;
+#[macro_use]
+extern crate serde_json;
 
 pub extern crate compact;
 #[macro_use]
@@ -36,6 +38,9 @@ pub mod construction;
 pub mod economy;
 pub mod land_use;
 pub mod dimensions;
+pub mod persistence;
+pub mod journal;
+pub mod governance;
 
 pub fn setup_common(system: &mut kay::ActorSystem) {
     for setup_fn in &[
@@ -45,6 +50,9 @@ pub fn setup_common(system: &mut kay::ActorSystem) {
         transport::setup,
         economy::setup,
         land_use::setup,
+        persistence::setup,
+        journal::setup,
+        governance::setup,
     ] {
         setup_fn(system)
     }
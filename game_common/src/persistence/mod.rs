@@ -0,0 +1,347 @@
+use kay::{ActorSystem, World, Fate, External};
+use compact::{CVec, CHashMap, COption, CString};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+
+use simulation::{SimulationID, Instant};
+use planning::{PlanManagerID, PlanHistory, PlanResult, Proposal, ProposalID, PrototypeID,
+PrototypeKind};
+use planning::checkpoints::Checkpoint;
+use planning::voting::Ballot;
+use planning::permissions::PlanningPolicy;
+use land_use::zone_planning::{LotPrototype, LotOccupancy};
+use economy::resources::Inventory;
+use economy::immigration_and_development::HouseholdTypeToSpawn;
+use governance::treasury::{TreasuryID, FundingRequest};
+use construction::ConstructionID;
+
+// Bump this whenever the layout of SaveGame or anything it contains changes
+pub const SAVEGAME_VERSION: u32 = 10;
+
+#[derive(Compact, Clone, Serialize, Deserialize)]
+pub struct SimulationState {
+    pub current_instant: Instant,
+    pub speed: u16,
+    pub paused: bool,
+    pub run_until: COption<Instant>,
+    pub fast_forward_until: COption<Instant>,
+}
+
+#[derive(Compact, Clone, Serialize, Deserialize)]
+pub struct PlanningState {
+    pub master_plan: PlanHistory,
+    pub master_result: PlanResult,
+    pub proposals: CHashMap<ProposalID, Proposal>,
+    pub implemented_proposals: CHashMap<ProposalID, Proposal>,
+    pub checkpoints: CVec<Checkpoint>,
+    // votes on proposals that are still submitted
+    pub ballots: CHashMap<ProposalID, Ballot>,
+    pub policy: PlanningPolicy,
+}
+
+#[derive(Compact, Clone, Serialize, Deserialize)]
+pub struct TreasuryState {
+    pub inventory: Inventory,
+    pub pending_requests: CVec<FundingRequest>,
+}
+
+// Job ids double as treasury request ids, so they continue where they left off.
+// Otherwise a saved request could be granted to a new job with the same id
+#[derive(Compact, Clone, Serialize, Deserialize)]
+pub struct ConstructionState {
+    pub next_job_id: u32,
+}
+
+// A household and the building it lives in, identified by the prototype of its lot
+#[derive(Compact, Clone, Serialize, Deserialize)]
+pub struct HouseholdState {
+    pub home: PrototypeID,
+    pub household_type: HouseholdTypeToSpawn,
+    pub resources: Inventory,
+    pub member_resources: CVec<Inventory>,
+}
+
+// Lanes, lots, buildings and the construction queue are not stored directly:
+// they are fully determined by the master plan result and are reconstructed
+// from it on load. Households move back into the rebuilt buildings.
+// Pending wake-ups of sleepers are not stored either, since they refer to actors
+// of the previous run - all sleepers schedule their first wake-up again when respawned.
+#[derive(Serialize, Deserialize)]
+pub struct SaveGame {
+    pub version: u32,
+    pub simulation: SimulationState,
+    pub planning: PlanningState,
+    pub treasury: TreasuryState,
+    pub construction: ConstructionState,
+    pub households: CVec<HouseholdState>,
+}
+
+impl SaveGame {
+    pub fn serialize(&self) -> Result<String, String> {
+        ::serde_json::to_string(self).map_err(|err| err.to_string())
+    }
+
+    pub fn read_from(path: &str) -> Result<SaveGame, String> {
+        let mut file = File::open(path).map_err(|err| err.to_string())?;
+        let mut serialized = String::new();
+        file.read_to_string(&mut serialized)
+            .map_err(|err| err.to_string())?;
+        let save_game: SaveGame =
+            ::serde_json::from_str(&serialized).map_err(|err| err.to_string())?;
+
+        if save_game.version == SAVEGAME_VERSION {
+            Ok(save_game)
+        } else {
+            Err(format!(
+                "Savegame has version {}, but this version of Citybound reads version {}",
+                save_game.version, SAVEGAME_VERSION
+            ))
+        }
+    }
+}
+
+fn write_serialized(serialized: &str, path: &str) -> Result<(), String> {
+    let mut file = File::create(path).map_err(|err| err.to_string())?;
+    file.write_all(serialized.as_bytes())
+        .map_err(|err| err.to_string())
+}
+
+// The last complete savegame, kept outside of the actor system so that an
+// emergency save after a crash doesn't depend on actors that might have panicked
+pub type LastGoodSave = Arc<Mutex<Option<String>>>;
+
+pub fn write_last_good_save(last_good_save: &LastGoodSave, path: &str) -> Result<(), String> {
+    // a panic while holding the lock doesn't make the snapshot itself invalid
+    let snapshot = match last_good_save.lock() {
+        Ok(snapshot) => snapshot,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+
+    match *snapshot {
+        Some(ref serialized) => write_serialized(serialized, path),
+        None => Err("No complete savegame yet".to_owned()),
+    }
+}
+
+#[derive(Compact, Clone)]
+pub struct SaveGameWriter {
+    id: SaveGameWriterID,
+    // without a path, only the last good save is updated
+    path: COption<CString>,
+    last_good_save: External<LastGoodSave>,
+    simulation: COption<SimulationState>,
+    planning: COption<PlanningState>,
+    treasury: COption<TreasuryState>,
+    construction: COption<ConstructionState>,
+    // buildings of the master plan result that still have to report their households
+    pending_buildings: CVec<PrototypeID>,
+    n_expected_households: u32,
+    households: CVec<HouseholdState>,
+}
+
+impl SaveGameWriter {
+    pub fn spawn(
+        id: SaveGameWriterID,
+        path: &COption<CString>,
+        last_good_save: &External<LastGoodSave>,
+        simulation: SimulationID,
+        plan_manager: PlanManagerID,
+        treasury: TreasuryID,
+        world: &mut World,
+    ) -> SaveGameWriter {
+        simulation.save_to(id, world);
+        plan_manager.save_to(id, world);
+        treasury.save_to(id, world);
+        ConstructionID::global_first(world).save_to(id, world);
+
+        SaveGameWriter {
+            id,
+            path: path.clone(),
+            last_good_save: last_good_save.steal(),
+            simulation: COption(None),
+            planning: COption(None),
+            treasury: COption(None),
+            construction: COption(None),
+            pending_buildings: CVec::new(),
+            n_expected_households: 0,
+            households: CVec::new(),
+        }
+    }
+
+    pub fn on_simulation_state(&mut self, state: &SimulationState, world: &mut World) {
+        self.simulation = COption(Some(state.clone()));
+        self.try_write(world);
+    }
+
+    pub fn on_planning_state(&mut self, state: &PlanningState, world: &mut World) {
+        self.planning = COption(Some(state.clone()));
+
+        for prototype in state.master_result.prototypes.values() {
+            if let PrototypeKind::Lot(LotPrototype {
+                occupancy: LotOccupancy::Occupied(_),
+                ..
+            }) = prototype.kind
+            {
+                self.pending_buildings.push(prototype.id);
+            }
+        }
+
+        if self.pending_buildings.is_empty() {
+            self.try_write(world);
+        } else {
+            ConstructionID::global_first(world).report_buildings_for_save(
+                self.pending_buildings.clone(),
+                self.id,
+                world,
+            );
+        }
+    }
+
+    pub fn on_building_saved(
+        &mut self,
+        as_prototype: PrototypeID,
+        n_households: u32,
+        world: &mut World,
+    ) {
+        self.pending_buildings
+            .retain(|pending| *pending != as_prototype);
+        self.n_expected_households += n_households;
+        self.try_write(world);
+    }
+
+    pub fn on_household_state(&mut self, state: &HouseholdState, world: &mut World) {
+        self.households.push(state.clone());
+        self.try_write(world);
+    }
+
+    pub fn on_treasury_state(&mut self, state: &TreasuryState, world: &mut World) {
+        self.treasury = COption(Some(state.clone()));
+        self.try_write(world);
+    }
+
+    pub fn on_construction_state(&mut self, state: &ConstructionState, world: &mut World) {
+        self.construction = COption(Some(state.clone()));
+        self.try_write(world);
+    }
+
+    fn n_missing_households(&self) -> usize {
+        (self.n_expected_households as usize).saturating_sub(self.households.len())
+    }
+
+    fn try_write(&mut self, world: &mut World) {
+        let households_complete =
+            self.pending_buildings.is_empty() && self.n_missing_households() == 0;
+
+        if households_complete && self.write() {
+            self.id.done(world);
+        }
+    }
+
+    // Buildings and households that are removed while saving never report back,
+    // so a save that is still waiting for them when the next one starts is written without them
+    pub fn finish_incomplete(&mut self, world: &mut World) {
+        println!(
+            "Saving without {} buildings and {} households that didn't report back",
+            self.pending_buildings.len(),
+            self.n_missing_households()
+        );
+
+        if !self.write() {
+            println!("Gave up on a save that is missing the state of whole subsystems");
+        }
+
+        self.id.done(world);
+    }
+
+    // Returns whether everything but the households was there to be written
+    fn write(&mut self) -> bool {
+        if let (Some(simulation), Some(planning), Some(treasury), Some(construction)) = (
+            self.simulation.as_ref(),
+            self.planning.as_ref(),
+            self.treasury.as_ref(),
+            self.construction.as_ref(),
+        ) {
+            let save_game = SaveGame {
+                version: SAVEGAME_VERSION,
+                simulation: simulation.clone(),
+                planning: planning.clone(),
+                treasury: treasury.clone(),
+                construction: construction.clone(),
+                households: self.households.clone(),
+            };
+
+            match save_game.serialize() {
+                Ok(serialized) => {
+                    if let Some(ref path) = *self.path {
+                        match write_serialized(&serialized, path) {
+                            Ok(()) => println!("Saved city to {}", &**path),
+                            Err(err) => println!("Couldn't save city to {}: {}", &**path, err),
+                        }
+                    }
+
+                    if let Ok(mut snapshot) = self.last_good_save.lock() {
+                        *snapshot = Some(serialized);
+                    }
+                }
+                Err(err) => println!("Couldn't serialize city: {}", err),
+            }
+
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn done(&mut self, _: &mut World) -> Fate {
+        Fate::Die
+    }
+}
+
+pub fn save(
+    world: &mut World,
+    path: Option<&str>,
+    last_good_save: &LastGoodSave,
+    simulation: SimulationID,
+    plan_manager: PlanManagerID,
+    treasury: TreasuryID,
+) {
+    SaveGameWriterID::local_broadcast(world).finish_incomplete(world);
+
+    let c_path = COption(path.map(|path| {
+        let mut c_path = CString::new();
+        c_path.push_str(path);
+        c_path
+    }));
+    SaveGameWriterID::spawn(
+        c_path,
+        External::new(last_good_save.clone()),
+        simulation,
+        plan_manager,
+        treasury,
+        world,
+    );
+}
+
+pub fn restore(
+    world: &mut World,
+    save_game: SaveGame,
+    simulation: SimulationID,
+    plan_manager: PlanManagerID,
+    treasury: TreasuryID,
+) {
+    simulation.restore(save_game.simulation, world);
+    // before the city is rebuilt and saved requests are granted
+    ConstructionID::global_first(world).restore(save_game.construction, world);
+    plan_manager.restore(save_game.planning, world);
+    treasury.restore(save_game.treasury, world);
+    ConstructionID::global_first(world).restore_households(save_game.households, world);
+}
+
+pub fn setup(system: &mut ActorSystem) {
+    system.register::<SaveGameWriter>();
+    auto_setup(system);
+}
+
+mod kay_auto;
+pub use self::kay_auto::*;
@@ -0,0 +1,272 @@
+use kay::{World, MachineID, TypedID};
+use compact::{CVec, CHashMap, CString, COption};
+use super::{PlanHistory, PlanResult, Proposal, ProposalID, PlanManager, GestureID,
+VersionedGesture, Prototype, PrototypeID, Action, ActionGroups};
+use super::ui::PlanningUIID;
+use super::permissions::{PlanningPolicy, PlanningAction};
+use super::voting::ProposalStatus;
+use construction::ConstructionID;
+use construction::queue::ConstructionGroupID;
+
+// What is needed to take an implemented proposal back out of the master plan,
+// as long as none of its construction has started
+#[derive(Compact, Clone)]
+pub struct UnbuiltImplementation {
+    // gestures as they were before the proposal was implemented
+    previous_gestures: CHashMap<GestureID, VersionedGesture>,
+    added_gestures: CVec<GestureID>,
+    // prototypes that the construction would replace or tear down
+    replaced_prototypes: CHashMap<PrototypeID, Prototype>,
+    actions: ActionGroups,
+}
+
+impl UnbuiltImplementation {
+    pub fn new(
+        proposal: &Proposal,
+        master_plan: &PlanHistory,
+        master_result: &PlanResult,
+        actions: &ActionGroups,
+    ) -> UnbuiltImplementation {
+        let mut previous_gestures = CHashMap::new();
+        let mut added_gestures = CVec::new();
+
+        for plan in &proposal.undoable_history {
+            for gesture_id in plan.gestures.keys() {
+                if let Some(previous_gesture) = master_plan.gestures.get(*gesture_id) {
+                    previous_gestures.insert(*gesture_id, previous_gesture.clone());
+                } else if !added_gestures.contains(gesture_id) {
+                    added_gestures.push(*gesture_id);
+                }
+            }
+        }
+
+        let mut replaced_prototypes = CHashMap::new();
+
+        for group in &actions.0 {
+            for action in &group.0 {
+                match *action {
+                    Action::Morph(prototype_id, _) | Action::Destruct(prototype_id) => {
+                        if let Some(prototype) = master_result.prototypes.get(prototype_id) {
+                            replaced_prototypes.insert(prototype_id, prototype.clone());
+                        }
+                    }
+                    Action::Construct(_) => {}
+                }
+            }
+        }
+
+        UnbuiltImplementation {
+            previous_gestures,
+            added_gestures,
+            replaced_prototypes,
+            actions: actions.clone(),
+        }
+    }
+
+    // If a later implementation changed the same gestures again, reverting would undo that, too
+    fn is_superseded(&self, proposal: &Proposal, master_plan: &PlanHistory) -> bool {
+        let step_ids = proposal
+            .undoable_history
+            .iter()
+            .map(|plan| plan.step_id)
+            .collect::<Vec<_>>();
+
+        self.previous_gestures
+            .keys()
+            .chain(self.added_gestures.iter())
+            .any(|gesture_id| {
+                master_plan
+                    .gestures
+                    .get(*gesture_id)
+                    .map(|gesture| !step_ids.contains(&gesture.1))
+                    .unwrap_or(true)
+            })
+    }
+
+    fn reverted_plan(&self, master_plan: &PlanHistory) -> PlanHistory {
+        let mut history = master_plan.clone();
+        for (gesture_id, previous_gesture) in self.previous_gestures.pairs() {
+            history
+                .gestures
+                .insert(*gesture_id, previous_gesture.clone());
+        }
+        for gesture_id in &self.added_gestures {
+            history.gestures.remove(*gesture_id);
+        }
+        history
+    }
+
+    // What will actually be built once the construction is dropped
+    fn result_without_construction(&self, master_result: &PlanResult) -> PlanResult {
+        let mut result = master_result.clone();
+
+        for group in &self.actions.0 {
+            for action in &group.0 {
+                let (removed, restored) = match *action {
+                    Action::Construct(prototype_id) => (Some(prototype_id), None),
+                    Action::Morph(old_prototype_id, new_prototype_id) => {
+                        (Some(new_prototype_id), Some(old_prototype_id))
+                    }
+                    Action::Destruct(prototype_id) => (None, Some(prototype_id)),
+                };
+
+                if let Some(prototype_id) = removed {
+                    result.prototypes.remove(prototype_id);
+                }
+
+                if let Some(prototype_id) = restored {
+                    if let Some(prototype) = self.replaced_prototypes.get(prototype_id) {
+                        result.prototypes.insert(prototype_id, prototype.clone());
+                    }
+                }
+            }
+        }
+
+        result
+    }
+}
+
+impl PlanManager {
+    // Reverting follows the same rules as implementing
+    fn may_revert(&self, machine: MachineID, proposal_id: ProposalID) -> bool {
+        self.implemented_proposals
+            .get(proposal_id)
+            .map(|proposal| match self.policy {
+                PlanningPolicy::FreeForAll => proposal.is_owned_by(machine) || proposal.shared,
+                PlanningPolicy::OwnerOnly | PlanningPolicy::Voting => {
+                    proposal.is_owned_by(machine)
+                }
+            }).unwrap_or(false)
+    }
+
+    // Prioritizing follows the same rules as reverting. Construction that doesn't
+    // implement a proposal belongs to everyone, so only the server may reorder it
+    pub fn prioritize_implementation(
+        &mut self,
+        group_id: ConstructionGroupID,
+        proposal_id: &COption<ProposalID>,
+        ui: PlanningUIID,
+        world: &mut World,
+    ) {
+        let machine = ui.as_raw().machine;
+
+        match **proposal_id {
+            Some(proposal_id) if !self.may_revert(machine, proposal_id) => {
+                PlanningUIID::global_broadcast(world).on_permission_denied(
+                    proposal_id,
+                    machine,
+                    PlanningAction::Implement,
+                    world,
+                );
+            }
+            None if self.policy != PlanningPolicy::FreeForAll
+                && machine != self.id.as_raw().machine =>
+            {
+                println!("Machine {:?} may not prioritize {:?}", machine, group_id);
+            }
+            _ => ConstructionID::global_first(world).move_group_to_front(group_id, ui, world),
+        }
+    }
+
+    pub fn on_construction_started(&mut self, proposal_id: ProposalID, _: &mut World) {
+        self.unbuilt_implementations.remove(proposal_id);
+    }
+
+    fn refuse_revert(&self, group_id: ConstructionGroupID, reason: &str, world: &mut World) {
+        let mut reason_string = CString::new();
+        reason_string.push_str(reason);
+        ConstructionID::global_first(world).resume_group(group_id, reason_string, world);
+    }
+
+    // Takes the proposal back out of the master plan and makes it a draft again,
+    // while the construction drops the group and corrects anything else that changed
+    pub fn revert_implementation(
+        &mut self,
+        proposal_id: ProposalID,
+        group_id: ConstructionGroupID,
+        ui: PlanningUIID,
+        world: &mut World,
+    ) {
+        let machine = ui.as_raw().machine;
+
+        if !self.may_revert(machine, proposal_id) {
+            PlanningUIID::global_broadcast(world).on_permission_denied(
+                proposal_id,
+                machine,
+                PlanningAction::Implement,
+                world,
+            );
+            self.refuse_revert(group_id, "Not allowed to cancel this proposal", world);
+            return;
+        }
+
+        let (reverted_plan, reverted_result, built_result) = {
+            let unbuilt = match self.unbuilt_implementations.get(proposal_id) {
+                Some(unbuilt) => unbuilt,
+                None => {
+                    self.refuse_revert(group_id, "It can't be cancelled anymore", world);
+                    return;
+                }
+            };
+
+            let proposal = self
+                .implemented_proposals
+                .get(proposal_id)
+                .expect("Should have implemented proposal");
+
+            if unbuilt.is_superseded(proposal, &self.master_plan) {
+                self.refuse_revert(group_id, "A later proposal changed it again", world);
+                return;
+            }
+
+            let reverted_plan = unbuilt.reverted_plan(&self.master_plan);
+            let reverted_result = match reverted_plan.calculate_result() {
+                Ok(result) => result,
+                Err(error) => {
+                    PlanningUIID::global_broadcast(world).on_plan_error(
+                        proposal_id,
+                        error,
+                        world,
+                    );
+                    self.refuse_revert(group_id, "The master plan without it is invalid", world);
+                    return;
+                }
+            };
+
+            (
+                reverted_plan,
+                reverted_result,
+                unbuilt.result_without_construction(&self.master_result),
+            )
+        };
+
+        let (corrections, new_prototypes) = built_result.actions_to(&reverted_result);
+        ConstructionID::global_first(world).drop_group(
+            group_id,
+            corrections,
+            new_prototypes,
+            world,
+        );
+
+        self.unbuilt_implementations.remove(proposal_id);
+        let old_master_plan = ::std::mem::replace(&mut self.master_plan, reverted_plan);
+        self.master_result = reverted_result;
+        self.rebase_after_replaced_master(&old_master_plan, world);
+
+        let mut proposal = self
+            .implemented_proposals
+            .remove(proposal_id)
+            .expect("Should have implemented proposal");
+        proposal.base_on(&self.master_plan);
+        self.proposals.insert(proposal_id, proposal);
+        self.set_status(proposal_id, ProposalStatus::Draft, world);
+
+        let all_proposal_ids = self.proposals.keys().cloned().collect::<Vec<_>>();
+        for proposal_id in all_proposal_ids {
+            self.clear_previews(proposal_id);
+        }
+    }
+}
+
+pub mod kay_auto;
+pub use self::kay_auto::*;
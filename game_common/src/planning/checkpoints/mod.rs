@@ -0,0 +1,254 @@
+use kay::{World, MachineID, TypedID};
+use compact::{CVec, CString, COption, CHashMap};
+use super::{PlanHistory, PlanManager, GestureID};
+use super::ui::PlanningUIID;
+use super::permissions::PlanningPolicy;
+use super::voting::ProposalStatus;
+use construction::ConstructionID;
+use journal::{JournalID, JournaledCommand};
+
+// A named snapshot of the master plan that it can be rolled back to
+#[derive(Compact, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub name: CString,
+    pub history: PlanHistory,
+}
+
+// Gestures that were added, changed or removed between two checkpoints
+#[derive(Compact, Clone, Debug, Serialize, Deserialize)]
+pub struct PlanDiff {
+    pub added: CVec<GestureID>,
+    pub changed: CVec<GestureID>,
+    pub removed: CVec<GestureID>,
+}
+
+impl PlanDiff {
+    pub fn between(from: &PlanHistory, to: &PlanHistory) -> PlanDiff {
+        let mut added = Vec::new();
+        let mut changed = Vec::new();
+        let mut removed = Vec::new();
+
+        for (gesture_id, to_gesture) in to.gestures.pairs() {
+            match from.gestures.get(*gesture_id) {
+                None => added.push(*gesture_id),
+                // every change of a gesture happens in a new step
+                Some(from_gesture) if from_gesture.1 != to_gesture.1 => {
+                    changed.push(*gesture_id)
+                }
+                Some(_) => {}
+            }
+        }
+
+        for gesture_id in from.gestures.keys() {
+            if !to.gestures.contains_key(*gesture_id) {
+                removed.push(*gesture_id);
+            }
+        }
+
+        added.sort_by_key(|gesture_id| gesture_id.0);
+        changed.sort_by_key(|gesture_id| gesture_id.0);
+        removed.sort_by_key(|gesture_id| gesture_id.0);
+
+        PlanDiff {
+            added: added.into(),
+            changed: changed.into(),
+            removed: removed.into(),
+        }
+    }
+}
+
+impl PlanManager {
+    fn checkpoint(&self, name: &str) -> Option<&Checkpoint> {
+        self.checkpoints
+            .iter()
+            .find(|checkpoint| &*checkpoint.name == name)
+    }
+
+    // Rolling back affects everyone, so unless anything goes,
+    // only the server machine itself may manage checkpoints
+    fn may_manage_checkpoints(&self, machine: MachineID) -> bool {
+        self.policy == PlanningPolicy::FreeForAll || machine == self.id.as_raw().machine
+    }
+
+    fn checkpoint_names(&self) -> CVec<CString> {
+        self.checkpoints
+            .iter()
+            .map(|checkpoint| checkpoint.name.clone())
+            .collect::<Vec<_>>()
+            .into()
+    }
+
+    // Replaces an older checkpoint with the same name
+    pub fn create_checkpoint(&mut self, name: &CString, ui: PlanningUIID, world: &mut World) {
+        let machine = ui.as_raw().machine;
+
+        if !self.may_manage_checkpoints(machine) {
+            println!("Machine {:?} may not create checkpoints", machine);
+            return;
+        }
+
+        JournalID::local_first(world).record(
+            JournaledCommand::CreateCheckpoint(name.clone(), ui),
+            world,
+        );
+
+        self.checkpoints
+            .retain(|checkpoint| *checkpoint.name != **name);
+        self.checkpoints.push(Checkpoint {
+            name: name.clone(),
+            history: self.master_plan.clone(),
+        });
+
+        let names = self.checkpoint_names();
+        PlanningUIID::global_broadcast(world).on_checkpoints_update(names, world);
+    }
+
+    pub fn get_checkpoints(&mut self, ui: PlanningUIID, world: &mut World) {
+        ui.on_checkpoints_update(self.checkpoint_names(), world);
+    }
+
+    // An empty name stands for the current master plan
+    pub fn diff_checkpoints(
+        &mut self,
+        from: &CString,
+        to: &CString,
+        ui: PlanningUIID,
+        world: &mut World,
+    ) {
+        let diff = {
+            let history_of = |name: &CString| {
+                if name.is_empty() {
+                    Some(&self.master_plan)
+                } else {
+                    self.checkpoint(name).map(|checkpoint| &checkpoint.history)
+                }
+            };
+
+            match (history_of(from), history_of(to)) {
+                (Some(from_history), Some(to_history)) => {
+                    PlanDiff::between(from_history, to_history)
+                }
+                _ => {
+                    println!("Tried to diff unknown checkpoints {} and {}", &**from, &**to);
+                    return;
+                }
+            }
+        };
+
+        ui.on_checkpoint_diff(from.clone(), to.clone(), diff, world);
+    }
+
+    // Goes back to the master plan of the checkpoint and tears down or rebuilds
+    // whatever changed since. Later checkpoints are kept, so this can be undone
+    pub fn rollback_to_checkpoint(
+        &mut self,
+        name: &CString,
+        ui: PlanningUIID,
+        world: &mut World,
+    ) {
+        let machine = ui.as_raw().machine;
+
+        if !self.may_manage_checkpoints(machine) {
+            println!("Machine {:?} may not roll back the master plan", machine);
+            return;
+        }
+
+        let history = match self.checkpoint(name) {
+            Some(checkpoint) => checkpoint.history.clone(),
+            None => {
+                println!("Tried to roll back to unknown checkpoint {}", &**name);
+                return;
+            }
+        };
+
+        let result = match history.calculate_result() {
+            Ok(result) => result,
+            Err(error) => {
+                println!(
+                    "Couldn't roll back to checkpoint {}: {}",
+                    &**name, &*error.description
+                );
+                return;
+            }
+        };
+
+        JournalID::local_first(world).record(
+            JournaledCommand::RollbackToCheckpoint(name.clone(), ui),
+            world,
+        );
+
+        let (actions, new_prototypes) = self.master_result.actions_to(&result);
+        ConstructionID::global_first(world).implement(
+            actions,
+            new_prototypes,
+            COption(None),
+            false,
+            world,
+        );
+        // what is still queued now belongs to the rolled back master plan
+        self.unbuilt_implementations = CHashMap::new();
+
+        let old_master_plan = ::std::mem::replace(&mut self.master_plan, history);
+        self.master_result = result;
+        self.rebase_after_replaced_master(&old_master_plan, world);
+
+        // implemented proposals whose last step was rolled back become drafts again
+        let rolled_back_ids = self
+            .implemented_proposals
+            .pairs()
+            .filter(|&(_, proposal)| {
+                proposal
+                    .current_history()
+                    .last()
+                    .map(|plan| !self.master_plan.steps.contains(&plan.step_id))
+                    .unwrap_or(false)
+            }).map(|(proposal_id, _)| *proposal_id)
+            .collect::<Vec<_>>();
+
+        for proposal_id in rolled_back_ids {
+            let mut proposal = self
+                .implemented_proposals
+                .remove(proposal_id)
+                .expect("just found it");
+            proposal.base_on(&self.master_plan);
+            self.proposals.insert(proposal_id, proposal);
+            self.set_status(proposal_id, ProposalStatus::Draft, world);
+        }
+
+        let all_proposal_ids = self.proposals.keys().cloned().collect::<Vec<_>>();
+        for proposal_id in all_proposal_ids {
+            self.clear_previews(proposal_id);
+        }
+
+        PlanningUIID::global_broadcast(world).on_rolled_back(name.clone(), world);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use planning::test_util::step;
+
+    #[test]
+    fn diff_finds_added_changed_and_removed_gestures() {
+        let (a, b, c) = (GestureID::new(), GestureID::new(), GestureID::new());
+        let earlier = PlanHistory::new().and_then(&[step(&[(a, 1.0), (b, 1.0)])]);
+        let later = earlier.and_then(&[step(&[(b, 2.0)]), step(&[(c, 1.0)])]);
+
+        let diff = PlanDiff::between(&earlier, &later);
+        assert_eq!(&diff.added[..], &[c][..]);
+        assert_eq!(&diff.changed[..], &[b][..]);
+        assert!(diff.removed.is_empty());
+
+        // rolling back removes what was added since
+        let diff = PlanDiff::between(&later, &earlier);
+        assert!(diff.added.is_empty());
+        assert_eq!(&diff.changed[..], &[b][..]);
+        assert_eq!(&diff.removed[..], &[c][..]);
+
+        assert!(PlanDiff::between(&later, &later).changed.is_empty());
+    }
+}
+
+pub mod kay_auto;
+pub use self::kay_auto::*;
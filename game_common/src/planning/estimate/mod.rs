@@ -0,0 +1,161 @@
+use kay::{World, MachineID};
+use compact::CVec;
+use simulation::Duration;
+use transport::transport_planning::{RoadPrototype, LanePrototype, SwitchLanePrototype};
+use land_use::zone_planning::{LotPrototype, LotOccupancy};
+
+use super::{PlanManager, PlanResult, Prototype, PrototypeKind, PrototypeID, Action,
+ActionGroups};
+
+#[derive(Compact, Clone, Serialize, Deserialize)]
+pub struct ProposalEstimate {
+    pub lane_length_built: f32,
+    pub lane_length_destroyed: f32,
+    pub intersections_built: u32,
+    pub intersections_destroyed: u32,
+    pub lots_affected: u32,
+    pub buildings_affected: u32,
+    pub displaced_households: u32,
+    // destroyed buildings whose households haven't been counted yet
+    pub buildings_pending_count: CVec<PrototypeID>,
+    pub cost: f32,
+    pub construction_time: Duration,
+}
+
+fn lane_length(prototype: &Prototype) -> f32 {
+    match prototype.kind {
+        PrototypeKind::Road(RoadPrototype::Lane(LanePrototype(ref path, _)))
+        | PrototypeKind::Road(RoadPrototype::SwitchLane(SwitchLanePrototype(ref path))) => {
+            path.length()
+        }
+        PrototypeKind::Road(RoadPrototype::Intersection(ref intersection)) => intersection
+            .connecting_lanes
+            .values()
+            .flat_map(|lanes| lanes.iter())
+            .map(|&LanePrototype(ref path, _)| path.length())
+            .sum(),
+        _ => 0.0,
+    }
+}
+
+fn is_intersection(prototype: &Prototype) -> bool {
+    if let PrototypeKind::Road(RoadPrototype::Intersection(_)) = prototype.kind {
+        true
+    } else {
+        false
+    }
+}
+
+fn is_building(prototype: &Prototype) -> bool {
+    if let PrototypeKind::Lot(LotPrototype {
+        occupancy: LotOccupancy::Occupied(_),
+        ..
+    }) = prototype.kind
+    {
+        true
+    } else {
+        false
+    }
+}
+
+fn is_lot(prototype: &Prototype) -> bool {
+    if let PrototypeKind::Lot(_) = prototype.kind {
+        true
+    } else {
+        false
+    }
+}
+
+impl ProposalEstimate {
+    // Constructed and morphed-to prototypes are looked up in the preview result,
+    // destructed and morphed-from prototypes in the current master result
+    pub fn from_actions(
+        actions: &ActionGroups,
+        master_result: &PlanResult,
+        preview_result: &PlanResult,
+    ) -> ProposalEstimate {
+        let mut estimate = ProposalEstimate {
+            lane_length_built: 0.0,
+            lane_length_destroyed: 0.0,
+            intersections_built: 0,
+            intersections_destroyed: 0,
+            lots_affected: 0,
+            buildings_affected: 0,
+            displaced_households: 0,
+            buildings_pending_count: CVec::new(),
+            cost: 0.0,
+            construction_time: Duration(0),
+        };
+
+        for action in actions.0.iter().flat_map(|group| group.0.iter()) {
+            match *action {
+                Action::Construct(prototype_id) => {
+                    if let Some(prototype) = preview_result.prototypes.get(prototype_id) {
+                        estimate.construction_time += prototype.construction_time();
+                        estimate.cost += prototype.construction_cost();
+                        estimate.lane_length_built += lane_length(prototype);
+                        if is_intersection(prototype) {
+                            estimate.intersections_built += 1;
+                        }
+                        if is_lot(prototype) {
+                            estimate.lots_affected += 1;
+                        }
+                        if is_building(prototype) {
+                            estimate.buildings_affected += 1;
+                        }
+                    }
+                }
+                Action::Morph(_, new_prototype_id) => {
+                    if let Some(prototype) = preview_result.prototypes.get(new_prototype_id) {
+                        if is_lot(prototype) {
+                            estimate.lots_affected += 1;
+                        }
+                        if is_building(prototype) {
+                            estimate.buildings_affected += 1;
+                        }
+                    }
+                }
+                Action::Destruct(prototype_id) => {
+                    if let Some(prototype) = master_result.prototypes.get(prototype_id) {
+                        estimate.construction_time += prototype.destruction_time();
+                        estimate.cost += prototype.destruction_cost();
+                        estimate.lane_length_destroyed += lane_length(prototype);
+                        if is_intersection(prototype) {
+                            estimate.intersections_destroyed += 1;
+                        }
+                        if is_lot(prototype) {
+                            estimate.lots_affected += 1;
+                        }
+                        if is_building(prototype) {
+                            estimate.buildings_affected += 1;
+                            estimate.buildings_pending_count.push(prototype_id);
+                        }
+                    }
+                }
+            }
+        }
+
+        estimate
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.buildings_pending_count.is_empty()
+    }
+}
+
+impl PlanManager {
+    pub fn on_displaced_households(
+        &mut self,
+        machine: MachineID,
+        building_prototype_id: PrototypeID,
+        n_households: u32,
+        _: &mut World,
+    ) {
+        if let Some(ui_state) = self.ui_state.get_mut(machine) {
+            ui_state.count_displaced_households(building_prototype_id, n_households);
+        }
+    }
+}
+
+pub mod kay_auto;
+pub use self::kay_auto::*;
@@ -0,0 +1,877 @@
+use kay::{World, ActorSystem, Fate, TypedID};
+use compact::{CVec, COption, CString};
+use descartes::{N, P2, Area, LinePath};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{Read, Write};
+use transport::transport_planning::{RoadIntent, RoadPrototype, LanePrototype,
+SwitchLanePrototype};
+use land_use::zone_planning::{ZoneIntent, LandUse, Lot, LotPrototype, LotOccupancy};
+use land_use::buildings::{BuildingID, BuildingStyle};
+use construction::ConstructionID;
+
+use super::{ProposalFile, ExportedGesture, PROPOSAL_FILE_VERSION};
+use planning::{ProposalID, PlanManager, PlanManagerID, PlanResult, Prototype, PrototypeID,
+PrototypeKind, Gesture, GestureID, GestureIntent, VersionedGesture};
+
+const METERS_PER_DEGREE: f64 = 6_371_000.0 * ::std::f64::consts::PI / 180.0;
+// points of imported roads closer than this are merged
+const MIN_POINT_DISTANCE: N = 1.0;
+
+// Equirectangular projection around an origin, precise enough for city-sized areas.
+// x points east, y points north, both in meters
+#[derive(Copy, Clone, Debug, Default)]
+pub struct GeoProjection {
+    pub origin_longitude: f64,
+    pub origin_latitude: f64,
+}
+
+impl GeoProjection {
+    pub fn new(origin_longitude: f64, origin_latitude: f64) -> GeoProjection {
+        GeoProjection {
+            origin_longitude,
+            origin_latitude,
+        }
+    }
+
+    // Parses "longitude,latitude"
+    pub fn parse(origin: &str) -> Result<GeoProjection, String> {
+        let parts = origin
+            .split(',')
+            .map(|part| part.trim().parse::<f64>().map_err(|err| err.to_string()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if parts.len() == 2 {
+            Ok(GeoProjection::new(parts[0], parts[1]))
+        } else {
+            Err(format!("Expected longitude,latitude but got {}", origin))
+        }
+    }
+
+    pub fn to_p2(&self, longitude: f64, latitude: f64) -> P2 {
+        let x = (longitude - self.origin_longitude)
+            * METERS_PER_DEGREE
+            * self.origin_latitude.to_radians().cos();
+        let y = (latitude - self.origin_latitude) * METERS_PER_DEGREE;
+        P2::new(x as N, y as N)
+    }
+
+    pub fn to_longitude_latitude(&self, point: P2) -> (f64, f64) {
+        let longitude = self.origin_longitude
+            + f64::from(point.x) / (METERS_PER_DEGREE * self.origin_latitude.to_radians().cos());
+        let latitude = self.origin_latitude + f64::from(point.y) / METERS_PER_DEGREE;
+        (longitude, latitude)
+    }
+}
+
+fn position(value: &Value) -> Option<(f64, f64)> {
+    let coordinates = value.as_array()?;
+    Some((coordinates.get(0)?.as_f64()?, coordinates.get(1)?.as_f64()?))
+}
+
+fn positions(value: &Value) -> Vec<(f64, f64)> {
+    value
+        .as_array()
+        .map(|array| array.iter().filter_map(position).collect())
+        .unwrap_or_else(Vec::new)
+}
+
+// LineStrings and outer rings of Polygons, as lists of positions
+fn lines_and_rings(geometry: &Value) -> (Vec<Vec<(f64, f64)>>, Vec<Vec<(f64, f64)>>) {
+    let coordinates = &geometry["coordinates"];
+    let as_arrays = |value: &Value| value.as_array().cloned().unwrap_or_else(Vec::new);
+
+    match geometry["type"].as_str() {
+        Some("LineString") => (vec![positions(coordinates)], vec![]),
+        Some("MultiLineString") => (
+            as_arrays(coordinates).iter().map(positions).collect(),
+            vec![],
+        ),
+        Some("Polygon") => (vec![], vec![positions(&coordinates[0])]),
+        Some("MultiPolygon") => (
+            vec![],
+            as_arrays(coordinates)
+                .iter()
+                .map(|polygon| positions(&polygon[0]))
+                .collect(),
+        ),
+        Some("GeometryCollection") => {
+            let mut lines = Vec::new();
+            let mut rings = Vec::new();
+            for sub_geometry in as_arrays(&geometry["geometries"]) {
+                let (sub_lines, sub_rings) = lines_and_rings(&sub_geometry);
+                lines.extend(sub_lines);
+                rings.extend(sub_rings);
+            }
+            (lines, rings)
+        }
+        _ => (vec![], vec![]),
+    }
+}
+
+// Numbers might be stored as strings, as in OSM tags
+fn number_property(properties: &Value, key: &str) -> Option<u8> {
+    match properties[key] {
+        Value::Number(ref number) => number.as_u64().map(|n| n.min(255) as u8),
+        Value::String(ref string) => string.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+// Returns the intent and whether the line needs to be reversed (for oneway=-1)
+fn road_intent(properties: &Value) -> (RoadIntent, bool) {
+    // our own exports
+    if let (Some(forward), Some(backward)) = (
+        number_property(properties, "n_lanes_forward"),
+        number_property(properties, "n_lanes_backward"),
+    ) {
+        return (RoadIntent::new(forward.max(1), backward), false);
+    }
+
+    let oneway = match properties["oneway"] {
+        Value::Bool(oneway) => if oneway { Some(false) } else { None },
+        Value::String(ref oneway) => match oneway.as_str() {
+            "yes" | "true" | "1" => Some(false),
+            "-1" | "reverse" => Some(true),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    let default_lanes = match properties["highway"].as_str() {
+        Some("motorway") | Some("trunk") => 3,
+        Some("primary") | Some("secondary") => 2,
+        _ => 1,
+    };
+
+    let forward = number_property(properties, "lanes:forward");
+    let backward = number_property(properties, "lanes:backward");
+    let total = number_property(properties, "lanes");
+
+    match oneway {
+        Some(reversed) => (
+            RoadIntent::new(forward.or(total).unwrap_or(default_lanes).max(1), 0),
+            reversed,
+        ),
+        None => {
+            let (forward, backward) = match (forward, backward, total) {
+                (Some(forward), Some(backward), _) => (forward, backward),
+                (Some(forward), None, Some(total)) => (forward, total.saturating_sub(forward)),
+                (None, Some(backward), Some(total)) => (total.saturating_sub(backward), backward),
+                (_, _, Some(total)) => ((total + 1) / 2, total / 2),
+                _ => (default_lanes, default_lanes),
+            };
+            (RoadIntent::new(forward.max(1), backward), false)
+        }
+    }
+}
+
+fn land_use(properties: &Value) -> Option<LandUse> {
+    // our own exports use the names of LandUse
+    let from_own_name = match properties["land_use"].as_str() {
+        Some("Residential") => Some(LandUse::Residential),
+        Some("Commercial") => Some(LandUse::Commercial),
+        Some("Industrial") => Some(LandUse::Industrial),
+        Some("Agricultural") => Some(LandUse::Agricultural),
+        Some("Recreational") => Some(LandUse::Recreational),
+        Some("Official") => Some(LandUse::Official),
+        _ => None,
+    };
+
+    from_own_name.or_else(|| {
+        match properties["landuse"]
+            .as_str()
+            .or_else(|| properties["leisure"].as_str())
+        {
+            Some("residential") => Some(LandUse::Residential),
+            Some("commercial") | Some("retail") => Some(LandUse::Commercial),
+            Some("industrial") => Some(LandUse::Industrial),
+            Some("farmland") | Some("farmyard") | Some("meadow") | Some("orchard") => {
+                Some(LandUse::Agricultural)
+            }
+            Some("recreation_ground") | Some("park") | Some("grass") => {
+                Some(LandUse::Recreational)
+            }
+            Some("institutional") | Some("education") | Some("religious") => {
+                Some(LandUse::Official)
+            }
+            _ => None,
+        }
+    })
+}
+
+fn project(positions: &[(f64, f64)], projection: &GeoProjection) -> Vec<P2> {
+    let mut points: Vec<P2> = Vec::with_capacity(positions.len());
+    for &(longitude, latitude) in positions {
+        let point = projection.to_p2(longitude, latitude);
+        let is_duplicate = points
+            .last()
+            .map(|last| (point - *last).norm() < MIN_POINT_DISTANCE)
+            .unwrap_or(false);
+        if !is_duplicate {
+            points.push(point);
+        }
+    }
+    points
+}
+
+// Roads from LineStrings with a highway property, zones from Polygons with a known land use.
+// Everything else is skipped, including the lots and buildings of our own exports,
+// which would otherwise come back as zones on top of the zones they were built in
+pub fn gestures_from_geojson(
+    geojson: &Value,
+    projection: &GeoProjection,
+) -> Vec<(GestureID, Gesture)> {
+    let no_properties = Value::Null;
+    // (properties, geometry) of each feature
+    let features: Vec<(&Value, &Value)> = match geojson["type"].as_str() {
+        Some("FeatureCollection") => geojson["features"]
+            .as_array()
+            .map(|features| {
+                features
+                    .iter()
+                    .map(|feature| (&feature["properties"], &feature["geometry"]))
+                    .collect()
+            }).unwrap_or_else(Vec::new),
+        Some("Feature") => vec![(&geojson["properties"], &geojson["geometry"])],
+        _ => vec![(&no_properties, geojson)],
+    };
+
+    let mut gestures = Vec::new();
+
+    for (properties, geometry) in features {
+        let is_derived = properties["kind"]
+            .as_str()
+            .map(|kind| kind != "road")
+            .unwrap_or(false);
+        if is_derived {
+            continue;
+        }
+
+        let (lines, rings) = lines_and_rings(geometry);
+
+        // rivers, railways, power lines and the like are LineStrings as well
+        if properties["highway"].is_string() {
+            for line in lines {
+                let (intent, reversed) = road_intent(properties);
+                let mut points = project(&line, projection);
+                if reversed {
+                    points.reverse();
+                }
+                if points.len() >= 2 {
+                    gestures.push(Gesture::new(points.into(), GestureIntent::Road(intent)));
+                }
+            }
+        }
+
+        if let Some(land_use) = land_use(properties) {
+            for ring in rings {
+                let mut points = project(&ring, projection);
+                // zone outlines are closed implicitly
+                let is_closed = points.len() > 1
+                    && (points[0] - points[points.len() - 1]).norm() < MIN_POINT_DISTANCE;
+                if is_closed {
+                    points.pop();
+                }
+                if points.len() >= 3 {
+                    gestures.push(Gesture::new(
+                        points.into(),
+                        GestureIntent::Zone(ZoneIntent::LandUse(land_use)),
+                    ));
+                }
+            }
+        }
+    }
+
+    gestures
+        .into_iter()
+        .map(|gesture| (GestureID::new(), gesture))
+        .collect()
+}
+
+pub fn read_geojson(path: &str) -> Result<Value, String> {
+    let mut file = File::open(path).map_err(|err| err.to_string())?;
+    let mut serialized = String::new();
+    file.read_to_string(&mut serialized)
+        .map_err(|err| err.to_string())?;
+    ::serde_json::from_str(&serialized).map_err(|err| err.to_string())
+}
+
+// Loads all roads and zones of the file as a new proposal, owned by the machine of the UI.
+// Without a projection, the center of the file's bounding box ends up at the origin
+pub fn import(
+    world: &mut World,
+    path: &str,
+    maybe_projection: Option<GeoProjection>,
+    ui: PlanningUIID,
+) -> Result<ProposalID, String> {
+    let geojson = read_geojson(path)?;
+
+    let projection = match maybe_projection {
+        Some(projection) => projection,
+        None => bounding_box_center(&geojson)
+            .map(|(longitude, latitude)| GeoProjection::new(longitude, latitude))
+            .ok_or_else(|| format!("{} contains no coordinates", path))?,
+    };
+
+    let gestures = gestures_from_geojson(&geojson, &projection);
+    if gestures.is_empty() {
+        return Err(format!("{} contains no roads or zones", path));
+    }
+
+    let file = ProposalFile {
+        version: PROPOSAL_FILE_VERSION,
+        gestures: gestures
+            .into_iter()
+            .map(|(id, gesture)| ExportedGesture { id, gesture })
+            .collect(),
+    };
+
+    let proposal_id = ProposalID::new();
+    PlanManagerID::global_first(world).import_proposal(proposal_id, ui, file, world);
+    Ok(proposal_id)
+}
+
+fn bounding_box_center(geojson: &Value) -> Option<(f64, f64)> {
+    fn visit(value: &Value, bounds: &mut Option<(f64, f64, f64, f64)>) {
+        if let Some((longitude, latitude)) = position(value) {
+            *bounds = Some(match *bounds {
+                Some((min_lon, min_lat, max_lon, max_lat)) => (
+                    min_lon.min(longitude),
+                    min_lat.min(latitude),
+                    max_lon.max(longitude),
+                    max_lat.max(latitude),
+                ),
+                None => (longitude, latitude, longitude, latitude),
+            });
+        } else if let Some(array) = value.as_array() {
+            for item in array {
+                visit(item, bounds);
+            }
+        }
+    }
+
+    let mut bounds = None;
+    let mut stack = vec![geojson];
+    while let Some(value) = stack.pop() {
+        match *value {
+            Value::Object(ref object) => {
+                if let Some(coordinates) = object.get("coordinates") {
+                    visit(coordinates, &mut bounds);
+                }
+                stack.extend(
+                    object
+                        .iter()
+                        .filter(|&(key, _)| key != "coordinates")
+                        .map(|(_, nested)| nested),
+                );
+            }
+            Value::Array(ref array) => stack.extend(array.iter()),
+            _ => {}
+        }
+    }
+
+    bounds.map(|(min_lon, min_lat, max_lon, max_lat)| {
+        ((min_lon + max_lon) / 2.0, (min_lat + max_lat) / 2.0)
+    })
+}
+
+fn line_string(path: &LinePath, projection: &GeoProjection) -> Value {
+    let coordinates = path
+        .points
+        .iter()
+        .map(|point| {
+            let (longitude, latitude) = projection.to_longitude_latitude(*point);
+            json!([longitude, latitude])
+        }).collect::<Vec<_>>();
+
+    json!({ "type": "LineString", "coordinates": coordinates })
+}
+
+fn multi_polygon(area: &Area, projection: &GeoProjection) -> Value {
+    let polygons = area
+        .primitives
+        .iter()
+        .map(|primitive| {
+            let points = &primitive.boundary.path().points;
+            let mut ring = points
+                .iter()
+                .map(|point| {
+                    let (longitude, latitude) = projection.to_longitude_latitude(*point);
+                    json!([longitude, latitude])
+                }).collect::<Vec<_>>();
+            // GeoJSON rings have to be closed explicitly
+            if points.first() != points.last() {
+                let first = ring[0].clone();
+                ring.push(first);
+            }
+            json!([ring])
+        }).collect::<Vec<_>>();
+
+    json!({ "type": "MultiPolygon", "coordinates": polygons })
+}
+
+fn feature(geometry: Value, properties: Value) -> Value {
+    json!({ "type": "Feature", "geometry": geometry, "properties": properties })
+}
+
+// Exported with the same properties the importer reads, so that roads survive a round trip
+fn road_feature(gesture: &Gesture, projection: &GeoProjection) -> Option<Value> {
+    if let GestureIntent::Road(RoadIntent {
+        n_lanes_forward,
+        n_lanes_backward,
+    }) = gesture.intent
+    {
+        let coordinates = gesture
+            .points
+            .iter()
+            .map(|point| {
+                let (longitude, latitude) = projection.to_longitude_latitude(*point);
+                json!([longitude, latitude])
+            }).collect::<Vec<_>>();
+
+        Some(feature(
+            json!({ "type": "LineString", "coordinates": coordinates }),
+            json!({
+                "kind": "road",
+                "highway": "road",
+                "n_lanes_forward": n_lanes_forward,
+                "n_lanes_backward": n_lanes_backward,
+            }),
+        ))
+    } else {
+        None
+    }
+}
+
+fn lot_properties(lot: &Lot) -> Value {
+    let land_uses = lot
+        .land_uses
+        .iter()
+        .map(|land_use| land_use.to_string())
+        .collect::<Vec<_>>();
+
+    json!({
+        "land_uses": land_uses,
+        "max_height": lot.max_height,
+        "set_back": lot.set_back,
+    })
+}
+
+fn with_properties(mut properties: Value, more: Value) -> Value {
+    if let (Some(properties), Value::Object(more)) = (properties.as_object_mut(), more) {
+        properties.extend(more);
+    }
+    properties
+}
+
+fn prototype_feature(prototype: &Prototype, projection: &GeoProjection) -> Option<Value> {
+    let id = json!(prototype.id);
+
+    match prototype.kind {
+        PrototypeKind::Road(RoadPrototype::Lane(LanePrototype(ref path, _))) => Some(feature(
+            line_string(path, projection),
+            json!({ "kind": "lane", "prototype_id": id }),
+        )),
+        PrototypeKind::Road(RoadPrototype::SwitchLane(SwitchLanePrototype(ref path))) => {
+            Some(feature(
+                line_string(path, projection),
+                json!({ "kind": "switch_lane", "prototype_id": id }),
+            ))
+        }
+        PrototypeKind::Road(RoadPrototype::Intersection(ref intersection)) => Some(feature(
+            multi_polygon(&intersection.area, projection),
+            json!({ "kind": "intersection", "prototype_id": id }),
+        )),
+        PrototypeKind::Road(RoadPrototype::PavedArea(ref area)) => Some(feature(
+            multi_polygon(area, projection),
+            json!({ "kind": "paved_area", "prototype_id": id }),
+        )),
+        PrototypeKind::Lot(LotPrototype { ref lot, occupancy }) => {
+            let occupancy = match occupancy {
+                LotOccupancy::Vacant => json!({ "occupancy": "Vacant" }),
+                LotOccupancy::Occupied(style) => {
+                    json!({ "occupancy": "Occupied", "building_style": style })
+                }
+            };
+            Some(feature(
+                multi_polygon(&lot.area, projection),
+                with_properties(
+                    with_properties(json!({ "kind": "lot", "prototype_id": id }), occupancy),
+                    lot_properties(lot),
+                ),
+            ))
+        }
+    }
+}
+
+#[derive(Compact, Clone)]
+pub struct ExportedBuilding {
+    pub id: BuildingID,
+    pub style: BuildingStyle,
+    pub lot: Lot,
+    pub n_households: u32,
+    pub n_units: u32,
+}
+
+fn building_feature(building: &ExportedBuilding, projection: &GeoProjection) -> Value {
+    feature(
+        multi_polygon(&building.lot.area, projection),
+        with_properties(
+            json!({
+                "kind": "building",
+                "building_id": building.id,
+                "building_style": building.style,
+                "households": building.n_households,
+                "units": building.n_units,
+            }),
+            lot_properties(&building.lot),
+        ),
+    )
+}
+
+pub trait GeoJSONRecipient {
+    fn on_geojson_exported(&mut self, geojson: &CString, world: &mut World) -> Fate;
+}
+
+// Collects the road gestures of the master plan, the prototypes
+// of its result and all buildings living on its lots
+#[derive(Compact, Clone)]
+pub struct GeoJSONExporter {
+    id: GeoJSONExporterID,
+    recipient: GeoJSONRecipientID,
+    projection: GeoProjection,
+    features: CVec<CString>,
+    pending_buildings: CVec<PrototypeID>,
+}
+
+impl GeoJSONExporter {
+    pub fn spawn(
+        id: GeoJSONExporterID,
+        recipient: GeoJSONRecipientID,
+        projection: &GeoProjection,
+        world: &mut World,
+    ) -> GeoJSONExporter {
+        PlanManagerID::global_first(world).export_master_result(id, world);
+
+        GeoJSONExporter {
+            id,
+            recipient,
+            projection: *projection,
+            features: CVec::new(),
+            pending_buildings: CVec::new(),
+        }
+    }
+
+    fn add_feature(&mut self, feature: &Value) {
+        let mut serialized = CString::new();
+        serialized.push_str(&feature.to_string());
+        self.features.push(serialized);
+    }
+
+    pub fn on_master_result(
+        &mut self,
+        gestures: &CVec<Gesture>,
+        result: &PlanResult,
+        world: &mut World,
+    ) -> Fate {
+        for gesture in gestures.iter() {
+            if let Some(feature) = road_feature(gesture, &self.projection) {
+                self.add_feature(&feature);
+            }
+        }
+
+        for prototype in result.prototypes.values() {
+            if let Some(feature) = prototype_feature(prototype, &self.projection) {
+                self.add_feature(&feature);
+            }
+
+            if let PrototypeKind::Lot(LotPrototype {
+                occupancy: LotOccupancy::Occupied(_),
+                ..
+            }) = prototype.kind
+            {
+                self.pending_buildings.push(prototype.id);
+            }
+        }
+
+        if self.pending_buildings.is_empty() {
+            self.finish(world)
+        } else {
+            ConstructionID::global_first(world).report_buildings_for_export(
+                self.pending_buildings.clone(),
+                self.id,
+                world,
+            );
+            Fate::Live
+        }
+    }
+
+    pub fn on_building_exported(
+        &mut self,
+        as_prototype: PrototypeID,
+        maybe_building: &COption<ExportedBuilding>,
+        world: &mut World,
+    ) -> Fate {
+        if let Some(ref building) = **maybe_building {
+            let feature = building_feature(building, &self.projection);
+            self.add_feature(&feature);
+        }
+
+        self.pending_buildings
+            .retain(|pending| *pending != as_prototype);
+
+        if self.pending_buildings.is_empty() {
+            self.finish(world)
+        } else {
+            Fate::Live
+        }
+    }
+
+    fn finish(&mut self, world: &mut World) -> Fate {
+        let mut geojson = CString::new();
+        geojson.push_str("{\"type\":\"FeatureCollection\",\"features\":[");
+        for (i, feature) in self.features.iter().enumerate() {
+            if i > 0 {
+                geojson.push_str(",");
+            }
+            geojson.push_str(feature);
+        }
+        geojson.push_str("]}");
+
+        self.recipient.on_geojson_exported(geojson, world);
+        Fate::Die
+    }
+}
+
+impl PlanManager {
+    pub fn export_master_result(&mut self, exporter: GeoJSONExporterID, world: &mut World) {
+        let gestures = self
+            .master_plan
+            .gestures
+            .values()
+            .filter(|VersionedGesture(gesture, _)| !gesture.deleted)
+            .map(|VersionedGesture(gesture, _)| gesture.clone())
+            .collect::<Vec<_>>();
+        exporter.on_master_result(gestures.into(), self.master_result.clone(), world);
+    }
+}
+
+#[derive(Compact, Clone)]
+pub struct GeoJSONFileWriter {
+    id: GeoJSONFileWriterID,
+    path: CString,
+}
+
+impl GeoJSONFileWriter {
+    pub fn spawn(
+        id: GeoJSONFileWriterID,
+        path: &CString,
+        projection: &GeoProjection,
+        world: &mut World,
+    ) -> GeoJSONFileWriter {
+        GeoJSONExporterID::spawn(id.into(), *projection, world);
+
+        GeoJSONFileWriter {
+            id,
+            path: path.clone(),
+        }
+    }
+}
+
+impl GeoJSONRecipient for GeoJSONFileWriter {
+    fn on_geojson_exported(&mut self, geojson: &CString, _: &mut World) -> Fate {
+        let written =
+            File::create(&*self.path).and_then(|mut file| file.write_all(geojson.as_bytes()));
+
+        match written {
+            Ok(()) => println!("Exported city to {}", &*self.path),
+            Err(err) => println!("Couldn't export city to {}: {}", &*self.path, err),
+        }
+
+        Fate::Die
+    }
+}
+
+pub fn export(world: &mut World, path: &str, projection: GeoProjection) {
+    let mut c_path = CString::new();
+    c_path.push_str(path);
+    GeoJSONFileWriterID::spawn(c_path, projection, world);
+}
+
+pub fn setup(system: &mut ActorSystem) {
+    system.register::<GeoJSONExporter>();
+    system.register::<GeoJSONFileWriter>();
+    auto_setup(system);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lanes(gesture: &Gesture) -> Option<(u8, u8)> {
+        match gesture.intent {
+            GestureIntent::Road(RoadIntent {
+                n_lanes_forward,
+                n_lanes_backward,
+            }) => Some((n_lanes_forward, n_lanes_backward)),
+            _ => None,
+        }
+    }
+
+    fn land_use_of(gesture: &Gesture) -> Option<LandUse> {
+        match gesture.intent {
+            GestureIntent::Zone(ZoneIntent::LandUse(land_use)) => Some(land_use),
+            _ => None,
+        }
+    }
+
+    fn feature(properties: Value, geometry: Value) -> Value {
+        json!({ "type": "Feature", "properties": properties, "geometry": geometry })
+    }
+
+    fn parse(features: Vec<Value>) -> Vec<Gesture> {
+        let geojson = json!({ "type": "FeatureCollection", "features": features });
+        gestures_from_geojson(&geojson, &GeoProjection::new(13.4, 52.5))
+            .into_iter()
+            .map(|(_, gesture)| gesture)
+            .collect()
+    }
+
+    #[test]
+    fn projection_round_trips() {
+        let projection = GeoProjection::new(13.4, 52.5);
+        assert_eq!(projection.to_p2(13.4, 52.5), P2::new(0.0, 0.0));
+
+        // a thousandth of a degree north is about 111m
+        let north = projection.to_p2(13.4, 52.501);
+        assert!(north.x.abs() < 0.01);
+        assert!((north.y - 111.2).abs() < 0.1);
+
+        // and degrees of longitude get shorter away from the equator
+        let east = projection.to_p2(13.401, 52.5);
+        assert!((east.x - 111.2 * 52.5f32.to_radians().cos()).abs() < 0.1);
+
+        let (longitude, latitude) = projection.to_longitude_latitude(P2::new(250.0, -120.0));
+        let back = projection.to_p2(longitude, latitude);
+        assert!((back - P2::new(250.0, -120.0)).norm() < 0.01);
+    }
+
+    #[test]
+    fn parses_projection_origins() {
+        let projection = GeoProjection::parse("13.4, 52.5").expect("Should parse");
+        assert_eq!(projection.origin_longitude, 13.4);
+        assert_eq!(projection.origin_latitude, 52.5);
+        assert!(GeoProjection::parse("13.4").is_err());
+        assert!(GeoProjection::parse("east,north").is_err());
+    }
+
+    #[test]
+    fn only_highways_become_roads() {
+        let line = json!({ "type": "LineString", "coordinates": [[13.4, 52.5], [13.401, 52.5]] });
+        let gestures = parse(vec![
+            feature(json!({ "highway": "residential" }), line.clone()),
+            feature(json!({ "waterway": "river" }), line.clone()),
+            feature(json!(null), line),
+        ]);
+
+        assert_eq!(gestures.len(), 1);
+        assert_eq!(lanes(&gestures[0]), Some((1, 1)));
+    }
+
+    #[test]
+    fn reads_lanes_and_oneways() {
+        let line = json!({ "type": "LineString", "coordinates": [[13.4, 52.5], [13.401, 52.5]] });
+        let gestures = parse(vec![
+            feature(json!({ "highway": "primary", "lanes": "3" }), line.clone()),
+            feature(
+                json!({ "highway": "primary", "oneway": "-1", "lanes": 2 }),
+                line.clone(),
+            ),
+            feature(
+                json!({ "highway": "road", "n_lanes_forward": 2, "n_lanes_backward": 0 }),
+                line,
+            ),
+        ]);
+
+        assert_eq!(lanes(&gestures[0]), Some((2, 1)));
+        assert_eq!(lanes(&gestures[1]), Some((2, 0)));
+        assert!(gestures[1].points[0].x > gestures[1].points[1].x);
+        assert_eq!(lanes(&gestures[2]), Some((2, 0)));
+    }
+
+    #[test]
+    fn polygons_with_land_use_become_zones() {
+        let square = json!({
+            "type": "Polygon",
+            "coordinates": [[[13.4, 52.5], [13.401, 52.5], [13.401, 52.501], [13.4, 52.501],
+                [13.4, 52.5]]]
+        });
+        let gestures = parse(vec![
+            feature(json!({ "landuse": "residential" }), square.clone()),
+            feature(json!({ "land_use": "Commercial" }), square.clone()),
+            feature(json!({ "building": "yes" }), square),
+        ]);
+
+        assert_eq!(gestures.len(), 2);
+        assert_eq!(land_use_of(&gestures[0]), Some(LandUse::Residential));
+        assert_eq!(land_use_of(&gestures[1]), Some(LandUse::Commercial));
+        // the closing point is implicit
+        assert_eq!(gestures[0].points.len(), 4);
+    }
+
+    #[test]
+    fn exported_roads_import_with_the_same_lanes() {
+        let projection = GeoProjection::new(13.4, 52.5);
+        let road = Gesture::new(
+            vec![P2::new(0.0, 0.0), P2::new(100.0, 20.0), P2::new(200.0, 0.0)].into(),
+            GestureIntent::Road(RoadIntent::new(3, 1)),
+        );
+        let exported = road_feature(&road, &projection).expect("Should export roads");
+
+        let imported = gestures_from_geojson(&exported, &projection);
+        assert_eq!(imported.len(), 1);
+        let (_, ref gesture) = imported[0];
+        assert_eq!(lanes(gesture), Some((3, 1)));
+        for (before, after) in road.points.iter().zip(gesture.points.iter()) {
+            assert!((*before - *after).norm() < 0.01);
+        }
+
+        let zone = Gesture::new(
+            road.points.clone(),
+            GestureIntent::Zone(ZoneIntent::LandUse(LandUse::Residential)),
+        );
+        assert!(road_feature(&zone, &projection).is_none());
+    }
+
+    #[test]
+    fn exported_lots_and_buildings_are_not_imported() {
+        let square = json!({
+            "type": "Polygon",
+            "coordinates": [[[13.4, 52.5], [13.401, 52.5], [13.401, 52.501], [13.4, 52.501],
+                [13.4, 52.5]]]
+        });
+        let gestures = parse(vec![
+            feature(
+                json!({ "kind": "lot", "land_use": "Residential", "land_uses": ["Residential"] }),
+                square.clone(),
+            ),
+            feature(json!({ "kind": "building", "land_uses": ["Commercial"] }), square),
+        ]);
+
+        assert!(gestures.is_empty());
+    }
+
+    #[test]
+    fn merges_points_closer_than_min_distance() {
+        let line = json!({
+            "type": "LineString",
+            "coordinates": [[13.4, 52.5], [13.400_001, 52.5], [13.401, 52.5]]
+        });
+        let gestures = parse(vec![feature(json!({ "highway": "service" }), line)]);
+        assert_eq!(gestures[0].points.len(), 2);
+    }
+}
+
+pub mod kay_auto;
+pub use self::kay_auto::*;
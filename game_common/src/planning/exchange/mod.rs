@@ -0,0 +1,250 @@
+use kay::{World, ActorSystem, Fate, MachineID, TypedID};
+use compact::{CVec, COption, CString};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use util::random::uuid_from_seed;
+
+use super::{Plan, Proposal, ProposalID, PlanManager, PlanManagerID, Gesture, GestureID};
+use super::ui::PlanningUIID;
+use journal::{JournalID, JournaledCommand};
+
+pub mod geojson;
+
+// Bump this whenever the layout of ProposalFile or anything it contains changes
+pub const PROPOSAL_FILE_VERSION: u32 = 1;
+
+#[derive(Compact, Clone, Debug, Serialize, Deserialize)]
+pub struct ExportedGesture {
+    pub id: GestureID,
+    pub gesture: Gesture,
+}
+
+// A proposal flattened into its resulting gestures, without undo history.
+// Gestures are sorted by id, so that files diff nicely in version control
+#[derive(Compact, Clone, Debug, Serialize, Deserialize)]
+pub struct ProposalFile {
+    pub version: u32,
+    pub gestures: CVec<ExportedGesture>,
+}
+
+impl ProposalFile {
+    pub fn from_proposal(proposal: &Proposal) -> ProposalFile {
+        let mut latest_gestures = HashMap::new();
+
+        for plan in proposal
+            .undoable_history
+            .iter()
+            .chain(Some(&proposal.ongoing))
+        {
+            for (gesture_id, gesture) in plan.gestures.pairs() {
+                latest_gestures.insert(*gesture_id, gesture.clone());
+            }
+        }
+
+        let mut gestures = latest_gestures
+            .into_iter()
+            .map(|(id, gesture)| ExportedGesture { id, gesture })
+            .collect::<Vec<_>>();
+        gestures.sort_by_key(|exported| (exported.id).0);
+
+        ProposalFile {
+            version: PROPOSAL_FILE_VERSION,
+            gestures: gestures.into(),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, String> {
+        ::serde_json::to_string_pretty(self).map_err(|err| err.to_string())
+    }
+
+    pub fn write_to(&self, path: &str) -> Result<(), String> {
+        let serialized = self.to_json()?;
+        let mut file = File::create(path).map_err(|err| err.to_string())?;
+        file.write_all(serialized.as_bytes())
+            .map_err(|err| err.to_string())
+    }
+
+    pub fn read_from(path: &str) -> Result<ProposalFile, String> {
+        let mut file = File::open(path).map_err(|err| err.to_string())?;
+        let mut serialized = String::new();
+        file.read_to_string(&mut serialized)
+            .map_err(|err| err.to_string())?;
+        ProposalFile::from_json(&serialized)
+    }
+
+    pub fn from_json(serialized: &str) -> Result<ProposalFile, String> {
+        let proposal_file: ProposalFile =
+            ::serde_json::from_str(serialized).map_err(|err| err.to_string())?;
+
+        if proposal_file.version == PROPOSAL_FILE_VERSION {
+            Ok(proposal_file)
+        } else {
+            Err(format!(
+                "Proposal file has version {}, but this version of Citybound reads version {}",
+                proposal_file.version, PROPOSAL_FILE_VERSION
+            ))
+        }
+    }
+}
+
+// Gets exported proposals, None if there is no such proposal
+pub trait ProposalFileRecipient {
+    fn on_proposal_exported(
+        &mut self,
+        proposal_id: ProposalID,
+        maybe_file: &COption<ProposalFile>,
+        world: &mut World,
+    ) -> Fate;
+}
+
+pub trait ProposalListRecipient {
+    fn on_proposal_list(
+        &mut self,
+        open: &CVec<ProposalID>,
+        implemented: &CVec<ProposalID>,
+        world: &mut World,
+    );
+}
+
+impl PlanManager {
+    // Proposals the viewer may not see are reported as not existing
+    pub fn export_proposal(
+        &mut self,
+        proposal_id: ProposalID,
+        viewer: &COption<MachineID>,
+        recipient: ProposalFileRecipientID,
+        world: &mut World,
+    ) {
+        let maybe_file = if self.is_visible_to(**viewer, proposal_id) {
+            self.proposals
+                .get(proposal_id)
+                .or_else(|| self.implemented_proposals.get(proposal_id))
+                .map(ProposalFile::from_proposal)
+        } else {
+            None
+        };
+
+        recipient.on_proposal_exported(proposal_id, COption(maybe_file), world);
+    }
+
+    // So that proposals can be referred to for exporting
+    pub fn list_proposals(
+        &mut self,
+        viewer: &COption<MachineID>,
+        recipient: ProposalListRecipientID,
+        world: &mut World,
+    ) {
+        let visible = |proposal_ids: Vec<ProposalID>| {
+            proposal_ids
+                .into_iter()
+                .filter(|proposal_id| self.is_visible_to(**viewer, *proposal_id))
+                .collect::<Vec<_>>()
+        };
+
+        recipient.on_proposal_list(
+            visible(self.proposals.keys().cloned().collect()).into(),
+            visible(self.implemented_proposals.keys().cloned().collect()).into(),
+            world,
+        );
+    }
+
+    // Gestures that edit gestures of our master plan keep their id,
+    // all others get a new one derived from the new proposal id,
+    // so importing the same file twice doesn't lead to collisions
+    // The new proposal belongs to the machine of the importing UI
+    pub fn import_proposal(
+        &mut self,
+        proposal_id: ProposalID,
+        ui: PlanningUIID,
+        file: &ProposalFile,
+        world: &mut World,
+    ) {
+        JournalID::local_first(world).record(
+            JournaledCommand::ImportProposal(proposal_id, ui, file.clone()),
+            world,
+        );
+
+        let plan = {
+            let master_gestures = &self.master_plan.gestures;
+            Plan::from_gestures(file.gestures.iter().map(|exported| {
+                let id = if master_gestures.contains_key(exported.id) {
+                    exported.id
+                } else {
+                    GestureID(uuid_from_seed((proposal_id, exported.id)))
+                };
+                (id, exported.gesture.clone())
+            }))
+        };
+
+        let owner = ui.as_raw().machine;
+        self.add_proposal(proposal_id, Proposal::from_plan(plan, owner));
+    }
+}
+
+#[derive(Compact, Clone)]
+pub struct ProposalFileWriter {
+    id: ProposalFileWriterID,
+    path: CString,
+}
+
+impl ProposalFileWriter {
+    pub fn spawn(
+        id: ProposalFileWriterID,
+        path: &CString,
+        plan_manager: PlanManagerID,
+        proposal_id: ProposalID,
+        world: &mut World,
+    ) -> ProposalFileWriter {
+        // the server may export everything it can see itself
+        let server = COption(Some(world.local_machine_id()));
+        plan_manager.export_proposal(proposal_id, server, id.into(), world);
+
+        ProposalFileWriter {
+            id,
+            path: path.clone(),
+        }
+    }
+}
+
+impl ProposalFileRecipient for ProposalFileWriter {
+    fn on_proposal_exported(
+        &mut self,
+        _proposal_id: ProposalID,
+        maybe_file: &COption<ProposalFile>,
+        _: &mut World,
+    ) -> Fate {
+        match **maybe_file {
+            Some(ref file) => match file.write_to(&self.path) {
+                Ok(()) => println!("Exported proposal to {}", &*self.path),
+                Err(err) => println!("Couldn't export proposal to {}: {}", &*self.path, err),
+            },
+            None => println!("Couldn't export proposal to {}: no such proposal", &*self.path),
+        }
+
+        Fate::Die
+    }
+}
+
+pub fn export(world: &mut World, proposal_id: ProposalID, path: &str) {
+    let mut c_path = CString::new();
+    c_path.push_str(path);
+    ProposalFileWriterID::spawn(c_path, PlanManagerID::global_first(world), proposal_id, world);
+}
+
+// Imports on behalf of the given UI, whose machine will own the proposal
+pub fn import(world: &mut World, path: &str, ui: PlanningUIID) -> Result<ProposalID, String> {
+    let file = ProposalFile::read_from(path)?;
+    let proposal_id = ProposalID::new();
+    PlanManagerID::global_first(world).import_proposal(proposal_id, ui, file, world);
+    Ok(proposal_id)
+}
+
+pub fn setup(system: &mut ActorSystem) {
+    system.register::<ProposalFileWriter>();
+    auto_setup(system);
+    geojson::setup(system);
+}
+
+pub mod kay_auto;
+pub use self::kay_auto::*;
@@ -0,0 +1,490 @@
+use kay::{World, TypedID};
+use compact::CVec;
+use descartes::{N, P2, V2};
+use util::random::{seed, Rng, FnvRng, uuid_from_seed};
+use dimensions::LANE_DISTANCE;
+use transport::transport_planning::RoadIntent;
+use land_use::zone_planning::{ZoneIntent, LandUse};
+use journal::{JournalID, JournaledCommand};
+
+use super::{Plan, Proposal, ProposalID, PlanManager, Gesture, GestureID, GestureIntent,
+StepID, PlanError};
+use super::permissions::PlanningAction;
+use super::ui::PlanningUIID;
+
+// leave some room between roads and the zoned blocks next to them
+const BLOCK_MARGIN: N = 2.0;
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum StreetLayout {
+    // n_blocks x n_blocks square blocks
+    Grid { n_blocks: u32, block_size: N },
+    // rings around the center, connected by spokes
+    Radial {
+        n_rings: u32,
+        n_spokes: u32,
+        ring_spacing: N,
+    },
+    // like a grid, but intersections are moved randomly by up to
+    // irregularity * block_size, which makes the streets curve
+    Organic {
+        n_blocks: u32,
+        block_size: N,
+        irregularity: N,
+    },
+}
+
+impl StreetLayout {
+    // A medium-sized town of the given layout
+    pub fn parse(name: &str) -> Result<StreetLayout, String> {
+        match name {
+            "grid" => Ok(StreetLayout::Grid {
+                n_blocks: 6,
+                block_size: 100.0,
+            }),
+            "radial" => Ok(StreetLayout::Radial {
+                n_rings: 4,
+                n_spokes: 8,
+                ring_spacing: 80.0,
+            }),
+            "organic" => Ok(StreetLayout::Organic {
+                n_blocks: 6,
+                block_size: 100.0,
+                irregularity: 0.2,
+            }),
+            _ => Err(format!(
+                "Unknown street layout {}, expected grid, radial or organic",
+                name
+            )),
+        }
+    }
+}
+
+#[derive(Compact, Clone, Debug, Serialize, Deserialize)]
+pub struct GeneratorSettings {
+    pub layout: StreetLayout,
+    pub center: P2,
+    pub road_intent: RoadIntent,
+    // each block gets one of these at random, blocks stay unzoned if this is empty
+    pub land_uses: CVec<LandUse>,
+    pub seed: u64,
+}
+
+impl GeneratorSettings {
+    pub fn new(layout: StreetLayout, seed: u64) -> GeneratorSettings {
+        GeneratorSettings {
+            layout,
+            center: P2::new(0.0, 0.0),
+            road_intent: RoadIntent::new(2, 2),
+            land_uses: vec![
+                LandUse::Residential,
+                LandUse::Residential,
+                LandUse::Commercial,
+                LandUse::Industrial,
+            ].into(),
+            seed,
+        }
+    }
+
+    // Catches settings that would divide by zero or produce degenerate geometry
+    pub fn validate(&self) -> Result<(), String> {
+        let positive = |value: N, what: &str| {
+            if value.is_finite() && value > 0.0 {
+                Ok(())
+            } else {
+                Err(format!("{} has to be positive, but is {}", what, value))
+            }
+        };
+        let at_least = |value: u32, minimum: u32, what: &str| {
+            if value >= minimum {
+                Ok(())
+            } else {
+                Err(format!("Need at least {} {}, but got {}", minimum, what, value))
+            }
+        };
+
+        match self.layout {
+            StreetLayout::Grid {
+                n_blocks,
+                block_size,
+            } => {
+                at_least(n_blocks, 1, "blocks")?;
+                positive(block_size, "Block size")?;
+            }
+            StreetLayout::Radial {
+                n_rings,
+                n_spokes,
+                ring_spacing,
+            } => {
+                at_least(n_rings, 1, "rings")?;
+                // fewer spokes don't enclose any blocks
+                at_least(n_spokes, 3, "spokes")?;
+                positive(ring_spacing, "Ring spacing")?;
+            }
+            StreetLayout::Organic {
+                n_blocks,
+                block_size,
+                irregularity,
+            } => {
+                at_least(n_blocks, 1, "blocks")?;
+                positive(block_size, "Block size")?;
+                // more would let neighbouring intersections swap places
+                if irregularity.is_nan() || irregularity < 0.0 || irregularity >= 0.5 {
+                    return Err(format!(
+                        "Irregularity has to be at least 0 and below 0.5, but is {}",
+                        irregularity
+                    ));
+                }
+            }
+        }
+
+        if !(self.center.x.is_finite() && self.center.y.is_finite()) {
+            return Err(format!("Center {:?} isn't a valid position", self.center));
+        }
+
+        if self.road_intent.n_lanes_forward == 0 && self.road_intent.n_lanes_backward == 0 {
+            return Err("Roads need at least one lane".to_owned());
+        }
+
+        Ok(())
+    }
+}
+
+struct Generator<'a> {
+    settings: &'a GeneratorSettings,
+    step_id: StepID,
+    rng: FnvRng,
+    gestures: Vec<(GestureID, Gesture)>,
+}
+
+impl<'a> Generator<'a> {
+    fn add(&mut self, points: Vec<P2>, intent: GestureIntent) {
+        // ids are unique to the step, but replaying the same step gives the same ids
+        let id = GestureID(uuid_from_seed((self.step_id, self.gestures.len())));
+        self.gestures.push((id, Gesture::new(points.into(), intent)));
+    }
+
+    fn road(&mut self, points: Vec<P2>) {
+        let intent = GestureIntent::Road(self.settings.road_intent);
+        self.add(points, intent);
+    }
+
+    // Shrinks the block towards its center to keep it clear of the surrounding roads
+    fn block(&mut self, corners: Vec<P2>) {
+        if self.settings.land_uses.is_empty() {
+            return;
+        }
+
+        let n_lanes = self
+            .settings
+            .road_intent
+            .n_lanes_forward
+            .max(self.settings.road_intent.n_lanes_backward);
+        let inset = (f32::from(n_lanes) + 0.5) * LANE_DISTANCE + BLOCK_MARGIN;
+
+        let center = P2::from_coordinates(
+            corners
+                .iter()
+                .fold(V2::new(0.0, 0.0), |sum, corner| sum + corner.coords)
+                / corners.len() as N,
+        );
+
+        let inset_corners = corners
+            .iter()
+            .map(|corner| {
+                let to_center = center - *corner;
+                if to_center.norm() > 2.0 * inset {
+                    *corner + to_center.normalize() * inset * ::std::f32::consts::SQRT_2
+                } else {
+                    *corner
+                }
+            }).collect();
+
+        let land_use =
+            self.settings.land_uses[self.rng.gen_range(0, self.settings.land_uses.len())];
+        self.add(
+            inset_corners,
+            GestureIntent::Zone(ZoneIntent::LandUse(land_use)),
+        );
+    }
+
+    fn lattice(&mut self, n_blocks: u32, block_size: N, irregularity: N) {
+        let n_nodes = n_blocks as usize + 1;
+        let extent = n_blocks as N * block_size;
+        let origin = self.settings.center - V2::new(extent / 2.0, extent / 2.0);
+
+        let mut nodes = Vec::with_capacity(n_nodes * n_nodes);
+        for y in 0..n_nodes {
+            for x in 0..n_nodes {
+                let jitter = if irregularity > 0.0 {
+                    let max_offset = irregularity * block_size;
+                    V2::new(
+                        self.rng.gen_range(-max_offset, max_offset),
+                        self.rng.gen_range(-max_offset, max_offset),
+                    )
+                } else {
+                    V2::new(0.0, 0.0)
+                };
+                nodes.push(
+                    origin + V2::new(x as N * block_size, y as N * block_size) + jitter,
+                );
+            }
+        }
+
+        let node = |x: usize, y: usize| nodes[y * n_nodes + x];
+
+        for y in 0..n_nodes {
+            self.road((0..n_nodes).map(|x| node(x, y)).collect());
+        }
+
+        for x in 0..n_nodes {
+            self.road((0..n_nodes).map(|y| node(x, y)).collect());
+        }
+
+        for y in 0..(n_nodes - 1) {
+            for x in 0..(n_nodes - 1) {
+                self.block(vec![
+                    node(x, y),
+                    node(x + 1, y),
+                    node(x + 1, y + 1),
+                    node(x, y + 1),
+                ]);
+            }
+        }
+    }
+
+    fn radial(&mut self, n_rings: u32, n_spokes: u32, ring_spacing: N) {
+        let center = self.settings.center;
+        let angle_step = 2.0 * ::std::f32::consts::PI / n_spokes as N;
+        let on_ring = |ring: u32, angle: N| {
+            center + V2::new(angle.cos(), angle.sin()) * ring as N * ring_spacing
+        };
+        // a point between each pair of spokes, so that ring roads come out round
+        let arc = |ring: u32, spoke: u32| {
+            let start_angle = spoke as N * angle_step;
+            vec![
+                on_ring(ring, start_angle),
+                on_ring(ring, start_angle + angle_step / 2.0),
+                on_ring(ring, start_angle + angle_step),
+            ]
+        };
+
+        for spoke in 0..n_spokes {
+            let angle = spoke as N * angle_step;
+            self.road((1..=n_rings).map(|ring| on_ring(ring, angle)).collect());
+        }
+
+        for ring in 1..=n_rings {
+            for spoke in 0..n_spokes {
+                self.road(arc(ring, spoke));
+            }
+        }
+
+        // the square in the middle
+        let inner_ring = (0..n_spokes)
+            .flat_map(|spoke| arc(1, spoke).into_iter().take(2))
+            .collect();
+        self.block(inner_ring);
+
+        for ring in 1..n_rings {
+            for spoke in 0..n_spokes {
+                let mut corners = arc(ring, spoke);
+                corners.extend(arc(ring + 1, spoke).into_iter().rev());
+                self.block(corners);
+            }
+        }
+    }
+}
+
+// Generates a whole street network with zoned blocks as the given step.
+// The layout only depends on the settings, which makes it useful for test cities and benchmarks
+pub fn generate(settings: &GeneratorSettings, step_id: StepID) -> Result<Plan, String> {
+    settings.validate()?;
+
+    let mut generator = Generator {
+        settings,
+        step_id,
+        rng: seed(settings.seed),
+        gestures: Vec::new(),
+    };
+
+    match settings.layout {
+        StreetLayout::Grid {
+            n_blocks,
+            block_size,
+        } => generator.lattice(n_blocks, block_size, 0.0),
+        StreetLayout::Radial {
+            n_rings,
+            n_spokes,
+            ring_spacing,
+        } => generator.radial(n_rings, n_spokes, ring_spacing),
+        StreetLayout::Organic {
+            n_blocks,
+            block_size,
+            irregularity,
+        } => generator.lattice(n_blocks, block_size, irregularity),
+    }
+
+    Ok(Plan {
+        step_id,
+        gestures: generator.gestures.into_iter().collect(),
+    })
+}
+
+impl PlanManager {
+    // Adds the generated plan as a new step of the proposal, before any ongoing gesture,
+    // or creates the proposal for the machine of the UI if it doesn't exist yet.
+    // Callers pass a new step id
+    pub fn add_generated_plan(
+        &mut self,
+        proposal_id: ProposalID,
+        ui: PlanningUIID,
+        settings: &GeneratorSettings,
+        step_id: StepID,
+        world: &mut World,
+    ) {
+        let machine = ui.as_raw().machine;
+        let exists = self.proposals.contains_key(proposal_id)
+            || self.implemented_proposals.contains_key(proposal_id);
+
+        if exists && !self.check_allowed(machine, proposal_id, PlanningAction::Edit, world) {
+            return;
+        }
+
+        let plan = match generate(settings, step_id) {
+            Ok(plan) => plan,
+            Err(description) => {
+                PlanningUIID::global_broadcast(world).on_plan_error(
+                    proposal_id,
+                    PlanError::new(&description),
+                    world,
+                );
+                return;
+            }
+        };
+
+        JournalID::local_first(world).record(
+            JournaledCommand::AddGeneratedPlan(proposal_id, ui, settings.clone(), step_id),
+            world,
+        );
+
+        if self.proposals.contains_key(proposal_id) {
+            self.proposals
+                .get_mut(proposal_id)
+                .expect("just checked that it exists")
+                .push_step(plan);
+            self.clear_previews(proposal_id);
+        } else {
+            self.add_proposal(proposal_id, Proposal::from_plan(plan, machine));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // without their ids, sorted so that they can be compared
+    fn generated_gestures(settings: &GeneratorSettings, step_id: StepID) -> Vec<String> {
+        let mut gestures = generate(settings, step_id)
+            .unwrap()
+            .gestures
+            .values()
+            .map(|gesture| format!("{:?}", gesture))
+            .collect::<Vec<_>>();
+        gestures.sort();
+        gestures
+    }
+
+    fn organic(seed: u64) -> GeneratorSettings {
+        GeneratorSettings::new(StreetLayout::parse("organic").unwrap(), seed)
+    }
+
+    #[test]
+    fn same_settings_and_step_generate_the_same_plan() {
+        for layout in &["grid", "radial", "organic"] {
+            let settings = GeneratorSettings::new(StreetLayout::parse(layout).unwrap(), 42);
+            let step_id = StepID::new();
+            let first = generate(&settings, step_id).unwrap();
+            let second = generate(&settings, step_id).unwrap();
+
+            assert_eq!(first.step_id, second.step_id);
+            assert!(!first.gestures.is_empty());
+            assert_eq!(first.gestures.len(), second.gestures.len());
+            for (id, gesture) in first.gestures.pairs() {
+                let same = second
+                    .gestures
+                    .get(*id)
+                    .expect("Should generate the same gesture ids");
+                assert_eq!(format!("{:?}", gesture), format!("{:?}", same));
+            }
+        }
+    }
+
+    #[test]
+    fn new_steps_get_new_gesture_ids() {
+        let settings = organic(42);
+        let first = generate(&settings, StepID::new()).unwrap();
+        let second = generate(&settings, StepID::new()).unwrap();
+
+        assert!(first.gestures.keys().all(|id| !second.gestures.contains_key(*id)));
+    }
+
+    #[test]
+    fn layout_only_depends_on_the_seed() {
+        let step_id = StepID::new();
+
+        assert_eq!(
+            generated_gestures(&organic(1), step_id),
+            generated_gestures(&organic(1), StepID::new())
+        );
+        assert_ne!(
+            generated_gestures(&organic(1), step_id),
+            generated_gestures(&organic(2), step_id)
+        );
+    }
+
+    #[test]
+    fn degenerate_settings_are_rejected() {
+        let radial = |n_rings, n_spokes, ring_spacing| {
+            GeneratorSettings::new(
+                StreetLayout::Radial {
+                    n_rings,
+                    n_spokes,
+                    ring_spacing,
+                },
+                42,
+            )
+        };
+
+        assert!(generate(&radial(4, 0, 80.0), StepID::new()).is_err());
+        assert!(generate(&radial(0, 8, 80.0), StepID::new()).is_err());
+        assert!(generate(&radial(4, 8, 0.0), StepID::new()).is_err());
+        assert!(generate(&radial(4, 8, -80.0), StepID::new()).is_err());
+        assert!(generate(&radial(4, 8, ::std::f32::NAN), StepID::new()).is_err());
+
+        let grid = GeneratorSettings::new(
+            StreetLayout::Grid {
+                n_blocks: 0,
+                block_size: 100.0,
+            },
+            42,
+        );
+        assert!(generate(&grid, StepID::new()).is_err());
+
+        let organic = GeneratorSettings::new(
+            StreetLayout::Organic {
+                n_blocks: 6,
+                block_size: 100.0,
+                irregularity: 0.7,
+            },
+            42,
+        );
+        assert!(generate(&organic, StepID::new()).is_err());
+
+        assert!(generate(&radial(4, 8, 80.0), StepID::new()).is_ok());
+    }
+}
+
+pub mod kay_auto;
+pub use self::kay_auto::*;
@@ -1,12 +1,16 @@
-use kay::{World, MachineID,   ActorSystem};
-use compact::{CHashMap, COption};
-use descartes::{P2, AreaError};
-use super::{Plan, PlanHistory, PlanResult,  GestureID, ProposalID,
+use kay::{World, MachineID,   ActorSystem, TypedID};
+use compact::{CHashMap, CVec, COption};
+use descartes::P2;
+use super::{Plan, PlanHistory, PlanResult, PlanError, GestureID, ProposalID, PrototypeID,
 PlanManager, PlanManagerID, Gesture, GestureIntent,
 KnownHistoryState, KnownProposalState, ProposalUpdate,
 KnownPlanResultState,
 ActionGroups};
 use super::ui::PlanningUIID;
+use super::estimate::ProposalEstimate;
+use super::permissions::PlanningAction;
+use construction::ConstructionID;
+use journal::{JournalID, JournaledCommand};
 
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub struct ControlPointRef(pub GestureID, pub usize);
@@ -18,6 +22,47 @@ pub struct PlanManagerUIState {
     current_preview: COption<PlanHistory>,
     current_result_preview: COption<PlanResult>,
     current_action_preview: COption<ActionGroups>,
+    current_estimate_preview: COption<ProposalEstimate>,
+    displaced_households_requested: bool,
+    current_preview_error: COption<PlanError>,
+}
+
+impl PlanManagerUIState {
+    // Returns the destroyed buildings whose households still need to be counted,
+    // but only once per estimate
+    fn start_counting_displaced_households(&mut self) -> Option<CVec<PrototypeID>> {
+        if self.displaced_households_requested {
+            return None;
+        }
+
+        if let Some(ref estimate) = *self.current_estimate_preview {
+            if !estimate.is_complete() {
+                self.displaced_households_requested = true;
+                return Some(estimate.buildings_pending_count.clone());
+            }
+        }
+
+        None
+    }
+
+    pub fn count_displaced_households(
+        &mut self,
+        building_prototype_id: PrototypeID,
+        n_households: u32,
+    ) {
+        if let Some(ref mut estimate) = *self.current_estimate_preview {
+            let was_pending = estimate
+                .buildings_pending_count
+                .contains(&building_prototype_id);
+
+            if was_pending {
+                estimate
+                    .buildings_pending_count
+                    .retain(|pending| *pending != building_prototype_id);
+                estimate.displaced_households += n_households;
+            }
+        }
+    }
 }
 
 impl PlanManager {
@@ -28,15 +73,20 @@ impl PlanManager {
         known_proposals: &CHashMap<ProposalID, KnownProposalState>,
         world: &mut World,
     ) {
+        let machine = ui.as_raw().machine;
+        self.mark_participating(machine);
         let master_update = self.master_plan.update_for(known_master);
         let mut unmatched_known_proposals = known_proposals
             .keys()
             .cloned()
             .collect::<::std::collections::HashSet<_>>();
+        // proposals this machine may not see are reported as removed
         let proposal_updates = self
             .proposals
             .pairs()
-            .map(|(proposal_id, proposal)| {
+            .filter(|&(proposal_id, _)| {
+                self.is_allowed(machine, *proposal_id, PlanningAction::View)
+            }).map(|(proposal_id, proposal)| {
                 (
                     *proposal_id,
                     known_proposals
@@ -64,25 +114,52 @@ impl PlanManager {
         known_result: &KnownPlanResultState,
         world: &mut World,
     ) {
-        // TODO: this is a super ugly hack until we get rid of the native UI
-        let needs_switch = if let Some(ui_state) = self.ui_state.get_mut(world.local_machine_id()) {
+        // previews are per machine, so open the proposal for whoever is asking
+        let machine = ui.as_raw().machine;
+
+        if !self.check_allowed(machine, proposal_id, PlanningAction::View, world) {
+            return;
+        }
+
+        let needs_switch = if let Some(ui_state) = self.ui_state.get(machine) {
             ui_state.current_proposal != proposal_id
         } else {
-            false
+            true
         };
 
         if needs_switch {
-            self.switch_to(MachineID(0), proposal_id, world);
+            self.switch_to(machine, proposal_id, world);
         }
 
-        let (_, maybe_result, maybe_actions) =
-            self.try_ensure_preview(world.local_machine_id(), proposal_id);
+        {
+            let (_, maybe_result, maybe_actions, maybe_estimate, maybe_error) =
+                self.try_ensure_preview(machine, proposal_id);
+
+            if let (Some(result), Some(actions), Some(estimate)) =
+                (maybe_result, maybe_actions, maybe_estimate)
+            {
+                ui.on_proposal_preview_update(
+                    proposal_id,
+                    result.update_for(known_result),
+                    actions.clone(),
+                    estimate.clone(),
+                    world,
+                );
+            } else if let Some(error) = maybe_error {
+                ui.on_plan_error(proposal_id, error.clone(), world);
+            }
+        }
 
-        if let (Some(result), Some(actions)) = (maybe_result, maybe_actions) {
-            ui.on_proposal_preview_update(
-                proposal_id,
-                result.update_for(known_result),
-                actions.clone(),
+        let maybe_buildings_to_count = self
+            .ui_state
+            .get_mut(machine)
+            .and_then(|ui_state| ui_state.start_counting_displaced_households());
+
+        if let Some(buildings_to_count) = maybe_buildings_to_count {
+            ConstructionID::global_first(world).report_displaced_households(
+                buildings_to_count,
+                self.id,
+                machine,
                 world,
             );
         }
@@ -99,6 +176,9 @@ impl PlanManager {
                 current_preview: COption(None),
                 current_result_preview: COption(None),
                 current_action_preview: COption(None),
+                current_estimate_preview: COption(None),
+                displaced_households_requested: false,
+                current_preview_error: COption(None),
             },
         );
     }
@@ -112,6 +192,9 @@ impl PlanManager {
             state.current_preview = COption(None);
             state.current_result_preview = COption(None);
             state.current_action_preview = COption(None);
+            state.current_estimate_preview = COption(None);
+            state.displaced_households_requested = false;
+            state.current_preview_error = COption(None);
         }
     }
 
@@ -120,7 +203,13 @@ impl PlanManager {
         &self,
         machine_id: MachineID,
         proposal_id: ProposalID,
-    ) -> (&PlanHistory, Option<&PlanResult>, &Option<ActionGroups>) {
+    ) -> (
+        &PlanHistory,
+        Option<&PlanResult>,
+        &Option<ActionGroups>,
+        Option<&ProposalEstimate>,
+        Option<&PlanError>,
+    ) {
         let ui_state = self
             .ui_state
             .get(machine_id)
@@ -144,17 +233,23 @@ impl PlanManager {
                 match preview_plan.calculate_result() {
                     Ok(preview_plan_result) => {
                         let (actions, _) = self.master_result.actions_to(&preview_plan_result);
+                        let estimate = ProposalEstimate::from_actions(
+                            &actions,
+                            &self.master_result,
+                            &preview_plan_result,
+                        );
                         ui_state_mut.current_result_preview = COption(Some(preview_plan_result));
                         ui_state_mut.current_action_preview = COption(Some(actions));
+                        ui_state_mut.current_estimate_preview = COption(Some(estimate));
+                        ui_state_mut.displaced_households_requested = false;
+                        ui_state_mut.current_preview_error = COption(None);
+                    }
+                    Err(error) => {
+                        ui_state_mut.current_result_preview = COption(None);
+                        ui_state_mut.current_action_preview = COption(None);
+                        ui_state_mut.current_estimate_preview = COption(None);
+                        ui_state_mut.current_preview_error = COption(Some(error));
                     }
-                    Err(err) => match err {
-                        AreaError::LeftOver(string) => {
-                            println!("Preview Plan Error: {}", string);
-                        }
-                        _ => {
-                            println!("Preview Plan Error: {:?}", err);
-                        }
-                    },
                 }
 
                 ui_state_mut.current_preview = COption(Some(preview_plan));
@@ -165,18 +260,37 @@ impl PlanManager {
             ui_state.current_preview.as_ref().unwrap(),
             ui_state.current_result_preview.as_ref(),
             &*ui_state.current_action_preview,
+            ui_state.current_estimate_preview.as_ref(),
+            ui_state.current_preview_error.as_ref(),
         )
     }
 
     pub fn start_new_gesture(
         &mut self,
         proposal_id: ProposalID,
-        machine_id: MachineID,
+        ui: PlanningUIID,
         new_gesture_id: GestureID,
         intent: &GestureIntent,
         start: P2,
-        _: &mut World,
+        world: &mut World,
     ) {
+        let machine = ui.as_raw().machine;
+
+        if !self.check_allowed(machine, proposal_id, PlanningAction::Edit, world) {
+            return;
+        }
+
+        JournalID::local_first(world).record(
+            JournaledCommand::StartNewGesture(
+                proposal_id,
+                ui,
+                new_gesture_id,
+                intent.clone(),
+                start,
+            ),
+            world,
+        );
+
         let new_gesture = Gesture::new(vec![start].into(), intent.clone());
 
         let new_step = Plan::from_gestures(Some((new_gesture_id, new_gesture)));
@@ -191,16 +305,18 @@ impl PlanManager {
             .start_new_step();
 
         self.ui_state
-            .get_mut(machine_id)
+            .get_mut(machine)
             .expect("should already have ui state")
             .gesture_ongoing = true;
 
         self.clear_previews(proposal_id);
     }
 
-    pub fn finish_gesture(&mut self, machine_id: MachineID, _: &mut World) {
+    pub fn finish_gesture(&mut self, ui: PlanningUIID, world: &mut World) {
+        JournalID::local_first(world).record(JournaledCommand::FinishGesture(ui), world);
+
         self.ui_state
-            .get_mut(machine_id)
+            .get_mut(ui.as_raw().machine)
             .expect("should already have ui state")
             .gesture_ongoing = false;
     }
@@ -208,12 +324,29 @@ impl PlanManager {
     pub fn add_control_point(
         &mut self,
         proposal_id: ProposalID,
+        ui: PlanningUIID,
         gesture_id: GestureID,
         new_point: P2,
         add_to_end: bool,
         commit: bool,
-        _: &mut World,
+        world: &mut World,
     ) {
+        if !self.check_allowed(ui.as_raw().machine, proposal_id, PlanningAction::Edit, world) {
+            return;
+        }
+
+        JournalID::local_first(world).record(
+            JournaledCommand::AddControlPoint(
+                proposal_id,
+                ui,
+                gesture_id,
+                new_point,
+                add_to_end,
+                commit,
+            ),
+            world,
+        );
+
         let new_step = {
             let current_gesture = self.get_current_version_of(gesture_id, proposal_id);
 
@@ -258,12 +391,29 @@ impl PlanManager {
     pub fn move_control_point(
         &mut self,
         proposal_id: ProposalID,
+        ui: PlanningUIID,
         gesture_id: GestureID,
         point_index: u32,
         new_position: P2,
         is_move_finished: bool,
-        _: &mut World,
+        world: &mut World,
     ) {
+        if !self.check_allowed(ui.as_raw().machine, proposal_id, PlanningAction::Edit, world) {
+            return;
+        }
+
+        JournalID::local_first(world).record(
+            JournaledCommand::MoveControlPoint(
+                proposal_id,
+                ui,
+                gesture_id,
+                point_index,
+                new_position,
+                is_move_finished,
+            ),
+            world,
+        );
+
         let current_change = {
             let current_gesture = self.get_current_version_of(gesture_id, proposal_id);
 
@@ -302,11 +452,27 @@ impl PlanManager {
     pub fn set_intent(
         &mut self,
         proposal_id: ProposalID,
+        ui: PlanningUIID,
         gesture_id: GestureID,
         new_intent: &GestureIntent,
         is_move_finished: bool,
-        _: &mut World,
+        world: &mut World,
     ) {
+        if !self.check_allowed(ui.as_raw().machine, proposal_id, PlanningAction::Edit, world) {
+            return;
+        }
+
+        JournalID::local_first(world).record(
+            JournaledCommand::SetIntent(
+                proposal_id,
+                ui,
+                gesture_id,
+                new_intent.clone(),
+                is_move_finished,
+            ),
+            world,
+        );
+
         let current_change = {
             let current_gesture = self.get_current_version_of(gesture_id, proposal_id);
 
@@ -335,12 +501,22 @@ impl PlanManager {
         }
     }
 
-    pub fn undo(&mut self, proposal_id: ProposalID, _: &mut World) {
+    pub fn undo(&mut self, proposal_id: ProposalID, ui: PlanningUIID, world: &mut World) {
+        if !self.check_allowed(ui.as_raw().machine, proposal_id, PlanningAction::Edit, world) {
+            return;
+        }
+
+        JournalID::local_first(world).record(JournaledCommand::Undo(proposal_id, ui), world);
         self.proposals.get_mut(proposal_id).unwrap().undo();
         self.clear_previews(proposal_id);
     }
 
-    pub fn redo(&mut self, proposal_id: ProposalID, _: &mut World) {
+    pub fn redo(&mut self, proposal_id: ProposalID, ui: PlanningUIID, world: &mut World) {
+        if !self.check_allowed(ui.as_raw().machine, proposal_id, PlanningAction::Edit, world) {
+            return;
+        }
+
+        JournalID::local_first(world).record(JournaledCommand::Redo(proposal_id, ui), world);
         self.proposals.get_mut(proposal_id).unwrap().redo();
         self.clear_previews(proposal_id);
     }
@@ -1,17 +1,36 @@
 #![cfg_attr(feature = "cargo-clippy", allow(new_without_default_derive))]
 #![cfg_attr(feature = "cargo-clippy", allow(new_without_default))]
 use kay::{World, MachineID, ActorSystem, TypedID};
-use compact::{CVec, COption, CHashMap};
+use compact::{CVec, COption, CHashMap, CString};
 use descartes::{P2, AreaError};
-use util::random::{seed, RngCore, Uuid, uuid};
+use util::random::{seed, RngCore, Uuid, uuid, uuid_from_seed};
 use std::hash::Hash;
+use std::collections::HashSet;
+use std::time::Instant as WallInstant;
 
 use transport::transport_planning::{RoadIntent, RoadPrototype};
 use land_use::zone_planning::{ZoneIntent, BuildingIntent, LotPrototype};
 use construction::ConstructionID;
+use persistence::{SaveGameWriterID, PlanningState};
+use journal::{JournalID, JournaledCommand};
+use self::ui::PlanningUIID;
+use self::permissions::{PlanningPolicy, PlanningAction};
+use self::voting::{ProposalStatus, Ballot};
+use self::checkpoints::{Checkpoint, PlanDiff};
+use self::cancellation::UnbuiltImplementation;
 
 pub mod interaction;
+pub mod exchange;
+pub mod rebase;
+pub mod estimate;
+pub mod generation;
+pub mod permissions;
+pub mod voting;
+pub mod checkpoints;
+pub mod cancellation;
 pub mod ui;
+#[cfg(test)]
+mod test_util;
 
 // idea for improvement:
 // - everything (Gestures, Prototypes) immutable (helps caching)
@@ -63,6 +82,12 @@ impl GestureID {
 #[derive(Copy, Clone, Hash, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub struct StepID(pub Uuid);
 
+impl StepID {
+    pub fn new() -> StepID {
+        StepID(uuid())
+    }
+}
+
 #[derive(Compact, Clone, Debug, Serialize, Deserialize)]
 pub struct Plan {
     pub step_id: StepID,
@@ -72,14 +97,14 @@ pub struct Plan {
 impl Plan {
     pub fn new() -> Plan {
         Plan {
-            step_id: StepID(uuid()),
+            step_id: StepID::new(),
             gestures: CHashMap::new(),
         }
     }
 
     pub fn from_gestures<I: IntoIterator<Item = (GestureID, Gesture)>>(gestures: I) -> Plan {
         Plan {
-            step_id: StepID(uuid()),
+            step_id: StepID::new(),
             gestures: gestures.into_iter().collect(),
         }
     }
@@ -98,7 +123,7 @@ impl PlanHistory {
     pub fn new() -> PlanHistory {
         PlanHistory {
             gestures: CHashMap::new(),
-            steps: vec![StepID(uuid())].into(),
+            steps: vec![StepID::new()].into(),
         }
     }
 
@@ -244,30 +269,192 @@ pub struct Proposal {
     undoable_history: CVec<Plan>,
     ongoing: Plan,
     redoable_history: CVec<Plan>,
+    // the latest master plan step this proposal has seen
+    based_on: COption<StepID>,
+    // the machine that created, imported or generated this proposal
+    // (the server for the initial proposal and for startup imports)
+    owner: MachineID,
+    // whether machines other than the owner can see this proposal
+    shared: bool,
+    status: ProposalStatus,
 }
 
 impl Proposal {
-    pub fn new() -> Proposal {
+    pub fn new(owner: MachineID) -> Proposal {
         Proposal {
             undoable_history: CVec::new(),
             ongoing: Plan::new(),
             redoable_history: CVec::new(),
+            based_on: COption(None),
+            owner,
+            shared: true,
+            status: ProposalStatus::Draft,
         }
     }
 
-    pub fn from_plan(plan: Plan) -> Proposal {
+    pub fn from_plan(plan: Plan, owner: MachineID) -> Proposal {
         Proposal {
             undoable_history: vec![plan].into(),
             ongoing: Plan::new(),
             redoable_history: CVec::new(),
+            based_on: COption(None),
+            owner,
+            shared: true,
+            status: ProposalStatus::Draft,
         }
     }
 
+    pub fn based_on(&self) -> Option<StepID> {
+        *self.based_on
+    }
+
+    pub fn changed_gestures(&self) -> HashSet<GestureID> {
+        self.undoable_history
+            .iter()
+            .chain(Some(&self.ongoing))
+            .flat_map(|plan| plan.gestures.keys().cloned())
+            .collect()
+    }
+
+    // Gestures that both this proposal and the master plan changed since
+    // this proposal was last based on the master plan
+    pub fn conflicts_with(&self, master_plan: &PlanHistory) -> CVec<GestureID> {
+        let steps_since_base = match *self.based_on {
+            Some(based_on) => master_plan
+                .steps
+                .iter()
+                .rposition(|step| *step == based_on)
+                .map(|base_idx| &master_plan.steps[base_idx + 1..])
+                .unwrap_or(&master_plan.steps[..]),
+            None => &master_plan.steps[..],
+        };
+
+        let mut conflicts = self
+            .changed_gestures()
+            .into_iter()
+            .filter(|gesture_id| {
+                master_plan
+                    .gestures
+                    .get(*gesture_id)
+                    .map(|VersionedGesture(_, step)| steps_since_base.contains(step))
+                    .unwrap_or(false)
+            }).collect::<Vec<_>>();
+        conflicts.sort_by_key(|gesture_id| gesture_id.0);
+        conflicts.into()
+    }
+
+    // Gestures that both this proposal and other change
+    pub fn conflicts_with_proposal(&self, other: &Proposal) -> CVec<GestureID> {
+        let changed_by_other = other.changed_gestures();
+        let mut conflicts = self
+            .changed_gestures()
+            .into_iter()
+            .filter(|gesture_id| changed_by_other.contains(gesture_id))
+            .collect::<Vec<_>>();
+        conflicts.sort_by_key(|gesture_id| gesture_id.0);
+        conflicts.into()
+    }
+
+    // Replays the steps of this proposal on top of the latest master plan.
+    // Gestures that the master plan changed since this proposal was based on it
+    // are dropped from all steps, so the master plan's version of them wins.
+    // Returns the dropped gestures
+    pub fn rebase_onto(&mut self, master_plan: &PlanHistory) -> CVec<GestureID> {
+        let conflicts = self.conflicts_with(master_plan);
+        self.drop_gestures(&conflicts);
+        self.base_on(master_plan);
+        conflicts
+    }
+
+    // Like rebase_onto, for when the master plan was replaced by one that doesn't
+    // continue old_master_plan (rollbacks, reverts). Everything the replacement
+    // added, changed or removed counts as changed by the master plan
+    pub fn rebase_onto_replaced(
+        &mut self,
+        old_master_plan: &PlanHistory,
+        new_master_plan: &PlanHistory,
+    ) -> CVec<GestureID> {
+        let changed_gestures = self.changed_gestures();
+        let diff = PlanDiff::between(old_master_plan, new_master_plan);
+
+        let mut conflicts = self
+            .conflicts_with(old_master_plan)
+            .iter()
+            .chain(diff.added.iter())
+            .chain(diff.changed.iter())
+            .chain(diff.removed.iter())
+            .filter(|gesture_id| changed_gestures.contains(gesture_id))
+            .cloned()
+            .collect::<Vec<_>>();
+        conflicts.sort_by_key(|gesture_id| gesture_id.0);
+        conflicts.dedup();
+
+        self.drop_gestures(&conflicts);
+        self.base_on(new_master_plan);
+        conflicts.into()
+    }
+
+    fn drop_gestures(&mut self, gesture_ids: &[GestureID]) {
+        for plan in self
+            .undoable_history
+            .iter_mut()
+            .chain(Some(&mut self.ongoing))
+            .chain(self.redoable_history.iter_mut())
+        {
+            for gesture_id in gesture_ids {
+                plan.gestures.remove(*gesture_id);
+            }
+        }
+
+        // steps that only changed dropped gestures don't do anything anymore
+        self.undoable_history
+            .retain(|plan| !plan.gestures.is_empty());
+        self.redoable_history
+            .retain(|plan| !plan.gestures.is_empty());
+    }
+
+    // Only moves the base, for when the master plan changed in a way that
+    // can't be compared against the old base (restores, reverted implementations)
+    pub fn base_on(&mut self, master_plan: &PlanHistory) {
+        self.based_on = COption(Some(master_plan.latest_step_id()));
+    }
+
+    // Appends the steps of other, assuming the two don't conflict
+    pub fn merge(&mut self, other: &Proposal, master_plan: &PlanHistory) {
+        self.undoable_history
+            .extend(other.undoable_history.iter().cloned());
+        if !other.ongoing.gestures.is_empty() {
+            self.undoable_history.push(other.ongoing.clone());
+        }
+        self.redoable_history.clear();
+
+        // the merged proposal is only as up to date as the older of both
+        let position_in_master = |maybe_base: Option<StepID>| {
+            maybe_base.and_then(|base| master_plan.steps.iter().rposition(|step| *step == base))
+        };
+
+        self.based_on = match (
+            position_in_master(*self.based_on),
+            position_in_master(*other.based_on),
+        ) {
+            (Some(own_idx), Some(other_idx)) => {
+                COption(Some(master_plan.steps[own_idx.min(other_idx)]))
+            }
+            _ => COption(None),
+        };
+    }
+
     pub fn start_new_step(&mut self) {
         self.undoable_history.push(self.ongoing.clone());
         self.ongoing = Plan::new();
     }
 
+    // Adds a finished step, keeping whatever is ongoing as it is
+    pub fn push_step(&mut self, step: Plan) {
+        self.undoable_history.push(step);
+        self.redoable_history.clear();
+    }
+
     pub fn set_ongoing_step(&mut self, current_change: Plan) {
         self.ongoing = current_change;
         self.redoable_history.clear();
@@ -599,8 +786,47 @@ impl ActionGroups {
     }
 }
 
+// Why a plan can't be built, and which of its parts are to blame (if known)
+#[derive(Compact, Clone, Debug, Serialize, Deserialize)]
+pub struct PlanError {
+    pub description: CString,
+    pub gestures: CVec<GestureID>,
+    pub prototypes: CVec<PrototypeID>,
+}
+
+impl PlanError {
+    pub fn new(description: &str) -> PlanError {
+        let mut c_description = CString::new();
+        c_description.push_str(description);
+        PlanError {
+            description: c_description,
+            gestures: CVec::new(),
+            prototypes: CVec::new(),
+        }
+    }
+
+    pub fn caused_by_gesture(mut self, gesture_id: GestureID) -> PlanError {
+        self.gestures.push(gesture_id);
+        self
+    }
+
+    pub fn caused_by_prototype(mut self, prototype_id: PrototypeID) -> PlanError {
+        self.prototypes.push(prototype_id);
+        self
+    }
+}
+
+impl From<AreaError> for PlanError {
+    fn from(err: AreaError) -> PlanError {
+        match err {
+            AreaError::LeftOver(string) => PlanError::new(&string),
+            _ => PlanError::new(&format!("{:?}", err)),
+        }
+    }
+}
+
 impl PlanHistory {
-    pub fn calculate_result(&self) -> Result<PlanResult, AreaError> {
+    pub fn calculate_result(&self) -> Result<PlanResult, PlanError> {
         let mut result = PlanResult {
             prototypes: CHashMap::new(),
         };
@@ -632,6 +858,17 @@ impl ProposalID {
     pub fn new() -> ProposalID {
         ProposalID(uuid())
     }
+
+    pub fn parse(string: &str) -> Result<ProposalID, String> {
+        Uuid::parse_str(string)
+            .map(ProposalID)
+            .map_err(|err| err.to_string())
+    }
+
+    // deterministic, so that replayed sessions end up with the same proposal ids
+    pub fn successor_of(implemented: ProposalID, machine: MachineID) -> ProposalID {
+        ProposalID(uuid_from_seed((implemented, machine)))
+    }
 }
 
 #[derive(Compact, Clone)]
@@ -642,20 +879,42 @@ pub struct PlanManager {
     proposals: CHashMap<ProposalID, Proposal>,
     implemented_proposals: CHashMap<ProposalID, Proposal>,
     ui_state: CHashMap<MachineID, PlanManagerUIState>,
+    policy: PlanningPolicy,
+    // votes on submitted proposals
+    ballots: CHashMap<ProposalID, Ballot>,
+    // when each client machine last asked for plan updates, to know who can vote
+    participants: CHashMap<MachineID, WallInstant>,
+    checkpoints: CVec<Checkpoint>,
+    // implemented proposals that can still be cancelled
+    unbuilt_implementations: CHashMap<ProposalID, UnbuiltImplementation>,
 }
 
 impl PlanManager {
     pub fn spawn(id: PlanManagerID, initial_proposal_id: ProposalID, _: &mut World) -> PlanManager {
-        PlanManager {
+        let mut plan_manager = PlanManager {
             id,
             master_plan: PlanHistory::new(),
             master_result: PlanResult::new(),
-            proposals: Some((initial_proposal_id, Proposal::new()))
-                .into_iter()
-                .collect(),
+            proposals: CHashMap::new(),
             implemented_proposals: CHashMap::new(),
             ui_state: CHashMap::new(),
+            policy: PlanningPolicy::default(),
+            ballots: CHashMap::new(),
+            participants: CHashMap::new(),
+            checkpoints: CVec::new(),
+            unbuilt_implementations: CHashMap::new(),
+        };
+        // shared, so that every machine can start planning right away
+        plan_manager.add_proposal(initial_proposal_id, Proposal::new(id.as_raw().machine));
+        plan_manager
+    }
+
+    // New proposals start out based on the current master plan
+    fn add_proposal(&mut self, proposal_id: ProposalID, mut proposal: Proposal) {
+        if proposal.based_on.is_none() {
+            proposal.base_on(&self.master_plan);
         }
+        self.proposals.insert(proposal_id, proposal);
     }
 
     pub fn get_current_version_of(
@@ -680,53 +939,121 @@ impl PlanManager {
             .expect("Expected gesture (that point should be added to) to exist!")
     }
 
-    pub fn implement(&mut self, proposal_id: ProposalID, world: &mut World) {
+    pub fn implement(&mut self, proposal_id: ProposalID, ui: PlanningUIID, world: &mut World) {
+        let machine = ui.as_raw().machine;
+
+        if !self.check_allowed(machine, proposal_id, PlanningAction::Implement, world) {
+            return;
+        }
+
+        if !self.may_implement(proposal_id) {
+            PlanningUIID::global_broadcast(world).on_permission_denied(
+                proposal_id,
+                machine,
+                PlanningAction::Implement,
+                world,
+            );
+            return;
+        }
+
+        JournalID::local_first(world).record(
+            JournaledCommand::Implement(proposal_id, ui),
+            world,
+        );
+
+        match self.implement_without_journaling(proposal_id, world) {
+            Ok(()) => self.set_status(proposal_id, ProposalStatus::Implemented, world),
+            Err(error) => {
+                PlanningUIID::global_broadcast(world).on_plan_error(proposal_id, error, world);
+                // it needs to be fixed, which requires another vote
+                if self.status_of(proposal_id) == Some(ProposalStatus::Approved) {
+                    self.set_status(proposal_id, ProposalStatus::Draft, world);
+                }
+            }
+        }
+    }
+
+    // Leaves the proposal and the master plan untouched if the result can't be calculated
+    fn implement_without_journaling(
+        &mut self,
+        proposal_id: ProposalID,
+        world: &mut World,
+    ) -> Result<(), PlanError> {
+        let (new_master_plan, result) = {
+            let proposal = self
+                .proposals
+                .get(proposal_id)
+                .expect("Proposal should exist");
+
+            let new_master_plan = proposal.apply_to(&self.master_plan);
+
+            match new_master_plan.calculate_result() {
+                Ok(result) => (new_master_plan, result),
+                Err(mut error) => {
+                    // the master plan alone was fine, so blame what the proposal changed
+                    if error.gestures.is_empty() && error.prototypes.is_empty() {
+                        let mut changed_gestures =
+                            proposal.changed_gestures().into_iter().collect::<Vec<_>>();
+                        changed_gestures.sort_by_key(|gesture_id| gesture_id.0);
+                        error.gestures = changed_gestures.into();
+                    }
+                    return Err(error);
+                }
+            }
+        };
+
         let proposal = self
             .proposals
             .remove(proposal_id)
             .expect("Proposal should exist");
 
-        self.master_plan = proposal.apply_to(&self.master_plan);
-
-        match self.master_plan.calculate_result() {
-            Ok(result) => {
-                let (actions, new_prototypes) = self.master_result.actions_to(&result);
-                ConstructionID::global_first(world).implement(actions, new_prototypes, world);
-                self.implemented_proposals.insert(proposal_id, proposal);
-                self.master_result = result;
+        let old_master_plan = ::std::mem::replace(&mut self.master_plan, new_master_plan);
 
-                let potentially_affected_ui_states = self
-                    .ui_state
-                    .pairs()
-                    .map(|(machine, state)| (*machine, state.current_proposal))
-                    .collect::<Vec<_>>();
+        let (actions, new_prototypes) = self.master_result.actions_to(&result);
+        self.unbuilt_implementations.insert(
+            proposal_id,
+            UnbuiltImplementation::new(&proposal, &old_master_plan, &self.master_result, &actions),
+        );
+        ConstructionID::global_first(world).implement(
+            actions,
+            new_prototypes,
+            COption(Some(proposal_id)),
+            false,
+            world,
+        );
+        let (owner, shared) = (proposal.owner(), proposal.shared);
+        self.implemented_proposals.insert(proposal_id, proposal);
+        self.master_result = result;
+
+        let potentially_affected_ui_states = self
+            .ui_state
+            .pairs()
+            .map(|(machine, state)| (*machine, state.current_proposal))
+            .collect::<Vec<_>>();
 
-                for (machine, current_proposal) in potentially_affected_ui_states {
-                    if current_proposal == proposal_id {
-                        let new_proposal_id = ProposalID::new();
+        for (machine, current_proposal) in potentially_affected_ui_states {
+            if current_proposal == proposal_id {
+                let new_proposal_id = ProposalID::successor_of(proposal_id, machine);
 
-                        self.proposals.insert(new_proposal_id, Proposal::new());
+                // the successor keeps the ownership of the implemented proposal
+                let successor = Proposal {
+                    shared,
+                    ..Proposal::new(owner)
+                };
+                self.add_proposal(new_proposal_id, successor);
 
-                        self.switch_to(machine, new_proposal_id, world);
-                    }
-                }
+                self.switch_to(machine, new_proposal_id, world);
+            }
+        }
 
-                let all_proposal_ids = self.proposals.keys().cloned().collect::<Vec<_>>();
-                for old_proposal_id in all_proposal_ids {
-                    if old_proposal_id != proposal_id {
-                        self.clear_previews(old_proposal_id);
-                    }
-                }
+        let all_proposal_ids = self.proposals.keys().cloned().collect::<Vec<_>>();
+        for old_proposal_id in all_proposal_ids {
+            if old_proposal_id != proposal_id {
+                self.clear_previews(old_proposal_id);
             }
-            Err(err) => match err {
-                ::descartes::AreaError::LeftOver(string) => {
-                    println!("Implement Plan Error: {}", string);
-                }
-                _ => {
-                    println!("Implement Plan Error: {:?}", err);
-                }
-            },
         }
+
+        Ok(())
     }
 
     pub fn implement_artificial_proposal(
@@ -740,23 +1067,88 @@ impl PlanManager {
             .all(|prototype_id| self.master_result.prototypes.contains_key(*prototype_id))
         {
             let proposal_id = ProposalID::new();
-            self.proposals.insert(proposal_id, proposal.clone());
-            self.implement(proposal_id, world);
+            self.add_proposal(proposal_id, proposal.clone());
+            if let Err(error) = self.implement_without_journaling(proposal_id, world) {
+                println!(
+                    "Couldn't implement artificial proposal: {}",
+                    &*error.description
+                );
+                self.proposals.remove(proposal_id);
+            }
         } else {
             println!("Tried to implement artificial proposal based on outdated prototypes");
         }
     }
+
+    pub fn save_to(&mut self, writer: SaveGameWriterID, world: &mut World) {
+        writer.on_planning_state(
+            PlanningState {
+                master_plan: self.master_plan.clone(),
+                master_result: self.master_result.clone(),
+                proposals: self.proposals.clone(),
+                implemented_proposals: self.implemented_proposals.clone(),
+                checkpoints: self.checkpoints.clone(),
+                ballots: self.ballots.clone(),
+                policy: self.policy,
+            },
+            world,
+        );
+    }
+
+    pub fn restore(&mut self, state: &PlanningState, world: &mut World) {
+        self.master_plan = state.master_plan.clone();
+        self.implemented_proposals = state.implemented_proposals.clone();
+        self.checkpoints = state.checkpoints.clone();
+        // the saved policy is set by the server once startup imports and generation are done
+
+        // keep the proposals that machines currently have open
+        for proposal in self.proposals.values_mut() {
+            proposal.base_on(&state.master_plan);
+        }
+
+        for (proposal_id, proposal) in state.proposals.pairs() {
+            self.proposals.insert(*proposal_id, proposal.clone());
+        }
+
+        for (proposal_id, ballot) in state.ballots.pairs() {
+            self.ballots.insert(*proposal_id, ballot.clone());
+        }
+
+        // rebuild the physical city from scratch, without waiting for construction
+        let (actions, new_prototypes) = PlanResult::new().actions_to(&state.master_result);
+        ConstructionID::global_first(world).implement(
+            actions,
+            new_prototypes,
+            COption(None),
+            true,
+            world,
+        );
+        self.unbuilt_implementations = CHashMap::new();
+        self.master_result = state.master_result.clone();
+
+        let all_proposal_ids = self.proposals.keys().cloned().collect::<Vec<_>>();
+        for proposal_id in all_proposal_ids {
+            self.clear_previews(proposal_id);
+        }
+    }
 }
 
 pub fn setup(system: &mut ActorSystem) {
     system.register::<PlanManager>();
     auto_setup(system);
     interaction::setup(system);
+    exchange::setup(system);
+    rebase::auto_setup(system);
+    estimate::auto_setup(system);
+    generation::auto_setup(system);
+    permissions::auto_setup(system);
+    voting::auto_setup(system);
+    checkpoints::auto_setup(system);
+    cancellation::auto_setup(system);
     ui::auto_setup(system);
 }
 
-pub fn spawn(world: &mut World) -> PlanManagerID {
-    let initial_proposal_id = ProposalID::new();
+pub fn spawn(world: &mut World, initial_proposal_id: ProposalID) -> PlanManagerID {
     let plan_manager = PlanManagerID::spawn(initial_proposal_id, world);
     plan_manager.switch_to(MachineID(0), initial_proposal_id, world);
     plan_manager
@@ -0,0 +1,206 @@
+use kay::{World, MachineID, TypedID};
+use super::{Proposal, ProposalID, PlanManager};
+use super::ui::PlanningUIID;
+use super::voting::ProposalStatus;
+use journal::{JournalID, JournaledCommand};
+
+// Who may change and implement which proposals, chosen by the server
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum PlanningPolicy {
+    // everyone may edit and implement every proposal they can see
+    FreeForAll,
+    // only owners may edit and implement their proposals
+    OwnerOnly,
+    // only owners may edit their proposals,
+    // which have to be approved by a vote before they can be implemented
+    Voting,
+}
+
+impl Default for PlanningPolicy {
+    fn default() -> PlanningPolicy {
+        PlanningPolicy::FreeForAll
+    }
+}
+
+impl PlanningPolicy {
+    pub fn parse(name: &str) -> Result<PlanningPolicy, String> {
+        match name {
+            "free-for-all" => Ok(PlanningPolicy::FreeForAll),
+            "owner-only" => Ok(PlanningPolicy::OwnerOnly),
+            "voting" => Ok(PlanningPolicy::Voting),
+            _ => Err(format!(
+                "Unknown planning policy {}, expected free-for-all, owner-only or voting",
+                name
+            )),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum PlanningAction {
+    View,
+    Edit,
+    Implement,
+}
+
+impl Proposal {
+    // Proposals created by a player start out private
+    pub fn owned_by(machine: MachineID) -> Proposal {
+        Proposal {
+            shared: false,
+            ..Proposal::new(machine)
+        }
+    }
+
+    pub fn owner(&self) -> MachineID {
+        self.owner
+    }
+
+    pub fn is_shared(&self) -> bool {
+        self.shared
+    }
+
+    pub fn is_owned_by(&self, machine: MachineID) -> bool {
+        self.owner == machine
+    }
+}
+
+impl PlanManager {
+    pub fn is_allowed(
+        &self,
+        machine: MachineID,
+        proposal_id: ProposalID,
+        action: PlanningAction,
+    ) -> bool {
+        let proposal = match self.proposals.get(proposal_id) {
+            Some(proposal) => proposal,
+            None => return false,
+        };
+
+        let is_owner = proposal.is_owned_by(machine);
+
+        match action {
+            PlanningAction::View => is_owner || proposal.shared,
+            // submitted proposals are frozen until they are withdrawn again
+            PlanningAction::Edit if proposal.status() != ProposalStatus::Draft => false,
+            PlanningAction::Edit | PlanningAction::Implement => match self.policy {
+                PlanningPolicy::FreeForAll => is_owner || proposal.shared,
+                PlanningPolicy::OwnerOnly | PlanningPolicy::Voting => is_owner,
+            },
+        }
+    }
+
+    // Implemented proposals are part of the master plan, which everyone can see.
+    // Without a machine (like for the query API) only shared proposals are visible
+    pub fn is_visible_to(&self, viewer: Option<MachineID>, proposal_id: ProposalID) -> bool {
+        if self.implemented_proposals.contains_key(proposal_id) {
+            return true;
+        }
+
+        match viewer {
+            Some(machine) => self.is_allowed(machine, proposal_id, PlanningAction::View),
+            None => self
+                .proposals
+                .get(proposal_id)
+                .map(|proposal| proposal.shared)
+                .unwrap_or(false),
+        }
+    }
+
+    // Tells the machine if it isn't allowed to
+    pub(crate) fn check_allowed(
+        &self,
+        machine: MachineID,
+        proposal_id: ProposalID,
+        action: PlanningAction,
+        world: &mut World,
+    ) -> bool {
+        let allowed = self.is_allowed(machine, proposal_id, action);
+        if !allowed {
+            PlanningUIID::global_broadcast(world).on_permission_denied(
+                proposal_id,
+                machine,
+                action,
+                world,
+            );
+        }
+        allowed
+    }
+
+    pub fn set_policy(&mut self, policy: PlanningPolicy, _: &mut World) {
+        self.policy = policy;
+    }
+
+    pub fn create_proposal(
+        &mut self,
+        proposal_id: ProposalID,
+        ui: PlanningUIID,
+        world: &mut World,
+    ) {
+        let machine = ui.as_raw().machine;
+
+        JournalID::local_first(world).record(
+            JournaledCommand::CreateProposal(proposal_id, ui),
+            world,
+        );
+
+        self.add_proposal(proposal_id, Proposal::owned_by(machine));
+        self.switch_to(machine, proposal_id, world);
+    }
+
+    // Shared proposals can be seen (but, depending on the policy, not changed) by everyone
+    pub fn share_proposal(
+        &mut self,
+        proposal_id: ProposalID,
+        ui: PlanningUIID,
+        shared: bool,
+        world: &mut World,
+    ) {
+        let machine = ui.as_raw().machine;
+        let is_owner = self
+            .proposals
+            .get(proposal_id)
+            .map(|proposal| proposal.is_owned_by(machine))
+            .unwrap_or(false);
+
+        if !is_owner {
+            PlanningUIID::global_broadcast(world).on_permission_denied(
+                proposal_id,
+                machine,
+                PlanningAction::Edit,
+                world,
+            );
+            return;
+        }
+
+        JournalID::local_first(world).record(
+            JournaledCommand::ShareProposal(proposal_id, ui, shared),
+            world,
+        );
+
+        if let Some(proposal) = self.proposals.get_mut(proposal_id) {
+            proposal.shared = shared;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proposals_are_only_owned_by_their_machine() {
+        let proposal = Proposal::owned_by(MachineID(1));
+        assert!(proposal.is_owned_by(MachineID(1)));
+        assert!(!proposal.is_owned_by(MachineID(2)));
+        assert!(!proposal.is_shared());
+
+        // like the initial proposal, which belongs to the server
+        let shared_proposal = Proposal::new(MachineID(0));
+        assert!(!shared_proposal.is_owned_by(MachineID(1)));
+        assert!(shared_proposal.is_shared());
+    }
+}
+
+pub mod kay_auto;
+pub use self::kay_auto::*;
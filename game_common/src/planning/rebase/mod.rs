@@ -0,0 +1,257 @@
+use kay::{World, TypedID};
+use compact::CVec;
+use super::{ProposalID, PlanManager, PlanHistory, GestureID};
+use super::ui::PlanningUIID;
+use super::permissions::PlanningAction;
+use journal::{JournalID, JournaledCommand};
+
+impl PlanManager {
+    // Moves a proposal onto the latest master plan. Gestures that the master plan
+    // changed in the meantime are dropped from the proposal and reported as conflicts
+    pub fn rebase_proposal(
+        &mut self,
+        proposal_id: ProposalID,
+        ui: PlanningUIID,
+        world: &mut World,
+    ) {
+        if !self.check_allowed(ui.as_raw().machine, proposal_id, PlanningAction::Edit, world) {
+            return;
+        }
+
+        JournalID::local_first(world).record(
+            JournaledCommand::RebaseProposal(proposal_id, ui),
+            world,
+        );
+
+        let conflicts = if let Some(proposal) = self.proposals.get_mut(proposal_id) {
+            proposal.rebase_onto(&self.master_plan)
+        } else {
+            println!("Tried to rebase nonexistent proposal {:?}", proposal_id);
+            return;
+        };
+
+        self.clear_previews(proposal_id);
+        PlanningUIID::global_broadcast(world).on_proposal_rebased(proposal_id, conflicts, world);
+    }
+
+    // For when the master plan was replaced rather than continued. Open proposals are
+    // rebased right away, since their base might not exist anymore
+    pub(crate) fn rebase_after_replaced_master(
+        &mut self,
+        old_master_plan: &PlanHistory,
+        world: &mut World,
+    ) {
+        let all_proposal_ids = self.proposals.keys().cloned().collect::<Vec<_>>();
+
+        for proposal_id in all_proposal_ids {
+            let conflicts = self
+                .proposals
+                .get_mut(proposal_id)
+                .expect("just listed it")
+                .rebase_onto_replaced(old_master_plan, &self.master_plan);
+
+            if !conflicts.is_empty() {
+                PlanningUIID::global_broadcast(world).on_proposal_rebased(
+                    proposal_id,
+                    conflicts,
+                    world,
+                );
+            }
+        }
+    }
+
+    // Merges other into proposal, unless both change the same gestures
+    pub fn merge_proposals(
+        &mut self,
+        proposal_id: ProposalID,
+        other_id: ProposalID,
+        ui: PlanningUIID,
+        world: &mut World,
+    ) {
+        let machine = ui.as_raw().machine;
+
+        // merging changes both proposals, since other is removed afterwards
+        if !self.check_allowed(machine, proposal_id, PlanningAction::Edit, world)
+            || !self.check_allowed(machine, other_id, PlanningAction::Edit, world)
+        {
+            return;
+        }
+
+        JournalID::local_first(world).record(
+            JournaledCommand::MergeProposals(proposal_id, other_id, ui),
+            world,
+        );
+
+        if proposal_id == other_id {
+            return;
+        }
+
+        let conflicts: CVec<GestureID> =
+            match (self.proposals.get(proposal_id), self.proposals.get(other_id)) {
+                (Some(proposal), Some(other)) => proposal.conflicts_with_proposal(other),
+                _ => {
+                    println!(
+                        "Tried to merge nonexistent proposals {:?} and {:?}",
+                        proposal_id, other_id
+                    );
+                    return;
+                }
+            };
+
+        if conflicts.is_empty() {
+            let other = self
+                .proposals
+                .remove(other_id)
+                .expect("just checked that it exists");
+            self.proposals
+                .get_mut(proposal_id)
+                .expect("just checked that it exists")
+                .merge(&other, &self.master_plan);
+
+            let machines_on_other = self
+                .ui_state
+                .pairs()
+                .filter(|&(_, state)| state.current_proposal == other_id)
+                .map(|(machine, _)| *machine)
+                .collect::<Vec<_>>();
+
+            for machine in machines_on_other {
+                self.switch_to(machine, proposal_id, world);
+            }
+
+            self.clear_previews(proposal_id);
+        }
+
+        PlanningUIID::global_broadcast(world).on_proposals_merged(
+            proposal_id,
+            other_id,
+            conflicts,
+            world,
+        );
+    }
+}
+
+pub mod kay_auto;
+pub use self::kay_auto::*;
+
+#[cfg(test)]
+mod tests {
+    use kay::MachineID;
+    use planning::{Plan, PlanHistory, Proposal, GestureID, VersionedGesture};
+    use planning::test_util::step;
+
+    fn length_in(history: &PlanHistory, gesture_id: GestureID) -> Option<f32> {
+        history
+            .gestures
+            .get(gesture_id)
+            .map(|VersionedGesture(gesture, _)| gesture.points[1].x)
+    }
+
+    fn proposal_on(master_plan: &PlanHistory, steps: Vec<Plan>) -> Proposal {
+        let mut proposal = Proposal::new(MachineID(0));
+        proposal.undoable_history = steps.into();
+        proposal.base_on(master_plan);
+        proposal
+    }
+
+    #[test]
+    fn clean_rebase_keeps_all_steps() {
+        let (a, b, c) = (GestureID::new(), GestureID::new(), GestureID::new());
+        let master_plan = PlanHistory::new().and_then(&[step(&[(a, 1.0)])]);
+        let mut proposal = proposal_on(&master_plan, vec![step(&[(b, 2.0)])]);
+
+        let master_plan = master_plan.and_then(&[step(&[(c, 3.0)])]);
+        assert!(proposal.conflicts_with(&master_plan).is_empty());
+
+        let conflicts = proposal.rebase_onto(&master_plan);
+        assert!(conflicts.is_empty());
+        assert_eq!(proposal.based_on(), Some(master_plan.latest_step_id()));
+
+        let result = proposal.apply_to(&master_plan);
+        assert_eq!(length_in(&result, a), Some(1.0));
+        assert_eq!(length_in(&result, b), Some(2.0));
+        assert_eq!(length_in(&result, c), Some(3.0));
+    }
+
+    #[test]
+    fn conflicting_rebase_drops_gestures_changed_in_master() {
+        let (a, b) = (GestureID::new(), GestureID::new());
+        let master_plan = PlanHistory::new().and_then(&[step(&[(a, 1.0)])]);
+        let mut proposal = proposal_on(
+            &master_plan,
+            vec![step(&[(a, 10.0), (b, 2.0)]), step(&[(a, 20.0)])],
+        );
+
+        let master_plan = master_plan.and_then(&[step(&[(a, 5.0)])]);
+        let conflicts = proposal.rebase_onto(&master_plan);
+        assert_eq!(&conflicts[..], &[a][..]);
+
+        // the step that only changed a is gone
+        assert_eq!(proposal.current_history().len(), 1);
+        assert!(!proposal.changed_gestures().contains(&a));
+
+        let result = proposal.apply_to(&master_plan);
+        assert_eq!(length_in(&result, a), Some(5.0));
+        assert_eq!(length_in(&result, b), Some(2.0));
+
+        // after the rebase, nothing conflicts anymore
+        assert!(proposal.rebase_onto(&master_plan).is_empty());
+    }
+
+    #[test]
+    fn changes_from_before_the_base_are_not_conflicts() {
+        let a = GestureID::new();
+        let master_plan = PlanHistory::new().and_then(&[step(&[(a, 1.0)])]);
+        let mut proposal = proposal_on(&master_plan, vec![step(&[(a, 10.0)])]);
+
+        assert!(proposal.rebase_onto(&master_plan).is_empty());
+        assert_eq!(length_in(&proposal.apply_to(&master_plan), a), Some(10.0));
+    }
+
+    #[test]
+    fn replacing_the_master_plan_drops_gestures_it_took_back() {
+        let (a, b, c) = (GestureID::new(), GestureID::new(), GestureID::new());
+        let checkpoint = PlanHistory::new().and_then(&[step(&[(a, 1.0), (b, 1.0)])]);
+        let master_plan = checkpoint.and_then(&[step(&[(a, 5.0)])]);
+        let mut proposal = proposal_on(
+            &master_plan,
+            vec![step(&[(a, 10.0)]), step(&[(b, 10.0), (c, 10.0)])],
+        );
+
+        // a was changed after the checkpoint, b wasn't
+        let conflicts = proposal.rebase_onto_replaced(&master_plan, &checkpoint);
+        assert_eq!(&conflicts[..], &[a][..]);
+        assert_eq!(proposal.based_on(), Some(checkpoint.latest_step_id()));
+
+        let result = proposal.apply_to(&checkpoint);
+        assert_eq!(length_in(&result, a), Some(1.0));
+        assert_eq!(length_in(&result, b), Some(10.0));
+        assert_eq!(length_in(&result, c), Some(10.0));
+    }
+
+    #[test]
+    fn clean_merge_combines_steps() {
+        let (a, b) = (GestureID::new(), GestureID::new());
+        let master_plan = PlanHistory::new();
+        let mut proposal = proposal_on(&master_plan, vec![step(&[(a, 1.0)])]);
+        let other = proposal_on(&master_plan, vec![step(&[(b, 2.0)])]);
+
+        assert!(proposal.conflicts_with_proposal(&other).is_empty());
+        proposal.merge(&other, &master_plan);
+
+        let result = proposal.apply_to(&master_plan);
+        assert_eq!(length_in(&result, a), Some(1.0));
+        assert_eq!(length_in(&result, b), Some(2.0));
+        assert_eq!(proposal.based_on(), Some(master_plan.latest_step_id()));
+    }
+
+    #[test]
+    fn merging_proposals_that_change_the_same_gesture_conflicts() {
+        let (a, b) = (GestureID::new(), GestureID::new());
+        let master_plan = PlanHistory::new();
+        let proposal = proposal_on(&master_plan, vec![step(&[(a, 1.0), (b, 1.0)])]);
+        let other = proposal_on(&master_plan, vec![step(&[(a, 2.0)])]);
+
+        assert_eq!(&proposal.conflicts_with_proposal(&other)[..], &[a][..]);
+    }
+}
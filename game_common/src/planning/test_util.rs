@@ -0,0 +1,15 @@
+use descartes::P2;
+use super::{Plan, Gesture, GestureID, GestureIntent};
+use transport::transport_planning::RoadIntent;
+
+fn road(length: f32) -> Gesture {
+    Gesture::new(
+        vec![P2::new(0.0, 0.0), P2::new(length, 0.0)].into(),
+        GestureIntent::Road(RoadIntent::new(1, 1)),
+    )
+}
+
+// A step with straight roads of the given lengths, which makes versions easy to tell apart
+pub fn step(gestures: &[(GestureID, f32)]) -> Plan {
+    Plan::from_gestures(gestures.iter().map(|&(id, length)| (id, road(length))))
+}
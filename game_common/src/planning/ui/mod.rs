@@ -1,6 +1,12 @@
-use kay::World;
-use compact::CHashMap;
-use super::{PlanHistoryUpdate, ProposalID, ProposalUpdate, PlanResultUpdate, ActionGroups};
+use kay::{World, MachineID};
+use compact::{CHashMap, CVec, CString};
+use super::{PlanHistoryUpdate, ProposalID, ProposalUpdate, PlanResultUpdate, ActionGroups,
+GestureID, PlanError};
+use super::estimate::ProposalEstimate;
+use super::permissions::PlanningAction;
+use super::voting::{ProposalStatus, Ballot};
+use super::checkpoints::PlanDiff;
+use economy::resources::ResourceAmount;
 
 pub trait PlanningUI {
     fn on_plans_update(
@@ -15,6 +21,66 @@ pub trait PlanningUI {
         _proposal_id: ProposalID,
         result_update: &PlanResultUpdate,
         new_actions: &ActionGroups,
+        estimate: &ProposalEstimate,
+        _world: &mut World,
+    );
+
+    // conflicts are the gestures that were dropped from the proposal,
+    // because the master plan changed them as well
+    fn on_proposal_rebased(
+        &mut self,
+        proposal_id: ProposalID,
+        conflicts: &CVec<GestureID>,
+        _world: &mut World,
+    );
+
+    // conflicts are empty if the merge succeeded
+    fn on_proposals_merged(
+        &mut self,
+        proposal_id: ProposalID,
+        other_id: ProposalID,
+        conflicts: &CVec<GestureID>,
+        _world: &mut World,
+    );
+
+    // sent instead of a preview update if the preview can't be calculated,
+    // or if implementing the proposal failed (the proposal is left as it was)
+    fn on_plan_error(&mut self, proposal_id: ProposalID, error: &PlanError, _world: &mut World);
+
+    fn on_permission_denied(
+        &mut self,
+        proposal_id: ProposalID,
+        machine: MachineID,
+        action: PlanningAction,
+        _world: &mut World,
+    );
+
+    fn on_proposal_status_changed(
+        &mut self,
+        proposal_id: ProposalID,
+        status: ProposalStatus,
+        ballot: &Ballot,
+        quorum: u32,
+        _world: &mut World,
+    );
+
+    fn on_checkpoints_update(&mut self, names: &CVec<CString>, _world: &mut World);
+
+    fn on_checkpoint_diff(
+        &mut self,
+        from: &CString,
+        to: &CString,
+        diff: &PlanDiff,
+        _world: &mut World,
+    );
+
+    fn on_rolled_back(&mut self, checkpoint: &CString, _world: &mut World);
+
+    // requested is what waiting construction still needs
+    fn on_treasury_update(
+        &mut self,
+        balance: ResourceAmount,
+        requested: ResourceAmount,
         _world: &mut World,
     );
 }
@@ -0,0 +1,303 @@
+use kay::{World, MachineID, TypedID};
+use compact::CVec;
+use std::time::{Duration, Instant as WallInstant};
+use super::{Proposal, ProposalID, PlanManager};
+use super::ui::PlanningUIID;
+use super::permissions::{PlanningPolicy, PlanningAction};
+use journal::{JournalID, JournaledCommand};
+
+// A proposal can only be edited as a draft and, with the voting policy,
+// only be implemented once it has been approved
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum ProposalStatus {
+    Draft,
+    Submitted,
+    Approved,
+    Implemented,
+}
+
+impl Default for ProposalStatus {
+    fn default() -> ProposalStatus {
+        ProposalStatus::Draft
+    }
+}
+
+#[derive(Compact, Clone, Debug, Serialize, Deserialize)]
+pub struct Ballot {
+    pub approvals: CVec<MachineID>,
+    pub rejections: CVec<MachineID>,
+}
+
+impl Ballot {
+    pub fn new() -> Ballot {
+        Ballot {
+            approvals: CVec::new(),
+            rejections: CVec::new(),
+        }
+    }
+
+    // Every machine has one vote, but can change it until the vote is decided
+    pub fn cast(&mut self, machine: MachineID, approve: bool) {
+        self.approvals.retain(|voter| *voter != machine);
+        self.rejections.retain(|voter| *voter != machine);
+        if approve {
+            self.approvals.push(machine);
+        } else {
+            self.rejections.push(machine);
+        }
+    }
+
+    pub fn n_votes(&self) -> usize {
+        self.approvals.len() + self.rejections.len()
+    }
+}
+
+// Clients that haven't asked for plan updates for this long have disconnected
+const PARTICIPATION_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn is_participating(last_seen: WallInstant, now: WallInstant) -> bool {
+    now.duration_since(last_seen) <= PARTICIPATION_TIMEOUT
+}
+
+fn quorum_of<I: Iterator<Item = WallInstant>>(last_seen: I, now: WallInstant) -> usize {
+    let n_participants = last_seen
+        .filter(|last_seen| is_participating(*last_seen, now))
+        .count();
+    n_participants.max(1) / 2 + 1
+}
+
+impl Proposal {
+    pub fn status(&self) -> ProposalStatus {
+        self.status
+    }
+}
+
+impl PlanManager {
+    pub fn status_of(&self, proposal_id: ProposalID) -> Option<ProposalStatus> {
+        self.proposals
+            .get(proposal_id)
+            .or_else(|| self.implemented_proposals.get(proposal_id))
+            .map(|proposal| proposal.status)
+    }
+
+    // Clients ask for plan updates every frame, so this is called as long as they are connected
+    pub(crate) fn mark_participating(&mut self, machine: MachineID) {
+        // the server itself doesn't vote
+        if machine == self.id.as_raw().machine {
+            return;
+        }
+
+        let now = WallInstant::now();
+        let gone = self
+            .participants
+            .pairs()
+            .filter(|&(_, last_seen)| !is_participating(*last_seen, now))
+            .map(|(machine, _)| *machine)
+            .collect::<Vec<_>>();
+        for gone_machine in gone {
+            self.participants.remove(gone_machine);
+        }
+
+        self.participants.insert(machine, now);
+    }
+
+    // More than half of the client machines taking part in planning have to vote
+    pub fn quorum(&self) -> usize {
+        quorum_of(self.participants.values().cloned(), WallInstant::now())
+    }
+
+    pub(crate) fn set_status(
+        &mut self,
+        proposal_id: ProposalID,
+        status: ProposalStatus,
+        world: &mut World,
+    ) {
+        if let Some(proposal) = self.proposals.get_mut(proposal_id) {
+            proposal.status = status;
+        } else if let Some(proposal) = self.implemented_proposals.get_mut(proposal_id) {
+            proposal.status = status;
+        }
+
+        if status != ProposalStatus::Submitted {
+            self.ballots.remove(proposal_id);
+        }
+
+        self.report_status(proposal_id, world);
+    }
+
+    fn report_status(&self, proposal_id: ProposalID, world: &mut World) {
+        if let Some(status) = self.status_of(proposal_id) {
+            PlanningUIID::global_broadcast(world).on_proposal_status_changed(
+                proposal_id,
+                status,
+                self.ballots
+                    .get(proposal_id)
+                    .cloned()
+                    .unwrap_or_else(Ballot::new),
+                self.quorum() as u32,
+                world,
+            );
+        }
+    }
+
+    // Asks everyone to vote on the proposal, which can't be edited anymore from now on
+    pub fn submit_proposal(
+        &mut self,
+        proposal_id: ProposalID,
+        ui: PlanningUIID,
+        world: &mut World,
+    ) {
+        if !self.check_allowed(ui.as_raw().machine, proposal_id, PlanningAction::Edit, world) {
+            return;
+        }
+
+        JournalID::local_first(world).record(
+            JournaledCommand::SubmitProposal(proposal_id, ui),
+            world,
+        );
+
+        self.ballots.insert(proposal_id, Ballot::new());
+        self.set_status(proposal_id, ProposalStatus::Submitted, world);
+    }
+
+    // Turns a submitted or approved proposal back into a draft, dropping all votes
+    pub fn withdraw_proposal(
+        &mut self,
+        proposal_id: ProposalID,
+        ui: PlanningUIID,
+        world: &mut World,
+    ) {
+        let machine = ui.as_raw().machine;
+        let is_owner = self
+            .proposals
+            .get(proposal_id)
+            .map(|proposal| proposal.is_owned_by(machine))
+            .unwrap_or(false);
+
+        if !is_owner {
+            PlanningUIID::global_broadcast(world).on_permission_denied(
+                proposal_id,
+                machine,
+                PlanningAction::Edit,
+                world,
+            );
+            return;
+        }
+
+        JournalID::local_first(world).record(
+            JournaledCommand::WithdrawProposal(proposal_id, ui),
+            world,
+        );
+
+        self.set_status(proposal_id, ProposalStatus::Draft, world);
+    }
+
+    // Once the quorum is reached, the proposal is approved if most votes approve it,
+    // otherwise it goes back to being a draft
+    pub fn cast_vote(
+        &mut self,
+        proposal_id: ProposalID,
+        ui: PlanningUIID,
+        approve: bool,
+        world: &mut World,
+    ) {
+        let quorum = self.quorum() as u32;
+        self.decide_vote(proposal_id, ui, approve, quorum, world);
+    }
+
+    // Who is connected while replaying has nothing to do with who was connected
+    // when the vote was cast, so replayed votes use the recorded quorum
+    pub fn cast_replayed_vote(
+        &mut self,
+        proposal_id: ProposalID,
+        ui: PlanningUIID,
+        approve: bool,
+        quorum: u32,
+        world: &mut World,
+    ) {
+        self.decide_vote(proposal_id, ui, approve, quorum, world);
+    }
+
+    fn decide_vote(
+        &mut self,
+        proposal_id: ProposalID,
+        ui: PlanningUIID,
+        approve: bool,
+        quorum: u32,
+        world: &mut World,
+    ) {
+        let machine = ui.as_raw().machine;
+
+        if !self.check_allowed(machine, proposal_id, PlanningAction::View, world) {
+            return;
+        }
+
+        if self.status_of(proposal_id) != Some(ProposalStatus::Submitted) {
+            println!("Tried to vote on proposal {:?}, which isn't submitted", proposal_id);
+            return;
+        }
+
+        JournalID::local_first(world).record(
+            JournaledCommand::CastVote(proposal_id, ui, approve, quorum),
+            world,
+        );
+
+        let (n_votes, n_approvals) = {
+            let ballot = self
+                .ballots
+                .get_mut(proposal_id)
+                .expect("Submitted proposals should have a ballot");
+            ballot.cast(machine, approve);
+            (ballot.n_votes(), ballot.approvals.len())
+        };
+
+        if n_votes >= quorum as usize {
+            let status = if 2 * n_approvals > n_votes {
+                ProposalStatus::Approved
+            } else {
+                ProposalStatus::Draft
+            };
+            self.set_status(proposal_id, status, world);
+        } else {
+            self.report_status(proposal_id, world);
+        }
+    }
+
+    // With the voting policy, only approved proposals can be implemented
+    pub fn may_implement(&self, proposal_id: ProposalID) -> bool {
+        self.policy != PlanningPolicy::Voting
+            || self.status_of(proposal_id) == Some(ProposalStatus::Approved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quorum_only_counts_recent_participants() {
+        let now = WallInstant::now() + Duration::from_secs(60);
+        let recent = now - Duration::from_secs(1);
+        let gone = now - Duration::from_secs(30);
+
+        assert_eq!(quorum_of(vec![].into_iter(), now), 1);
+        assert_eq!(quorum_of(vec![recent].into_iter(), now), 1);
+        assert_eq!(quorum_of(vec![recent, recent, recent].into_iter(), now), 2);
+        assert_eq!(quorum_of(vec![recent, gone, gone, gone].into_iter(), now), 1);
+    }
+
+    #[test]
+    fn votes_can_be_changed() {
+        let mut ballot = Ballot::new();
+        ballot.cast(MachineID(1), true);
+        ballot.cast(MachineID(2), false);
+        ballot.cast(MachineID(1), false);
+
+        assert_eq!(ballot.n_votes(), 2);
+        assert!(ballot.approvals.is_empty());
+        assert!(&ballot.rejections[..] == &[MachineID(2), MachineID(1)][..]);
+    }
+}
+
+pub mod kay_auto;
+pub use self::kay_auto::*;
@@ -0,0 +1,348 @@
+use super::time::{Instant, TimeOfDay, TimeOfDayRange, TICKS_PER_SIM_MINUTE, BEGINNING_TIME_OF_DAY,
+MINUTES_PER_DAY};
+
+pub const DAYS_PER_WEEK: usize = 7;
+pub const WEEKS_PER_SEASON: usize = 4;
+pub const DAYS_PER_SEASON: usize = DAYS_PER_WEEK * WEEKS_PER_SEASON;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+const ALL_WEEKDAYS: [Weekday; DAYS_PER_WEEK] = [
+    Weekday::Monday,
+    Weekday::Tuesday,
+    Weekday::Wednesday,
+    Weekday::Thursday,
+    Weekday::Friday,
+    Weekday::Saturday,
+    Weekday::Sunday,
+];
+
+impl Weekday {
+    pub fn from_index(index: usize) -> Weekday {
+        ALL_WEEKDAYS[index % DAYS_PER_WEEK]
+    }
+
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+
+    pub fn is_weekend(&self) -> bool {
+        *self == Weekday::Saturday || *self == Weekday::Sunday
+    }
+
+    pub fn previous(&self) -> Weekday {
+        Weekday::from_index(self.index() + DAYS_PER_WEEK - 1)
+    }
+
+    pub fn next(&self) -> Weekday {
+        Weekday::from_index(self.index() + 1)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Season {
+    Spring,
+    Summer,
+    Autumn,
+    Winter,
+}
+
+// A day of the simulation, counted from the day the simulation starts on.
+// Days start at midnight, so day 0 is shorter, since the
+// simulation starts at BEGINNING_TIME_OF_DAY
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+pub struct Day(u32);
+
+impl Day {
+    pub fn new(index: usize) -> Self {
+        Day(index as u32)
+    }
+
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+
+    // The simulation starts on a Monday
+    pub fn weekday(&self) -> Weekday {
+        Weekday::from_index(self.index())
+    }
+
+    pub fn week(&self) -> usize {
+        self.index() / DAYS_PER_WEEK
+    }
+
+    // The simulation starts on the first day of spring
+    pub fn season(&self) -> Season {
+        match (self.index() / DAYS_PER_SEASON) % 4 {
+            0 => Season::Spring,
+            1 => Season::Summer,
+            2 => Season::Autumn,
+            _ => Season::Winter,
+        }
+    }
+
+    pub fn first_instant(&self) -> Instant {
+        let minutes_since_start =
+            (self.index() * MINUTES_PER_DAY).saturating_sub(BEGINNING_TIME_OF_DAY * 60);
+        Instant::new(minutes_since_start * TICKS_PER_SIM_MINUTE as usize)
+    }
+
+    pub fn next(&self) -> Day {
+        Day(self.0 + 1)
+    }
+}
+
+impl From<Instant> for Day {
+    fn from(instant: Instant) -> Day {
+        Day::new(
+            (BEGINNING_TIME_OF_DAY * 60 + instant.ticks() / TICKS_PER_SIM_MINUTE as usize)
+                / MINUTES_PER_DAY,
+        )
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Date {
+    pub day: Day,
+    pub time_of_day: TimeOfDay,
+}
+
+impl From<Instant> for Date {
+    fn from(instant: Instant) -> Date {
+        Date {
+            day: instant.into(),
+            time_of_day: instant.into(),
+        }
+    }
+}
+
+impl Date {
+    pub fn weekday(&self) -> Weekday {
+        self.day.weekday()
+    }
+
+    // None if the date is before the simulation starts
+    pub fn instant(&self) -> Option<Instant> {
+        let (hours, minutes) = self.time_of_day.hours_minutes();
+        let minutes_since_start = (self.day.index() * MINUTES_PER_DAY + hours * 60 + minutes)
+            .checked_sub(BEGINNING_TIME_OF_DAY * 60)?;
+        Some(Instant::new(
+            minutes_since_start * TICKS_PER_SIM_MINUTE as usize,
+        ))
+    }
+
+    // Parses a day and a time of day, like "3 14:30"
+    pub fn parse(date: &str) -> Result<Date, String> {
+        let invalid = || format!("Expected a day and a time like \"3 14:30\", got \"{}\"", date);
+        let mut parts = date.split_whitespace();
+
+        let day = parts
+            .next()
+            .and_then(|day| day.parse::<usize>().ok())
+            .ok_or_else(invalid)?;
+        let mut hours_minutes = parts.next().ok_or_else(invalid)?.splitn(2, ':');
+        let hours = hours_minutes
+            .next()
+            .and_then(|hours| hours.parse::<usize>().ok())
+            .ok_or_else(invalid)?;
+        let minutes = hours_minutes
+            .next()
+            .and_then(|minutes| minutes.parse::<usize>().ok())
+            .ok_or_else(invalid)?;
+
+        if parts.next().is_some() || hours >= 24 || minutes >= 60 {
+            return Err(invalid());
+        }
+
+        Ok(Date {
+            day: Day::new(day),
+            time_of_day: TimeOfDay::new(hours, minutes),
+        })
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Weekdays(u8);
+
+impl Weekdays {
+    pub fn all() -> Weekdays {
+        Weekdays(0b111_1111)
+    }
+
+    pub fn workdays() -> Weekdays {
+        Weekdays::only(&[
+            Weekday::Monday,
+            Weekday::Tuesday,
+            Weekday::Wednesday,
+            Weekday::Thursday,
+            Weekday::Friday,
+        ])
+    }
+
+    pub fn weekend() -> Weekdays {
+        Weekdays::only(&[Weekday::Saturday, Weekday::Sunday])
+    }
+
+    pub fn only(weekdays: &[Weekday]) -> Weekdays {
+        Weekdays(
+            weekdays
+                .iter()
+                .fold(0, |bits, weekday| bits | 1 << weekday.index()),
+        )
+    }
+
+    pub fn contains(&self, weekday: Weekday) -> bool {
+        self.0 & (1 << weekday.index()) != 0
+    }
+}
+
+// A daily time range that only applies on some days of the week.
+// Ranges that wrap around midnight belong to the day they start on
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub struct WeeklyRange {
+    pub hours: TimeOfDayRange,
+    pub weekdays: Weekdays,
+}
+
+impl WeeklyRange {
+    pub fn new(hours: TimeOfDayRange, weekdays: Weekdays) -> WeeklyRange {
+        WeeklyRange { hours, weekdays }
+    }
+
+    pub fn every_day(hours: TimeOfDayRange) -> WeeklyRange {
+        WeeklyRange::new(hours, Weekdays::all())
+    }
+
+    pub fn contains<T: Into<Date>>(&self, date: T) -> bool {
+        let date = date.into();
+
+        if !self.hours.contains(date.time_of_day) {
+            return false;
+        }
+
+        let started_yesterday =
+            self.hours.start > self.hours.end && date.time_of_day < self.hours.start;
+
+        if started_yesterday {
+            self.weekdays.contains(date.weekday().previous())
+        } else {
+            self.weekdays.contains(date.weekday())
+        }
+    }
+}
+
+impl From<TimeOfDayRange> for WeeklyRange {
+    fn from(hours: TimeOfDayRange) -> WeeklyRange {
+        WeeklyRange::every_day(hours)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(day: usize, hours: usize, minutes: usize) -> Instant {
+        Date {
+            day: Day::new(day),
+            time_of_day: TimeOfDay::new(hours, minutes),
+        }.instant()
+            .expect("Should be after the simulation start")
+    }
+
+    #[test]
+    fn first_day_starts_at_beginning_time_of_day() {
+        assert_eq!(Day::from(Instant::new(0)), Day::new(0));
+        assert_eq!(
+            TimeOfDay::from(Instant::new(0)).hours_minutes(),
+            (BEGINNING_TIME_OF_DAY, 0)
+        );
+
+        // 17 hours until the first midnight
+        let first_midnight =
+            (MINUTES_PER_DAY - BEGINNING_TIME_OF_DAY * 60) * TICKS_PER_SIM_MINUTE as usize;
+        assert_eq!(Day::from(Instant::new(first_midnight - 1)), Day::new(0));
+        assert_eq!(Day::from(Instant::new(first_midnight)), Day::new(1));
+        assert_eq!(Day::new(1).first_instant(), Instant::new(first_midnight));
+        assert_eq!(Day::new(0).first_instant(), Instant::new(0));
+    }
+
+    #[test]
+    fn dates_round_trip_through_instants() {
+        let instant = at(3, 14, 30);
+        let date = Date::from(instant);
+        assert_eq!(date.day, Day::new(3));
+        assert_eq!(date.time_of_day.hours_minutes(), (14, 30));
+
+        let before_start = Date {
+            day: Day::new(0),
+            time_of_day: TimeOfDay::new(6, 59),
+        };
+        assert_eq!(before_start.instant(), None);
+    }
+
+    #[test]
+    fn weekdays_and_weeks_roll_over() {
+        assert_eq!(Day::new(0).weekday(), Weekday::Monday);
+        assert_eq!(Day::new(6).weekday(), Weekday::Sunday);
+        assert_eq!(Day::new(7).weekday(), Weekday::Monday);
+        assert_eq!(Day::new(6).week(), 0);
+        assert_eq!(Day::new(7).week(), 1);
+        assert_eq!(Weekday::Sunday.next(), Weekday::Monday);
+        assert_eq!(Weekday::Monday.previous(), Weekday::Sunday);
+        assert_eq!(Day::new(DAYS_PER_SEASON - 1).season(), Season::Spring);
+        assert_eq!(Day::new(DAYS_PER_SEASON).season(), Season::Summer);
+        assert_eq!(Day::new(4 * DAYS_PER_SEASON).season(), Season::Spring);
+    }
+
+    #[test]
+    fn parses_day_and_time() {
+        let date = Date::parse("3 14:30").expect("Should parse");
+        assert_eq!(date.day, Day::new(3));
+        assert_eq!(date.time_of_day.hours_minutes(), (14, 30));
+
+        assert!(Date::parse("3").is_err());
+        assert!(Date::parse("3 24:00").is_err());
+        assert!(Date::parse("3 12:60").is_err());
+        assert!(Date::parse("monday 12:00").is_err());
+        assert!(Date::parse("3 12:00 extra").is_err());
+    }
+
+    #[test]
+    fn ranges_crossing_midnight_belong_to_their_start_day() {
+        // Friday and Saturday nights, 22:00 to 2:00
+        let nights = WeeklyRange::new(
+            TimeOfDayRange::new(22, 0, 2, 0),
+            Weekdays::only(&[Weekday::Friday, Weekday::Saturday]),
+        );
+
+        // day 4 is a Friday
+        assert!(!nights.contains(at(4, 1, 0)));
+        assert!(nights.contains(at(4, 23, 0)));
+        assert!(nights.contains(at(5, 1, 0)));
+        assert!(nights.contains(at(5, 23, 0)));
+        assert!(nights.contains(at(6, 1, 0)));
+        assert!(!nights.contains(at(6, 23, 0)));
+        assert!(!nights.contains(at(7, 1, 0)));
+        assert!(!nights.contains(at(5, 12, 0)));
+    }
+
+    #[test]
+    fn workday_ranges_skip_the_weekend() {
+        let office = WeeklyRange::new(TimeOfDayRange::new(9, 0, 17, 0), Weekdays::workdays());
+
+        assert!(office.contains(at(4, 10, 0)));
+        assert!(!office.contains(at(5, 10, 0)));
+        assert!(!office.contains(at(6, 10, 0)));
+        assert!(office.contains(at(7, 10, 0)));
+        assert!(!office.contains(at(7, 18, 0)));
+    }
+}
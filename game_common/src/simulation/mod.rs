@@ -1,70 +1,163 @@
 use kay::{ActorSystem, World, TypedID};
-use compact::CVec;
+use compact::COption;
+use persistence::{SaveGameWriterID, SimulationState};
 
 mod time;
+mod calendar;
+mod sleepers;
 pub mod ui;
 
 pub use self::time::{Instant, Ticks, Duration, TICKS_PER_SIM_MINUTE, TICKS_PER_SIM_SECOND,
 TimeOfDay, TimeOfDayRange};
+pub use self::sleepers::{SleeperQueue, WakeUpTag, UNTAGGED, heap_push, heap_pop};
+pub use self::calendar::{Day, Date, Weekday, Weekdays, WeeklyRange, Season, DAYS_PER_WEEK,
+DAYS_PER_SEASON};
 
 pub trait Simulatable {
     fn tick(&mut self, dt: f32, current_instant: Instant, world: &mut World);
 }
 
 pub trait Sleeper {
-    fn wake(&mut self, current_instant: Instant, world: &mut World);
+    fn wake(&mut self, current_instant: Instant, tag: WakeUpTag, world: &mut World);
+}
+
+// Told when fast-forwarding starts and ends, so that the main loop
+// can skip frame pacing and networking in between
+pub trait FastForwardObserver {
+    fn on_fast_forward(&mut self, fast_forwarding: bool, world: &mut World);
 }
 
 #[derive(Compact, Clone)]
 pub struct Simulation {
     id: SimulationID,
     current_instant: Instant,
-    sleepers: CVec<(Instant, SleeperID)>,
+    sleepers: SleeperQueue,
     speed: u16,
+    paused: bool,
+    // while paused, we still run ticks until this instant (used for stepping)
+    run_until: COption<Instant>,
+    fast_forward_until: COption<Instant>,
 }
 
+const FAST_FORWARD_TICKS_PER_PROGRESS: usize = 10;
+
 impl Simulation {
     pub fn spawn(id: SimulationID, _: &mut World) -> Simulation {
         Simulation {
             id,
             current_instant: Instant::new(0),
-            sleepers: CVec::new(),
+            sleepers: SleeperQueue::new(),
             speed: 1,
+            paused: false,
+            run_until: COption(None),
+            fast_forward_until: COption(None),
         }
     }
 
     pub fn progress(&mut self, world: &mut World) {
-        for _ in 0..self.speed {
-            SimulatableID::global_broadcast(world).tick(
-                1.0 / (TICKS_PER_SIM_SECOND as f32),
-                self.current_instant,
-                world,
-            );
-            while self
-                .sleepers
-                .last()
-                .map(|&(end, _)| end < self.current_instant)
-                .unwrap_or(false)
-            {
-                let (_, sleeper) = self
-                    .sleepers
-                    .pop()
-                    .expect("just checked that there are sleepers");
-                sleeper.wake(self.current_instant, world);
+        if let Some(fast_forward_until) = *self.fast_forward_until {
+            let remaining_ticks = fast_forward_until
+                .ticks()
+                .saturating_sub(self.current_instant.ticks());
+
+            for _ in 0..remaining_ticks.min(FAST_FORWARD_TICKS_PER_PROGRESS) {
+                self.advance_one_tick(world);
+            }
+
+            if self.current_instant >= fast_forward_until {
+                println!("Fast-forwarded to {:?}", self.current_instant);
+                self.fast_forward_until = COption(None);
+                FastForwardObserverID::local_broadcast(world).on_fast_forward(false, world);
+            }
+        } else if let Some(run_until) = *self.run_until {
+            let remaining_ticks = run_until
+                .ticks()
+                .saturating_sub(self.current_instant.ticks());
+
+            for _ in 0..remaining_ticks.min(self.speed.max(1) as usize) {
+                self.advance_one_tick(world);
+            }
+
+            if self.current_instant >= run_until {
+                self.run_until = COption(None);
+                self.paused = true;
+            }
+        } else if !self.paused {
+            for _ in 0..self.speed {
+                self.advance_one_tick(world);
             }
-            self.current_instant += Ticks(1);
+        }
+    }
+
+    fn advance_one_tick(&mut self, world: &mut World) {
+        SimulatableID::global_broadcast(world).tick(
+            1.0 / (TICKS_PER_SIM_SECOND as f32),
+            self.current_instant,
+            world,
+        );
+        while let Some((sleeper, tag)) = self.sleepers.pop_due(self.current_instant) {
+            sleeper.wake(self.current_instant, tag, world);
+        }
+        self.current_instant += Ticks(1);
+        ::util::crash_report::note_simulation_state(self.current_instant, self.speed);
+    }
+
+    pub fn fast_forward_to(&mut self, until: Instant, world: &mut World) {
+        if until > self.current_instant {
+            self.fast_forward_until = COption(Some(until));
+            FastForwardObserverID::local_broadcast(world).on_fast_forward(true, world);
+        } else {
+            println!("Already past {:?}, not fast-forwarding", until);
         }
     }
 
     pub fn wake_up_in(&mut self, remaining_ticks: Ticks, sleeper_id: SleeperID, _: &mut World) {
         let wake_up_at = self.current_instant + remaining_ticks;
-        let maybe_idx = self
-            .sleepers
-            .binary_search_by_key(&wake_up_at.iticks(), |&(t, _)| -(t.iticks()));
-        let insert_idx = match maybe_idx {
-            Ok(idx) | Err(idx) => idx,
-        };
-        self.sleepers.insert(insert_idx, (wake_up_at, sleeper_id));
+        self.sleepers.schedule(wake_up_at, sleeper_id, UNTAGGED);
+    }
+
+    // A sleeper can hold several tagged wake-ups at once and cancel them by tag
+    pub fn wake_up_tagged_in(
+        &mut self,
+        remaining_ticks: Ticks,
+        sleeper_id: SleeperID,
+        tag: WakeUpTag,
+        _: &mut World,
+    ) {
+        let wake_up_at = self.current_instant + remaining_ticks;
+        self.sleepers.schedule(wake_up_at, sleeper_id, tag);
+    }
+
+    pub fn cancel_wake_up(&mut self, sleeper_id: SleeperID, tag: WakeUpTag, _: &mut World) {
+        self.sleepers.cancel(sleeper_id, tag);
+    }
+
+    pub fn cancel_all_wake_ups(&mut self, sleeper_id: SleeperID, _: &mut World) {
+        self.sleepers.cancel_all(sleeper_id);
+    }
+
+    pub fn save_to(&mut self, writer: SaveGameWriterID, world: &mut World) {
+        writer.on_simulation_state(
+            SimulationState {
+                current_instant: self.current_instant,
+                speed: self.speed,
+                paused: self.paused,
+                run_until: self.run_until.clone(),
+                fast_forward_until: self.fast_forward_until.clone(),
+            },
+            world,
+        );
+    }
+
+    pub fn restore(&mut self, state: &SimulationState, world: &mut World) {
+        // sleepers are not restored - restored actors register themselves again
+        self.current_instant = state.current_instant;
+        self.speed = state.speed;
+        self.paused = state.paused;
+        self.run_until = state.run_until.clone();
+        self.fast_forward_until = state.fast_forward_until.clone();
+        FastForwardObserverID::local_broadcast(world)
+            .on_fast_forward(self.fast_forward_until.is_some(), world);
     }
 }
 
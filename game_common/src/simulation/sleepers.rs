@@ -0,0 +1,237 @@
+use compact::{CVec, Compact};
+use super::{Instant, SleeperID};
+
+// Binary min-heap helpers that work directly on a CVec,
+// also used by the benchmark_sleepers example
+
+pub fn heap_push<T: Compact + Clone, K: Ord, F: Fn(&T) -> K>(heap: &mut CVec<T>, item: T, key: F) {
+    heap.push(item);
+    let mut idx = heap.len() - 1;
+
+    while idx > 0 {
+        let parent = (idx - 1) / 2;
+        if key(&heap[idx]) < key(&heap[parent]) {
+            heap.swap(idx, parent);
+            idx = parent;
+        } else {
+            break;
+        }
+    }
+}
+
+pub fn heap_pop<T: Compact + Clone, K: Ord, F: Fn(&T) -> K>(
+    heap: &mut CVec<T>,
+    key: F,
+) -> Option<T> {
+    if heap.is_empty() {
+        return None;
+    }
+
+    let last = heap.len() - 1;
+    heap.swap(0, last);
+    let popped = heap.pop();
+    sift_down(heap, 0, &key);
+    popped
+}
+
+fn sift_down<T, K: Ord, F: Fn(&T) -> K>(heap: &mut [T], mut idx: usize, key: &F) {
+    loop {
+        let left = 2 * idx + 1;
+        let right = left + 1;
+        let mut smallest = idx;
+
+        if left < heap.len() && key(&heap[left]) < key(&heap[smallest]) {
+            smallest = left;
+        }
+        if right < heap.len() && key(&heap[right]) < key(&heap[smallest]) {
+            smallest = right;
+        }
+
+        if smallest == idx {
+            break;
+        }
+
+        heap.swap(idx, smallest);
+        idx = smallest;
+    }
+}
+
+pub fn heapify<T, K: Ord, F: Fn(&T) -> K>(heap: &mut [T], key: F) {
+    for idx in (0..heap.len() / 2).rev() {
+        sift_down(heap, idx, &key);
+    }
+}
+
+// Lets sleepers tell apart (and cancel) several pending wake-ups
+pub type WakeUpTag = u32;
+pub const UNTAGGED: WakeUpTag = 0;
+
+#[derive(Copy, Clone)]
+pub struct WakeUp {
+    at: Instant,
+    // keeps wake-ups at the same instant in the order they were scheduled
+    sequence: u64,
+    sleeper: SleeperID,
+    tag: WakeUpTag,
+}
+
+fn wake_up_order(wake_up: &WakeUp) -> (Instant, u64) {
+    (wake_up.at, wake_up.sequence)
+}
+
+#[derive(Compact, Clone)]
+pub struct SleeperQueue {
+    wake_ups: CVec<WakeUp>,
+    next_sequence: u64,
+}
+
+impl SleeperQueue {
+    pub fn new() -> SleeperQueue {
+        SleeperQueue {
+            wake_ups: CVec::new(),
+            next_sequence: 0,
+        }
+    }
+
+    pub fn schedule(&mut self, at: Instant, sleeper: SleeperID, tag: WakeUpTag) {
+        let wake_up = WakeUp {
+            at,
+            sequence: self.next_sequence,
+            sleeper,
+            tag,
+        };
+        self.next_sequence += 1;
+        heap_push(&mut self.wake_ups, wake_up, wake_up_order);
+    }
+
+    // Cancelling is rare compared to scheduling, so it is allowed to be O(n)
+    pub fn cancel(&mut self, sleeper: SleeperID, tag: WakeUpTag) {
+        self.wake_ups
+            .retain(|wake_up| wake_up.sleeper != sleeper || wake_up.tag != tag);
+        heapify(&mut self.wake_ups, wake_up_order);
+    }
+
+    pub fn cancel_all(&mut self, sleeper: SleeperID) {
+        self.wake_ups.retain(|wake_up| wake_up.sleeper != sleeper);
+        heapify(&mut self.wake_ups, wake_up_order);
+    }
+
+    // Wake-ups are due once their instant has passed
+    pub fn pop_due(&mut self, current_instant: Instant) -> Option<(SleeperID, WakeUpTag)> {
+        let is_due = self
+            .wake_ups
+            .first()
+            .map(|wake_up| wake_up.at < current_instant)
+            .unwrap_or(false);
+
+        if is_due {
+            heap_pop(&mut self.wake_ups, wake_up_order)
+                .map(|wake_up| (wake_up.sleeper, wake_up.tag))
+        } else {
+            None
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.wake_ups.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.wake_ups.is_empty()
+    }
+}
+
+impl Default for SleeperQueue {
+    fn default() -> SleeperQueue {
+        SleeperQueue::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kay::{RawID, ShortTypeId, MachineID, TypedID};
+
+    fn sleeper(instance_id: u32) -> SleeperID {
+        SleeperID::from_raw(RawID::new(ShortTypeId::new(0), instance_id, MachineID(0), 0))
+    }
+
+    fn pop_all_due(queue: &mut SleeperQueue, at: usize) -> Vec<(SleeperID, WakeUpTag)> {
+        let mut due = Vec::new();
+        while let Some(wake_up) = queue.pop_due(Instant::new(at)) {
+            due.push(wake_up);
+        }
+        due
+    }
+
+    #[test]
+    fn heap_pops_in_key_order() {
+        let mut heap = CVec::new();
+        for item in &[5u32, 1, 4, 1, 3, 9, 2, 6] {
+            heap_push(&mut heap, *item, |&item| item);
+        }
+
+        let mut popped = Vec::new();
+        while let Some(item) = heap_pop(&mut heap, |&item| item) {
+            popped.push(item);
+        }
+
+        assert_eq!(popped, vec![1, 1, 2, 3, 4, 5, 6, 9]);
+    }
+
+    #[test]
+    fn wake_ups_are_due_in_order_of_instant_then_scheduling() {
+        let mut queue = SleeperQueue::new();
+        queue.schedule(Instant::new(20), sleeper(1), UNTAGGED);
+        queue.schedule(Instant::new(10), sleeper(2), UNTAGGED);
+        queue.schedule(Instant::new(20), sleeper(3), UNTAGGED);
+        queue.schedule(Instant::new(10), sleeper(4), UNTAGGED);
+
+        // only due once their instant has passed
+        assert!(pop_all_due(&mut queue, 10).is_empty());
+        assert_eq!(
+            pop_all_due(&mut queue, 11),
+            vec![(sleeper(2), UNTAGGED), (sleeper(4), UNTAGGED)]
+        );
+        assert_eq!(
+            pop_all_due(&mut queue, 21),
+            vec![(sleeper(1), UNTAGGED), (sleeper(3), UNTAGGED)]
+        );
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn sleepers_can_hold_several_tagged_wake_ups() {
+        let mut queue = SleeperQueue::new();
+        queue.schedule(Instant::new(30), sleeper(1), 2);
+        queue.schedule(Instant::new(10), sleeper(1), 1);
+        queue.schedule(Instant::new(20), sleeper(1), 1);
+
+        assert_eq!(pop_all_due(&mut queue, 11), vec![(sleeper(1), 1)]);
+        assert_eq!(pop_all_due(&mut queue, 21), vec![(sleeper(1), 1)]);
+        assert_eq!(pop_all_due(&mut queue, 31), vec![(sleeper(1), 2)]);
+    }
+
+    #[test]
+    fn cancelling_only_removes_matching_wake_ups() {
+        let mut queue = SleeperQueue::new();
+        for at in 0..10 {
+            queue.schedule(Instant::new(at * 10), sleeper(1), 1);
+            queue.schedule(Instant::new(at * 10 + 5), sleeper(1), 2);
+            queue.schedule(Instant::new(at * 10 + 5), sleeper(2), 1);
+        }
+
+        queue.cancel(sleeper(1), 1);
+        assert_eq!(queue.len(), 20);
+
+        let due = pop_all_due(&mut queue, 1000);
+        assert_eq!(due.len(), 20);
+        assert!(!due.contains(&(sleeper(1), 1)));
+
+        queue.schedule(Instant::new(0), sleeper(1), 1);
+        queue.schedule(Instant::new(0), sleeper(1), 2);
+        queue.schedule(Instant::new(0), sleeper(2), 1);
+        queue.cancel_all(sleeper(1));
+        assert_eq!(pop_all_due(&mut queue, 1000), vec![(sleeper(2), 1)]);
+    }
+}
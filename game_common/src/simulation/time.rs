@@ -102,13 +102,13 @@ impl<D: Into<Ticks>> ::std::ops::SubAssign<D> for Instant {
     }
 }
 
-#[derive(Copy, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug, Serialize, Deserialize)]
 pub struct TimeOfDay {
     minutes_of_day: u16,
 }
 
-const BEGINNING_TIME_OF_DAY: usize = 7;
-const MINUTES_PER_DAY: usize = 60 * 24;
+pub const BEGINNING_TIME_OF_DAY: usize = 7;
+pub const MINUTES_PER_DAY: usize = 60 * 24;
 
 impl TimeOfDay {
     pub fn new(h: usize, m: usize) -> Self {
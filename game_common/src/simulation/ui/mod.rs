@@ -1,23 +1,55 @@
-use kay::World;
-use super::{Simulation, SimulationID};
+use kay::{World, TypedID};
+use compact::COption;
+use super::{Simulation, SimulationID, Instant, Ticks};
+use journal::{JournalID, JournaledCommand};
 
 pub trait SimulationUI {
     fn on_simulation_info(
         &mut self,
         current_instant: ::simulation::Instant,
         speed: u16,
+        paused: bool,
         _world: &mut World,
     );
 }
 
 impl Simulation {
     pub fn get_info(&mut self, requester: SimulationUIID, world: &mut World) {
-        requester.on_simulation_info(self.current_instant, self.speed, world);
+        requester.on_simulation_info(self.current_instant, self.speed, self.paused, world);
     }
 
-    pub fn set_speed(&mut self, speed: u16, _world: &mut World) {
+    pub fn set_speed(&mut self, speed: u16, world: &mut World) {
+        JournalID::local_first(world).record(JournaledCommand::SetSpeed(speed), world);
         self.speed = speed as u16;
     }
+
+    pub fn pause(&mut self, world: &mut World) {
+        JournalID::local_first(world).record(JournaledCommand::Pause, world);
+        self.paused = true;
+        self.run_until = COption(None);
+    }
+
+    pub fn resume(&mut self, world: &mut World) {
+        JournalID::local_first(world).record(JournaledCommand::Resume, world);
+        self.paused = false;
+        self.run_until = COption(None);
+    }
+
+    // Runs exactly n_ticks more ticks and pauses afterwards
+    pub fn step(&mut self, n_ticks: u32, world: &mut World) {
+        let until = self.current_instant + Ticks(n_ticks);
+        self.run_until(until, world);
+    }
+
+    // Runs at the current speed until the given instant and pauses there
+    pub fn run_until(&mut self, until: Instant, world: &mut World) {
+        JournalID::local_first(world).record(JournaledCommand::RunUntil(until), world);
+        if until > self.current_instant {
+            self.run_until = COption(Some(until));
+        } else {
+            self.paused = true;
+        }
+    }
 }
 
 pub mod kay_auto;
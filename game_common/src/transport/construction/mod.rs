@@ -16,6 +16,16 @@ IntersectionPrototype};
 
 use dimensions::{LANE_CONNECTION_TOLERANCE, MAX_SWITCHING_LANE_DISTANCE,
 MIN_SWITCHING_LANE_LENGTH};
+use simulation::Duration;
+use economy::resources::ResourceAmount;
+
+pub const SECONDS_PER_LANE_METER: usize = 60;
+pub const SECONDS_PER_DESTROYED_LANE_METER: usize = 10;
+pub const HOURS_PER_INTERSECTION: usize = 8;
+
+pub const COST_PER_LANE_METER: ResourceAmount = 100.0;
+pub const COST_PER_DESTROYED_LANE_METER: ResourceAmount = 20.0;
+pub const COST_PER_INTERSECTION: ResourceAmount = 5_000.0;
 
 impl RoadPrototype {
     pub fn construct(&self, report_to: ConstructionID, world: &mut World) -> CVec<ConstructableID> {
@@ -59,6 +69,49 @@ impl RoadPrototype {
             RoadPrototype::PavedArea(_) => CVec::new(),
         }
     }
+
+    fn lane_length(&self) -> f32 {
+        match *self {
+            RoadPrototype::Lane(LanePrototype(ref path, _))
+            | RoadPrototype::SwitchLane(SwitchLanePrototype(ref path)) => path.length(),
+            RoadPrototype::Intersection(IntersectionPrototype {
+                ref connecting_lanes,
+                ..
+            }) => connecting_lanes
+                .values()
+                .flat_map(|group| group.iter())
+                .map(|&LanePrototype(ref path, _)| path.length())
+                .sum(),
+            RoadPrototype::PavedArea(_) => 0.0,
+        }
+    }
+
+    pub fn construction_time(&self) -> Duration {
+        let lane_seconds = self.lane_length() as usize * SECONDS_PER_LANE_METER;
+        let intersection_hours = if let RoadPrototype::Intersection(_) = *self {
+            HOURS_PER_INTERSECTION
+        } else {
+            0
+        };
+        Duration::from_seconds(lane_seconds) + Duration::from_hours(intersection_hours)
+    }
+
+    pub fn destruction_time(&self) -> Duration {
+        Duration::from_seconds(self.lane_length() as usize * SECONDS_PER_DESTROYED_LANE_METER)
+    }
+
+    pub fn construction_cost(&self) -> ResourceAmount {
+        let intersection_cost = if let RoadPrototype::Intersection(_) = *self {
+            COST_PER_INTERSECTION
+        } else {
+            0.0
+        };
+        self.lane_length() * COST_PER_LANE_METER + intersection_cost
+    }
+
+    pub fn destruction_cost(&self) -> ResourceAmount {
+        self.lane_length() * COST_PER_DESTROYED_LANE_METER
+    }
 }
 
 impl Constructable for Lane {
@@ -69,6 +122,9 @@ impl Constructable for Lane {
         self.unbuild(report_to, world);
         Fate::Live
     }
+    fn finish_construction(&mut self, _: &mut World) {
+        self.construction.finished = true;
+    }
 }
 
 impl Constructable for SwitchLane {
@@ -79,13 +135,17 @@ impl Constructable for SwitchLane {
         self.unbuild(report_to, world);
         Fate::Live
     }
+    fn finish_construction(&mut self, _: &mut World) {
+        self.construction.finished = true;
+    }
 }
 
 #[derive(Compact, Clone)]
 pub struct ConstructionInfo {
     pub length: f32,
     pub path: LinePath,
-    pub progress: f32,
+    // unfinished lanes don't take part in routing yet
+    pub finished: bool,
     unbuilding_for: Option<ConstructionID>,
     disconnects_remaining: u8,
 }
@@ -95,7 +155,7 @@ impl ConstructionInfo {
         ConstructionInfo {
             length: path.length(),
             path,
-            progress: 0.0,
+            finished: false,
             unbuilding_for: None,
             disconnects_remaining: 0,
         }
@@ -460,7 +520,7 @@ impl Lane {
         world: &mut World,
     ) {
         if let Some(location) = self.pathfinding.location {
-            if !self.connectivity.on_intersection {
+            if !self.connectivity.on_intersection && self.construction.finished {
                 let path = &self.construction.path;
                 let distance = path.distance_to(lot_position);
 
@@ -250,10 +250,9 @@ impl Lane {
 
 impl Simulatable for Lane {
     fn tick(&mut self, dt: f32, current_instant: Instant, world: &mut World) {
+        let _measuring = ::util::profiling::measure("Lane::tick");
         let dt = dt / MICROTRAFFIC_UNREALISTIC_SLOWDOWN;
 
-        self.construction.progress += dt * 400.0;
-
         let do_traffic = current_instant.ticks() % TRAFFIC_LOGIC_THROTTLING
             == self.id.as_raw().instance_id as usize % TRAFFIC_LOGIC_THROTTLING;
 
@@ -295,8 +294,9 @@ impl Simulatable for Lane {
             }
         }
 
-        if current_instant.ticks() % PATHFINDING_THROTTLING
-            == self.id.as_raw().instance_id as usize % PATHFINDING_THROTTLING
+        if self.construction.finished
+            && current_instant.ticks() % PATHFINDING_THROTTLING
+                == self.id.as_raw().instance_id as usize % PATHFINDING_THROTTLING
         {
             self.update_routes(world);
         }
@@ -547,10 +547,9 @@ impl LaneLike for SwitchLane {
 
 impl Simulatable for SwitchLane {
     fn tick(&mut self, dt: f32, current_instant: Instant, world: &mut World) {
+        let _measuring = ::util::profiling::measure("SwitchLane::tick");
         let dt = dt / MICROTRAFFIC_UNREALISTIC_SLOWDOWN;
 
-        self.construction.progress += dt * 400.0;
-
         let do_traffic = current_instant.ticks() % TRAFFIC_LOGIC_THROTTLING
             == self.id.as_raw().instance_id as usize % TRAFFIC_LOGIC_THROTTLING;
 
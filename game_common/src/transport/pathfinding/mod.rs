@@ -243,6 +243,10 @@ impl Node for Lane {
     }
 
     fn query_routes(&mut self, requester: NodeID, is_switch: bool, world: &mut World) {
+        if !self.construction.finished {
+            return;
+        }
+
         let self_cost = if is_switch {
             0.0
         } else {
@@ -377,6 +381,10 @@ impl Node for Lane {
         hops_from_landmark: u8,
         world: &mut World,
     ) {
+        if !self.construction.finished {
+            return;
+        }
+
         let join = self
             .pathfinding
             .location
@@ -522,6 +530,10 @@ impl Node for SwitchLane {
     fn update_routes(&mut self, _: &mut World) {}
 
     fn query_routes(&mut self, requester: NodeID, _is_switch: bool, world: &mut World) {
+        if !self.construction.finished {
+            return;
+        }
+
         // TODO: ugly: untyped RawID shenanigans
         let requester_lane = LaneID::from_raw(requester.as_raw());
         if let Some(other_lane) = self.other_side(requester_lane) {
@@ -575,6 +587,10 @@ impl Node for SwitchLane {
         hops_from_landmark: u8,
         world: &mut World,
     ) {
+        if !self.construction.finished {
+            return;
+        }
+
         let from_lane = LaneID::from_raw(from.as_raw());
         if let Some(other_lane) = self.other_side(from_lane) {
             let other_lane: NodeID = other_lane.into();
@@ -161,7 +161,7 @@ impl LocationRequester for Trip {
     }
 }
 
-use simulation::{SimulationID, Sleeper, SleeperID};
+use simulation::{SimulationID, Sleeper, SleeperID, WakeUpTag};
 use simulation::Ticks;
 use super::super::microtraffic::{LaneLikeID, LaneCar, Obstacle};
 
@@ -205,7 +205,8 @@ impl TripCreator {
 use rand::Rng;
 
 impl Sleeper for TripCreator {
-    fn wake(&mut self, current_instant: Instant, world: &mut World) {
+    fn wake(&mut self, current_instant: Instant, _: WakeUpTag, world: &mut World) {
+        let _measuring = ::util::profiling::measure("TripCreator::wake");
         ::rand::thread_rng().shuffle(&mut self.lanes);
 
         for mut pair in &self.lanes.iter().chunks(2) {
@@ -1,11 +1,11 @@
 use compact::{CHashMap, CVec};
 use descartes::{N, P2, V2, Band, LinePath, ClosedLinePath, Area, Intersect, WithUniqueOrthogonal,
-RoughEq, PointContainer, AreaError, ArcOrLineSegment, Segment};
+RoughEq, PointContainer, ArcOrLineSegment, Segment};
 use itertools::Itertools;
 use ordered_float::OrderedFloat;
 
 use planning::{VersionedGesture, StepID, PrototypeID, PlanHistory, PlanResult,
-GestureIntent, Prototype, PrototypeKind, GestureID};
+GestureIntent, Prototype, PrototypeKind, GestureID, PlanError};
 
 mod intersection_connections;
 mod smooth_path;
@@ -125,7 +125,7 @@ impl GestureSideID {
 
 #[derive(Compact, Clone, Serialize, Deserialize, Debug)]
 pub struct IntersectionPrototype {
-    area: Area,
+    pub area: Area,
     incoming: CHashMap<GestureSideID, CVec<IntersectionConnector>>,
     outgoing: CHashMap<GestureSideID, CVec<IntersectionConnector>>,
     pub connecting_lanes: CHashMap<(GestureSideID, GestureSideID), CVec<LanePrototype>>,
@@ -177,7 +177,7 @@ fn gesture_intent_smooth_paths(
 pub fn calculate_prototypes(
     history: &PlanHistory,
     _current_result: &PlanResult,
-) -> Result<Vec<Prototype>, AreaError> {
+) -> Result<Vec<Prototype>, PlanError> {
     let gesture_intent_smooth_paths = gesture_intent_smooth_paths(history);
 
     let gesture_areas_for_intersection = gesture_intent_smooth_paths
@@ -0,0 +1,110 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+
+use simulation::Instant;
+use journal::JournalEntry;
+
+pub const CRASH_REPORT_VERSION: u32 = 1;
+const N_RECENT_COMMANDS: usize = 50;
+
+// What we know about the simulation right before a crash.
+// Kept up to date by the actors themselves, since they can't be asked anymore
+// once a panic is underway
+#[derive(Default)]
+struct CrashContext {
+    current_instant: Option<Instant>,
+    speed: u16,
+    recent_commands: VecDeque<JournalEntry>,
+    crashed: bool,
+}
+
+// All actors of one machine are handled on the same thread as the panic hook
+thread_local! {
+    static CONTEXT: RefCell<CrashContext> = RefCell::new(CrashContext::default());
+}
+
+pub fn note_simulation_state(current_instant: Instant, speed: u16) {
+    CONTEXT.with(|context| {
+        if let Ok(mut context) = context.try_borrow_mut() {
+            context.current_instant = Some(current_instant);
+            context.speed = speed;
+        }
+    })
+}
+
+pub fn note_command(entry: &JournalEntry) {
+    CONTEXT.with(|context| {
+        if let Ok(mut context) = context.try_borrow_mut() {
+            if context.recent_commands.len() == N_RECENT_COMMANDS {
+                context.recent_commands.pop_front();
+            }
+            context.recent_commands.push_back(entry.clone());
+        }
+    })
+}
+
+pub fn crash_happened() -> bool {
+    CONTEXT.with(|context| {
+        context
+            .try_borrow()
+            .map(|context| context.crashed)
+            .unwrap_or(false)
+    })
+}
+
+#[derive(Serialize)]
+pub struct CrashReport {
+    pub version: u32,
+    pub citybound_version: String,
+    pub message: String,
+    pub location: String,
+    pub backtrace: String,
+    pub current_instant: Option<Instant>,
+    pub speed: u16,
+    pub recent_commands: Vec<JournalEntry>,
+}
+
+impl CrashReport {
+    // Marks the simulation as crashed, so the main loop can attempt an emergency save
+    pub fn collect(message: String, location: String, backtrace: String) -> CrashReport {
+        CONTEXT.with(|context| {
+            let (current_instant, speed, recent_commands) = match context.try_borrow_mut() {
+                Ok(mut context) => {
+                    context.crashed = true;
+                    (
+                        context.current_instant,
+                        context.speed,
+                        context.recent_commands.iter().cloned().collect(),
+                    )
+                }
+                Err(_) => (None, 0, Vec::new()),
+            };
+
+            CrashReport {
+                version: CRASH_REPORT_VERSION,
+                citybound_version: ::ENV_VERSION.to_owned(),
+                message,
+                location,
+                backtrace,
+                current_instant,
+                speed,
+                recent_commands,
+            }
+        })
+    }
+
+    pub fn write(&self) -> Result<PathBuf, String> {
+        let mut path = ::std::env::temp_dir();
+        path.push("cb_last_crash.json");
+
+        let serialized = ::serde_json::to_string_pretty(self).map_err(|err| err.to_string())?;
+        let mut file = File::create(&path).map_err(|err| err.to_string())?;
+        file.write_all(serialized.as_bytes())
+            .map_err(|err| err.to_string())?;
+
+        Ok(path)
+    }
+}
@@ -19,10 +19,9 @@ pub fn ensure_crossplatform_proper_thread<F: Fn() -> () + Send + 'static>(callba
 
 use std::panic::{set_hook, PanicInfo};
 use backtrace::Backtrace;
-use std::fs::File;
-use std::io::Write;
+use super::crash_report::CrashReport;
 
-pub fn set_error_hook() {
+pub fn set_error_hook(open_report: bool) {
     let callback: Box<FnMut(&PanicInfo)> = Box::new(move |panic_info| {
         let title = "SIMULATION BROKE :(";
 
@@ -50,25 +49,22 @@ pub fn set_error_hook() {
                             BUGS:\nhttps://github.\
                             com/citybound/citybound/blob/master/CONTRIBUTING.md#reporting-bugs";
 
-        let mut error_file_path = ::std::env::temp_dir();
-        error_file_path.push("cb_last_error.txt");
+        println!("{}\n\n{}\n\n{}", title, body, report_guide);
 
-        println!(
-            "{}\n\n{}\n\nALSO SEE {:?} (AUTO-OPENED)",
-            title, body, error_file_path
-        );
+        let report = CrashReport::collect(message, location, format!("{:?}", backtrace));
 
-        {
-            if let Ok(mut file) = File::create(&error_file_path) {
-                let file_content = format!("{}\n\n{}\n\n{}", title, report_guide, body);
-                let file_content = file_content.replace("\n", "\r\n");
+        match report.write() {
+            Ok(report_path) => {
+                println!("CRASH REPORT WRITTEN TO {:?}", report_path);
 
-                file.write_all(file_content.as_bytes())
-                    .expect("Error writing error file, lol");
-            };
+                if open_report {
+                    if let Err(err) = open::that(&report_path) {
+                        println!("Couldn't open crash report: {}", err);
+                    }
+                }
+            }
+            Err(err) => println!("Couldn't write crash report: {}", err),
         }
-
-        open::that(error_file_path).expect("Couldn't open error file");
     });
 
     set_hook(unsafe { ::std::mem::transmute(callback) });
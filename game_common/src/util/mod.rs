@@ -3,3 +3,6 @@ pub mod disjoint_sets;
 pub mod grid_accelerator;
 pub mod async_counter;
 pub mod random;
+pub mod profiling;
+pub mod crash_report;
+pub mod numeric_id;
@@ -0,0 +1,7 @@
+use kay::RawID;
+
+// Instance ids are only unique per actor type, so the type is folded in as well.
+// Stable for as long as the actor lives, which makes it usable in URLs and answers.
+pub fn numeric_id(raw: RawID) -> u64 {
+    (raw.type_id.as_usize() as u64) << 32 | u64::from(raw.instance_id)
+}
@@ -0,0 +1,72 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
+use std::time::{Duration, Instant};
+
+// Opt-in, since measuring every tick and wake-up is not free
+static ENABLED: AtomicBool = ATOMIC_BOOL_INIT;
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+#[derive(Copy, Clone, Default)]
+pub struct Measurement {
+    pub count: usize,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl Measurement {
+    pub fn add(&mut self, other: &Measurement) {
+        self.count += other.count;
+        self.total += other.total;
+        self.max = self.max.max(other.max);
+    }
+}
+
+// All actors of one machine are handled on the same thread
+thread_local! {
+    static MEASUREMENTS: RefCell<HashMap<&'static str, Measurement>> =
+        RefCell::new(HashMap::new());
+}
+
+pub struct Measuring {
+    started: Option<(&'static str, Instant)>,
+}
+
+impl Drop for Measuring {
+    fn drop(&mut self) {
+        if let Some((label, started)) = self.started {
+            let elapsed = started.elapsed();
+            MEASUREMENTS.with(|measurements| {
+                let mut measurements = measurements.borrow_mut();
+                let measurement = measurements.entry(label).or_insert_with(Measurement::default);
+                measurement.count += 1;
+                measurement.total += elapsed;
+                measurement.max = measurement.max.max(elapsed);
+            });
+        }
+    }
+}
+
+// Measures until the returned value is dropped, labels look like "Lane::tick"
+pub fn measure(label: &'static str) -> Measuring {
+    Measuring {
+        started: if is_enabled() {
+            Some((label, Instant::now()))
+        } else {
+            None
+        },
+    }
+}
+
+pub fn take_measurements() -> HashMap<&'static str, Measurement> {
+    MEASUREMENTS.with(|measurements| {
+        ::std::mem::replace(&mut *measurements.borrow_mut(), HashMap::new())
+    })
+}
@@ -42,3 +42,16 @@ pub fn seed<S: Hash>(seed: S) -> FnvRng {
 pub fn uuid() -> Uuid {
     Uuid::from_random_bytes(thread_rng().gen())
 }
+
+// Deterministic counterpart to uuid(), for ids that need to be reproducible
+pub fn uuid_from_seed<S: Hash>(seed_value: S) -> Uuid {
+    let mut rng = seed(seed_value);
+    let mut bytes = [0u8; 16];
+    for chunk in bytes.chunks_mut(8) {
+        let random = rng.next_u64();
+        for (i, byte) in chunk.iter_mut().enumerate() {
+            *byte = (random >> (8 * i)) as u8;
+        }
+    }
+    Uuid::from_random_bytes(bytes)
+}
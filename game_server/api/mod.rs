@@ -0,0 +1,628 @@
+use kay::{World, ActorSystem, Actor, External, Fate, MachineID, TypedID};
+use compact::{CVec, CString, COption, CHashMap};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::sync::mpsc::{channel, Sender, Receiver};
+use std::time::{Duration, Instant as WallInstant};
+use rouille::{Request, Response};
+use serde::Serialize;
+
+use simulation::{Instant, TimeOfDay, Day, Weekday, SimulationID};
+use simulation::ui::{SimulationUI, SimulationUIID};
+use land_use::buildings::{BuildingID, BuildingStyle};
+use land_use::zone_planning::Lot;
+use land_use::ui::{LandUseUI, LandUseUIID};
+use economy::households::{HouseholdID, HouseholdCore};
+use economy::households::ui::{HouseholdUI, HouseholdUIID};
+use planning::{PlanManagerID, ProposalID, PlanHistoryUpdate, ProposalUpdate, PlanResultUpdate,
+ActionGroups, GestureID, PlanError};
+use planning::ui::{PlanningUI, PlanningUIID};
+use planning::estimate::ProposalEstimate;
+use planning::permissions::PlanningAction;
+use planning::voting::{ProposalStatus, Ballot};
+use planning::checkpoints::PlanDiff;
+use economy::resources::ResourceAmount;
+use planning::exchange::{ProposalFile, ProposalFileRecipient, ProposalListRecipient};
+use planning::exchange::geojson::{GeoProjection, GeoJSONExporterID, GeoJSONRecipient};
+use construction::events::{ConstructionEvent, ConstructionEventObserver,
+ConstructionEventObserverID};
+use profiler::LatestReport;
+use util::numeric_id::numeric_id;
+use util::random::Uuid;
+
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+const N_RECENT_CONSTRUCTION_EVENTS: usize = 200;
+// a broadcast is answered within one round of messages,
+// so a building or household that hasn't answered by then doesn't exist
+const FRAMES_UNTIL_NOT_FOUND: usize = 2;
+
+pub enum Query {
+    Simulation,
+    Building(u64),
+    Household(u64),
+    GeoJSON,
+    Construction,
+    Proposals,
+    Proposal(ProposalID),
+    Checkpoints,
+}
+
+pub enum Answer {
+    Found(String),
+    NotFound(String),
+}
+
+pub struct PendingQuery {
+    query: Query,
+    respond_to: Sender<Answer>,
+}
+
+pub type QuerySender = Mutex<Sender<PendingQuery>>;
+
+pub fn query_channel() -> (QuerySender, Receiver<PendingQuery>) {
+    let (sender, receiver) = channel();
+    (Mutex::new(sender), receiver)
+}
+
+// Runs on the HTTP server thread, waits for the simulation thread to answer
+pub fn handle_request(
+    request: &Request,
+    queries: &QuerySender,
+    latest_profile: &LatestReport,
+) -> Option<Response> {
+    if !request.url().starts_with("/api/") {
+        return None;
+    }
+
+    let url = request.url();
+
+    if url == "/api/profile" {
+        // collected by the main loop, no need to ask the simulation
+        return Some(
+            match *latest_profile
+                .lock()
+                .expect("Latest profile should be available")
+            {
+                Some(ref json) => Response::from_data("application/json", json.clone()),
+                None => {
+                    Response::text("No profile yet, start with --profile").with_status_code(404)
+                }
+            },
+        );
+    }
+
+    let mut path = url["/api/".len()..].splitn(2, '/');
+
+    let maybe_query = match (path.next(), path.next()) {
+        (Some("simulation"), None) => Some(Query::Simulation),
+        (Some("buildings"), Some(id)) => id.parse().ok().map(Query::Building),
+        (Some("households"), Some(id)) => id.parse().ok().map(Query::Household),
+        (Some("geojson"), None) => Some(Query::GeoJSON),
+        (Some("construction"), None) => Some(Query::Construction),
+        (Some("proposals"), None) => Some(Query::Proposals),
+        (Some("proposals"), Some(id)) => Uuid::parse_str(id)
+            .ok()
+            .map(|uuid| Query::Proposal(ProposalID(uuid))),
+        (Some("checkpoints"), None) => Some(Query::Checkpoints),
+        _ => None,
+    };
+
+    Some(if let Some(query) = maybe_query {
+        let (respond_to, response) = channel();
+
+        queries
+            .lock()
+            .expect("Query sender should be available")
+            .send(PendingQuery { query, respond_to })
+            .expect("Simulation should be receiving queries");
+
+        match response.recv_timeout(QUERY_TIMEOUT) {
+            Ok(Answer::Found(json)) => Response::from_data("application/json", json),
+            Ok(Answer::NotFound(message)) => Response::text(message).with_status_code(404),
+            Err(_) => Response::text("Simulation didn't answer in time").with_status_code(504),
+        }
+    } else {
+        Response::text(format!("Unknown query: {}", url)).with_status_code(404)
+    })
+}
+
+#[derive(Compact, Clone)]
+pub struct QueryAPI {
+    id: QueryAPIID,
+    state: External<QueryAPINonPersistedState>,
+}
+
+impl ::std::ops::Deref for QueryAPI {
+    type Target = QueryAPINonPersistedState;
+
+    fn deref(&self) -> &QueryAPINonPersistedState {
+        &self.state
+    }
+}
+
+impl ::std::ops::DerefMut for QueryAPI {
+    fn deref_mut(&mut self) -> &mut QueryAPINonPersistedState {
+        &mut self.state
+    }
+}
+
+pub struct Waiting {
+    respond_to: Sender<Answer>,
+    since: WallInstant,
+    frames: usize,
+}
+
+impl Waiting {
+    fn new(respond_to: Sender<Answer>) -> Waiting {
+        Waiting {
+            respond_to,
+            since: WallInstant::now(),
+            frames: 0,
+        }
+    }
+
+    fn timed_out(&self) -> bool {
+        self.since.elapsed() > QUERY_TIMEOUT
+    }
+}
+
+pub struct QueryAPINonPersistedState {
+    incoming: Receiver<PendingQuery>,
+    waiting_for_simulation: Vec<Waiting>,
+    waiting_for_buildings: HashMap<u64, Vec<Waiting>>,
+    waiting_for_households: HashMap<u64, Vec<Waiting>>,
+    waiting_for_geojson: Vec<Waiting>,
+    // an export that doesn't finish in time is given up on, so a new one can start
+    geojson_export_started: Option<WallInstant>,
+    waiting_for_proposal_list: Vec<Waiting>,
+    waiting_for_proposals: HashMap<ProposalID, Vec<Waiting>>,
+    waiting_for_checkpoints: Vec<Waiting>,
+    // oldest first
+    recent_construction_events: VecDeque<ConstructionEvent>,
+    geo_projection: GeoProjection,
+}
+
+impl QueryAPINonPersistedState {
+    pub fn new(
+        incoming: Receiver<PendingQuery>,
+        geo_projection: GeoProjection,
+    ) -> QueryAPINonPersistedState {
+        QueryAPINonPersistedState {
+            incoming,
+            waiting_for_simulation: Vec::new(),
+            waiting_for_buildings: HashMap::new(),
+            waiting_for_households: HashMap::new(),
+            waiting_for_geojson: Vec::new(),
+            geojson_export_started: None,
+            waiting_for_proposal_list: Vec::new(),
+            waiting_for_proposals: HashMap::new(),
+            waiting_for_checkpoints: Vec::new(),
+            recent_construction_events: VecDeque::new(),
+            geo_projection,
+        }
+    }
+}
+
+fn respond<T: Serialize>(waiting: Vec<Waiting>, answer: &T) {
+    let json = ::serde_json::to_string(answer).expect("Should be able to serialize answer");
+    respond_with_json(waiting, &json);
+}
+
+fn respond_with_json(waiting: Vec<Waiting>, json: &str) {
+    for Waiting { respond_to, .. } in waiting {
+        // the HTTP request might have timed out already
+        let _ = respond_to.send(Answer::Found(json.to_owned()));
+    }
+}
+
+// Answers lookups that nobody responded to with "not found" and forgets them
+fn expire_lookups(waiting_by_number: &mut HashMap<u64, Vec<Waiting>>, kind: &str) {
+    for waiting in waiting_by_number.values_mut() {
+        for waiter in waiting.iter_mut() {
+            waiter.frames += 1;
+        }
+    }
+
+    let not_found = waiting_by_number
+        .iter()
+        .filter(|&(_, waiting)| {
+            waiting.iter().all(|waiter| waiter.frames > FRAMES_UNTIL_NOT_FOUND)
+        })
+        .map(|(number, _)| *number)
+        .collect::<Vec<_>>();
+
+    for number in not_found {
+        if let Some(waiting) = waiting_by_number.remove(&number) {
+            let message = format!("No {} with id {}", kind, number);
+            for Waiting { respond_to, .. } in waiting {
+                let _ = respond_to.send(Answer::NotFound(message.clone()));
+            }
+        }
+    }
+}
+
+fn expire_timed_out(waiting: &mut Vec<Waiting>) {
+    waiting.retain(|waiter| !waiter.timed_out());
+}
+
+impl QueryAPI {
+    pub fn spawn(
+        id: QueryAPIID,
+        state: &External<QueryAPINonPersistedState>,
+        _: &mut World,
+    ) -> QueryAPI {
+        QueryAPI {
+            id,
+            state: state.steal(),
+        }
+    }
+
+    pub fn process_queries(&mut self, world: &mut World) {
+        expire_lookups(&mut self.waiting_for_buildings, "building");
+        expire_lookups(&mut self.waiting_for_households, "household");
+        expire_timed_out(&mut self.waiting_for_simulation);
+        expire_timed_out(&mut self.waiting_for_geojson);
+        expire_timed_out(&mut self.waiting_for_proposal_list);
+        expire_timed_out(&mut self.waiting_for_checkpoints);
+        for waiting in self.waiting_for_proposals.values_mut() {
+            expire_timed_out(waiting);
+        }
+        self.waiting_for_proposals
+            .retain(|_, waiting| !waiting.is_empty());
+
+        let pending_queries = self.incoming.try_iter().collect::<Vec<_>>();
+
+        for PendingQuery { query, respond_to } in pending_queries {
+            match query {
+                Query::Simulation => {
+                    self.waiting_for_simulation.push(Waiting::new(respond_to));
+                    SimulationID::global_first(world).get_info(self.id_as(), world);
+                }
+                Query::Building(number) => {
+                    self.waiting_for_buildings
+                        .entry(number)
+                        .or_insert_with(Vec::new)
+                        .push(Waiting::new(respond_to));
+                    BuildingID::local_broadcast(world).get_ui_info_if_numbered(
+                        number,
+                        self.id_as(),
+                        world,
+                    );
+                }
+                Query::Household(number) => {
+                    self.waiting_for_households
+                        .entry(number)
+                        .or_insert_with(Vec::new)
+                        .push(Waiting::new(respond_to));
+                    HouseholdID::local_broadcast(world).get_ui_info_if_numbered(
+                        number,
+                        self.id_as(),
+                        world,
+                    );
+                }
+                Query::GeoJSON => {
+                    // one export at a time is enough for everyone waiting
+                    let export_running = self
+                        .geojson_export_started
+                        .map(|started| started.elapsed() < QUERY_TIMEOUT)
+                        .unwrap_or(false);
+                    if !export_running {
+                        GeoJSONExporterID::spawn(self.id_as(), self.geo_projection, world);
+                        self.geojson_export_started = Some(WallInstant::now());
+                    }
+                    self.waiting_for_geojson.push(Waiting::new(respond_to));
+                }
+                Query::Construction => {
+                    // collected as they happen, no need to ask the simulation
+                    respond(
+                        vec![Waiting::new(respond_to)],
+                        &self.recent_construction_events,
+                    );
+                }
+                Query::Proposals => {
+                    self.waiting_for_proposal_list
+                        .push(Waiting::new(respond_to));
+                    // anyone can ask, so only what is public is shown
+                    PlanManagerID::global_first(world).list_proposals(
+                        COption(None),
+                        self.id_as(),
+                        world,
+                    );
+                }
+                Query::Proposal(proposal_id) => {
+                    self.waiting_for_proposals
+                        .entry(proposal_id)
+                        .or_insert_with(Vec::new)
+                        .push(Waiting::new(respond_to));
+                    PlanManagerID::global_first(world).export_proposal(
+                        proposal_id,
+                        COption(None),
+                        self.id_as(),
+                        world,
+                    );
+                }
+                Query::Checkpoints => {
+                    self.waiting_for_checkpoints.push(Waiting::new(respond_to));
+                    PlanManagerID::global_first(world).get_checkpoints(self.id_as(), world);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SimulationInfo {
+    ticks: usize,
+    day: usize,
+    weekday: Weekday,
+    time_of_day: (usize, usize),
+    speed: u16,
+    paused: bool,
+}
+
+impl SimulationUI for QueryAPI {
+    fn on_simulation_info(
+        &mut self,
+        current_instant: Instant,
+        speed: u16,
+        paused: bool,
+        _world: &mut World,
+    ) {
+        let waiting = ::std::mem::replace(&mut self.waiting_for_simulation, Vec::new());
+        let day = Day::from(current_instant);
+        respond(
+            waiting,
+            &SimulationInfo {
+                ticks: current_instant.ticks(),
+                day: day.index(),
+                weekday: day.weekday(),
+                time_of_day: TimeOfDay::from(current_instant).hours_minutes(),
+                speed,
+                paused,
+            },
+        );
+    }
+}
+
+#[derive(Serialize)]
+struct BuildingInfo {
+    id: u64,
+    style: BuildingStyle,
+    households: Vec<u64>,
+}
+
+impl LandUseUI for QueryAPI {
+    fn on_building_constructed(
+        &mut self,
+        _id: BuildingID,
+        _lot: &Lot,
+        _style: BuildingStyle,
+        _world: &mut World,
+    ) {
+    }
+
+    fn on_building_destructed(&mut self, _id: BuildingID, _world: &mut World) {}
+
+    fn on_building_ui_info(
+        &mut self,
+        id: BuildingID,
+        style: BuildingStyle,
+        households: &CVec<HouseholdID>,
+        _world: &mut World,
+    ) {
+        let number = numeric_id(id.as_raw());
+        if let Some(waiting) = self.waiting_for_buildings.remove(&number) {
+            respond(
+                waiting,
+                &BuildingInfo {
+                    id: number,
+                    style,
+                    households: households
+                        .iter()
+                        .map(|household| numeric_id(household.as_raw()))
+                        .collect(),
+                },
+            );
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct HouseholdInfo<'a> {
+    id: u64,
+    core: &'a HouseholdCore,
+}
+
+impl HouseholdUI for QueryAPI {
+    fn on_household_ui_info(&mut self, id: HouseholdID, core: &HouseholdCore, _world: &mut World) {
+        let number = numeric_id(id.as_raw());
+        if let Some(waiting) = self.waiting_for_households.remove(&number) {
+            respond(waiting, &HouseholdInfo { id: number, core });
+        }
+    }
+}
+
+impl GeoJSONRecipient for QueryAPI {
+    fn on_geojson_exported(&mut self, geojson: &CString, _world: &mut World) -> Fate {
+        // already serialized
+        self.geojson_export_started = None;
+        let waiting = ::std::mem::replace(&mut self.waiting_for_geojson, Vec::new());
+        respond_with_json(waiting, geojson);
+
+        Fate::Live
+    }
+}
+
+#[derive(Serialize)]
+struct ProposalIDs {
+    open: Vec<ProposalID>,
+    implemented: Vec<ProposalID>,
+}
+
+impl ProposalListRecipient for QueryAPI {
+    fn on_proposal_list(
+        &mut self,
+        open: &CVec<ProposalID>,
+        implemented: &CVec<ProposalID>,
+        _world: &mut World,
+    ) {
+        let waiting = ::std::mem::replace(&mut self.waiting_for_proposal_list, Vec::new());
+        respond(
+            waiting,
+            &ProposalIDs {
+                open: open.iter().cloned().collect(),
+                implemented: implemented.iter().cloned().collect(),
+            },
+        );
+    }
+}
+
+impl ProposalFileRecipient for QueryAPI {
+    fn on_proposal_exported(
+        &mut self,
+        proposal_id: ProposalID,
+        maybe_file: &COption<ProposalFile>,
+        _world: &mut World,
+    ) -> Fate {
+        if let Some(waiting) = self.waiting_for_proposals.remove(&proposal_id) {
+            match **maybe_file {
+                Some(ref file) => respond(waiting, file),
+                None => {
+                    let message = format!("No proposal with id {}", proposal_id.0);
+                    for Waiting { respond_to, .. } in waiting {
+                        let _ = respond_to.send(Answer::NotFound(message.clone()));
+                    }
+                }
+            }
+        }
+
+        Fate::Live
+    }
+}
+
+impl ConstructionEventObserver for QueryAPI {
+    fn on_construction_events(&mut self, events: &CVec<ConstructionEvent>, _world: &mut World) {
+        for event in events {
+            if self.recent_construction_events.len() == N_RECENT_CONSTRUCTION_EVENTS {
+                self.recent_construction_events.pop_front();
+            }
+            self.recent_construction_events.push_back(event.clone());
+        }
+    }
+}
+
+// Planning commands of the server itself are sent on behalf of the query API,
+// which logs what went wrong with them and answers checkpoint queries
+impl PlanningUI for QueryAPI {
+    fn on_plans_update(
+        &mut self,
+        _: &PlanHistoryUpdate,
+        _: &CHashMap<ProposalID, ProposalUpdate>,
+        _: &mut World,
+    ) {
+    }
+
+    fn on_proposal_preview_update(
+        &mut self,
+        _: ProposalID,
+        _: &PlanResultUpdate,
+        _: &ActionGroups,
+        _: &ProposalEstimate,
+        _: &mut World,
+    ) {
+    }
+
+    fn on_proposal_rebased(&mut self, _: ProposalID, _: &CVec<GestureID>, _: &mut World) {}
+
+    fn on_proposals_merged(
+        &mut self,
+        _: ProposalID,
+        _: ProposalID,
+        _: &CVec<GestureID>,
+        _: &mut World,
+    ) {
+    }
+
+    fn on_plan_error(&mut self, proposal_id: ProposalID, error: &PlanError, _: &mut World) {
+        println!("Error in proposal {}: {}", proposal_id.0, &*error.description);
+    }
+
+    fn on_permission_denied(
+        &mut self,
+        proposal_id: ProposalID,
+        machine: MachineID,
+        action: PlanningAction,
+        _: &mut World,
+    ) {
+        if machine == self.id.as_raw().machine {
+            println!("Not allowed to {:?} proposal {}", action, proposal_id.0);
+        }
+    }
+
+    fn on_proposal_status_changed(
+        &mut self,
+        _: ProposalID,
+        _: ProposalStatus,
+        _: &Ballot,
+        _: u32,
+        _: &mut World,
+    ) {
+    }
+
+    fn on_checkpoints_update(&mut self, names: &CVec<CString>, _: &mut World) {
+        let waiting = ::std::mem::replace(&mut self.waiting_for_checkpoints, Vec::new());
+        let names = names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        respond(waiting, &names);
+    }
+
+    fn on_checkpoint_diff(&mut self, _: &CString, _: &CString, _: &PlanDiff, _: &mut World) {}
+
+    fn on_rolled_back(&mut self, checkpoint: &CString, _: &mut World) {
+        println!("Rolled back to checkpoint {}", &**checkpoint);
+    }
+
+    fn on_treasury_update(&mut self, _: ResourceAmount, _: ResourceAmount, _: &mut World) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unanswered_lookups_become_not_found() {
+        let (respond_to, response) = channel();
+        let mut waiting = HashMap::new();
+        waiting.insert(7, vec![Waiting::new(respond_to)]);
+
+        for _ in 0..FRAMES_UNTIL_NOT_FOUND {
+            expire_lookups(&mut waiting, "building");
+            assert!(response.try_recv().is_err());
+        }
+
+        expire_lookups(&mut waiting, "building");
+        match response.try_recv() {
+            Ok(Answer::NotFound(message)) => assert_eq!(message, "No building with id 7"),
+            _ => panic!("Expected a not found answer"),
+        }
+        assert!(waiting.is_empty());
+    }
+}
+
+mod kay_auto;
+pub use self::kay_auto::*;
+
+pub fn setup(system: &mut ActorSystem) {
+    system.register::<QueryAPI>();
+    auto_setup(system);
+}
+
+pub fn spawn(
+    world: &mut World,
+    incoming: Receiver<PendingQuery>,
+    geo_projection: GeoProjection,
+) -> QueryAPIID {
+    QueryAPIID::spawn(
+        External::new(QueryAPINonPersistedState::new(incoming, geo_projection)),
+        world,
+    )
+}
@@ -0,0 +1,6 @@
+extern crate kay_codegen;
+use kay_codegen::scan_and_generate;
+
+fn main() {
+    scan_and_generate("game_server");
+}
@@ -0,0 +1,46 @@
+use kay::{World, ActorSystem, External};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use simulation::{FastForwardObserver, FastForwardObserverID};
+
+// Read by the main loop, which skips frame pacing and networking while it is set
+pub type FastForwarding = Arc<AtomicBool>;
+
+#[derive(Compact, Clone)]
+pub struct FastForwardWatcher {
+    id: FastForwardWatcherID,
+    fast_forwarding: External<FastForwarding>,
+}
+
+impl FastForwardWatcher {
+    pub fn spawn(
+        id: FastForwardWatcherID,
+        fast_forwarding: &External<FastForwarding>,
+        _: &mut World,
+    ) -> FastForwardWatcher {
+        FastForwardWatcher {
+            id,
+            fast_forwarding: fast_forwarding.steal(),
+        }
+    }
+}
+
+impl FastForwardObserver for FastForwardWatcher {
+    fn on_fast_forward(&mut self, fast_forwarding: bool, _: &mut World) {
+        self.fast_forwarding.store(fast_forwarding, Ordering::Relaxed);
+    }
+}
+
+mod kay_auto;
+pub use self::kay_auto::*;
+
+pub fn setup(system: &mut ActorSystem) {
+    system.register::<FastForwardWatcher>();
+    auto_setup(system);
+}
+
+pub fn spawn(world: &mut World) -> FastForwarding {
+    let fast_forwarding = FastForwarding::default();
+    FastForwardWatcherID::spawn(External::new(fast_forwarding.clone()), world);
+    fast_forwarding
+}
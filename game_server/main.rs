@@ -4,6 +4,17 @@ use citybound_common::*;
 extern crate rouille;
 use rouille::{Response, extension_to_mime};
 
+#[macro_use]
+extern crate compact_macros;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+mod api;
+mod profiler;
+mod fast_forward;
+
 #[macro_use]
 extern crate rust_embed_flag;
 
@@ -29,6 +40,15 @@ fn main() {
                 .possible_values(&["local", "lan", "internet"])
                 .default_value("local")
                 .help("Where to expose the simulation. Sets defaults other settings."),
+        ).arg(
+            Arg::with_name("headless")
+                .long("headless")
+                .help("Only run the simulation, without serving the browser UI"),
+        ).arg(
+            Arg::with_name("serve-api")
+                .long("serve-api")
+                .requires("headless")
+                .help("When headless, still serve the query API from --bind"),
         ).arg(
             Arg::with_name("bind")
                 .long("bind")
@@ -37,7 +57,7 @@ fn main() {
                     ("mode", Some("local"), "localhost:1234"),
                     ("mode", Some("lan"), "0.0.0.0:1234"),
                     ("mode", Some("internet"), "0.0.0.0:1234"),
-                ]).help("Address and port to serve the browser UI from"),
+                ]).help("Address and port to serve the browser UI and query API from"),
         ).arg(
             Arg::with_name("bind-sim")
                 .long("bind-sim")
@@ -68,6 +88,128 @@ fn main() {
                 .value_name("n-turns")
                 .default_value("5")
                 .help("How many network turns to skip if server/client are ahead"),
+        ).arg(
+            Arg::with_name("load")
+                .long("load")
+                .value_name("file")
+                .help("Savegame to restore the city from on startup"),
+        ).arg(
+            Arg::with_name("save")
+                .long("save")
+                .value_name("file")
+                .help("Savegame to periodically save the city to"),
+        ).arg(
+            Arg::with_name("autosave-every")
+                .long("autosave-every")
+                .value_name("n-minutes")
+                .default_value("5")
+                .help("How many real-time minutes to wait between saves"),
+        ).arg(
+            Arg::with_name("journal")
+                .long("journal")
+                .value_name("file")
+                .help("Record all planning and simulation commands to a journal"),
+        ).arg(
+            Arg::with_name("replay")
+                .long("replay")
+                .value_name("journal")
+                .conflicts_with("load")
+                .help("Re-run a recorded journal, starting from an empty world"),
+        ).arg(
+            Arg::with_name("fast-forward-until")
+                .long("fast-forward-until")
+                .value_name("day hh:mm")
+                .validator(|date| fast_forward_instant(&date).map(|_| ()))
+                .help("Simulate as fast as possible until this day and time, day 0 starts at 7:00"),
+        ).arg(
+            Arg::with_name("import-proposal")
+                .long("import-proposal")
+                .value_name("file")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Add the proposal from a plan file to the city on startup"),
+        ).arg(
+            Arg::with_name("export-proposal")
+                .long("export-proposal")
+                .value_names(&["proposal-id", "file"])
+                .help("Write a proposal of the (loaded) city to a plan file on startup"),
+        ).arg(
+            Arg::with_name("import-geojson")
+                .long("import-geojson")
+                .value_name("file")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Add the roads and zones of a GeoJSON file as a new proposal on startup"),
+        ).arg(
+            Arg::with_name("geo-origin")
+                .long("geo-origin")
+                .value_name("longitude,latitude")
+                .help("Where the center of the city is, for GeoJSON imports and exports"),
+        ).arg(
+            Arg::with_name("export-geojson")
+                .long("export-geojson")
+                .value_name("file")
+                .help("Write roads, lots and buildings of the (loaded) city to GeoJSON on startup"),
+        ).arg(
+            Arg::with_name("generate-city")
+                .long("generate-city")
+                .value_name("layout")
+                .possible_values(&["grid", "radial", "organic"])
+                .help("Generate streets and zones with the given layout and build them on startup"),
+        ).arg(
+            Arg::with_name("generation-seed")
+                .long("generation-seed")
+                .value_name("seed")
+                .default_value("0")
+                .help("Seed for --generate-city, the same seed always gives the same city"),
+        ).arg(
+            Arg::with_name("planning-policy")
+                .long("planning-policy")
+                .value_name("policy")
+                .possible_values(&["free-for-all", "owner-only", "voting"])
+                .help("Who may edit and implement others' proposals, free-for-all unless loaded"),
+        ).arg(
+            Arg::with_name("construction-crews")
+                .long("construction-crews")
+                .value_name("n-crews")
+                .default_value("4")
+                .help("How many construction sites can be worked on at the same time"),
+        ).arg(
+            Arg::with_name("construction-log")
+                .long("construction-log")
+                .value_name("file")
+                .help("Write when constructions start and finish to a file, one event per line"),
+        ).arg(
+            Arg::with_name("initial-funds")
+                .long("initial-funds")
+                .value_name("money")
+                .default_value("10000000")
+                .help("Money in the city treasury when starting a new city"),
+        ).arg(
+            Arg::with_name("income-per-hour")
+                .long("income-per-hour")
+                .value_name("money")
+                .default_value("100000")
+                .help("Money the city treasury receives every simulated hour"),
+        ).arg(
+            Arg::with_name("open-crash-report")
+                .long("open-crash-report")
+                .help("Open the crash report with the default viewer if the simulation breaks"),
+        ).arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .help("Measure time spent per actor and message type and report it periodically"),
+        ).arg(
+            Arg::with_name("profile-every")
+                .long("profile-every")
+                .value_name("n-seconds")
+                .default_value("10")
+                .help("How many real-time seconds each profiling report covers"),
+        ).arg(
+            Arg::with_name("profile-to")
+                .long("profile-to")
+                .value_name("file")
+                .help("Append profiling reports to a file instead of printing them"),
         ).get_matches();
 
     let serve_host_port = arg_matches.value_of("bind").unwrap().to_owned();
@@ -84,52 +226,77 @@ fn main() {
         serve_host_port.split(':').nth(1).unwrap(),
     );
 
-    ::std::thread::spawn(move || {
+    let headless = arg_matches.is_present("headless");
+    // the browser UI always comes with the query API
+    let serve_api = !headless || arg_matches.is_present("serve-api");
+    let (query_sender, query_receiver) = api::query_channel();
+    let query_receiver = ::std::cell::Cell::new(Some(query_receiver));
+    let latest_profile = profiler::LatestReport::default();
+    let latest_profile_2 = latest_profile.clone();
+
+    if headless {
+        println!("Citybound {} (headless)", VERSION.trim());
+        if serve_api {
+            println!("  {: ^41}  ", format!("query API at http://{}/api/", my_host));
+        }
+        println!("SIMULATION LOG:");
+    } else {
         println!("  {: ^41}  ", format!("Citybound {}", VERSION.trim()));
         println!("  {: ^41}  ", "please connect with your browser");
         println!("╭───────────────────────────────────────────╮");
         println!("│ {: ^41} │", format!("http://{}", my_host));
         println!("╰───────────────────────────────────────────╯");
         println!("SIMULATION LOG:");
+    }
 
-        rouille::start_server(serve_host_port, move |request| {
-            if request.raw_url() == "/" {
-                println!("{:?} loaded page", request.remote_addr());
-
-                let template = std::str::from_utf8(
-                    &Asset::get("index.html").expect("index.html should exist as asset"),
-                ).unwrap()
-                .to_owned();
-
-                let rendered = template
-                    .replace("CB_VERSION", VERSION.trim())
-                    .replace(
-                        "CB_BATCH_MESSAGE_BYTES",
-                        arg_matches_2.value_of("batch-msg-b").unwrap(),
-                    ).replace(
-                        "CB_ACCEPTABLE_TURN_DISTANCE",
-                        arg_matches_2.value_of("ok-turn-dist").unwrap(),
-                    ).replace(
-                        "CB_SKIP_TURNS_PER_TURN_AHEAD",
-                        arg_matches_2.value_of("skip-ratio").unwrap(),
-                    );
+    if serve_api {
+        ::std::thread::spawn(move || {
+            rouille::start_server(serve_host_port, move |request| {
+                if let Some(response) =
+                    api::handle_request(request, &query_sender, &latest_profile_2)
+                {
+                    response
+                } else if headless {
+                    Response::text("Running headless, only /api/ is available")
+                        .with_status_code(404)
+                } else if request.raw_url() == "/" {
+                    println!("{:?} loaded page", request.remote_addr());
 
-                Response::html(rendered)
-            } else if let Some(asset) = Asset::get(&request.url()[1..]) {
-                Response::from_data(
-                    if request.url().ends_with(".wasm") {
-                        "application/wasm"
-                    } else {
-                        extension_to_mime(request.url().split('.').last().unwrap_or(""))
-                    },
-                    asset,
-                )
-            } else {
-                Response::html(format!("404 error. Not found: {}", request.url()))
-                    .with_status_code(404)
-            }
+                    let template = std::str::from_utf8(
+                        &Asset::get("index.html").expect("index.html should exist as asset"),
+                    ).unwrap()
+                    .to_owned();
+
+                    let rendered = template
+                        .replace("CB_VERSION", VERSION.trim())
+                        .replace(
+                            "CB_BATCH_MESSAGE_BYTES",
+                            arg_matches_2.value_of("batch-msg-b").unwrap(),
+                        ).replace(
+                            "CB_ACCEPTABLE_TURN_DISTANCE",
+                            arg_matches_2.value_of("ok-turn-dist").unwrap(),
+                        ).replace(
+                            "CB_SKIP_TURNS_PER_TURN_AHEAD",
+                            arg_matches_2.value_of("skip-ratio").unwrap(),
+                        );
+
+                    Response::html(rendered)
+                } else if let Some(asset) = Asset::get(&request.url()[1..]) {
+                    Response::from_data(
+                        if request.url().ends_with(".wasm") {
+                            "application/wasm"
+                        } else {
+                            extension_to_mime(request.url().split('.').last().unwrap_or(""))
+                        },
+                        asset,
+                    )
+                } else {
+                    Response::html(format!("404 error. Not found: {}", request.url()))
+                        .with_status_code(404)
+                }
+            });
         });
-    });
+    }
 
     util::init::ensure_crossplatform_proper_thread(move || {
         let mut system = Box::new(kay::ActorSystem::new(kay::Networking::new(
@@ -152,54 +319,303 @@ fn main() {
         )));
 
         setup_common(&mut system);
+        api::setup(&mut system);
+        fast_forward::setup(&mut system);
 
         let world = &mut system.world();
 
         system.networking_connect();
 
         let simulation = simulation::spawn(world);
-        util::init::set_error_hook();
+        let fast_forwarding_flag = fast_forward::spawn(world);
+        util::init::set_error_hook(arg_matches.is_present("open-crash-report"));
+
+        let maybe_replay = arg_matches.value_of("replay").map(|replay_path| {
+            match journal::JournalFile::read_from(replay_path) {
+                Ok(journal_file) => {
+                    println!("Replaying journal {}", replay_path);
+                    journal_file
+                }
+                Err(err) => panic!("Couldn't read journal {}: {}", replay_path, err),
+            }
+        });
+
+        let (initial_proposal_id, to_replay) = match maybe_replay {
+            Some(journal_file) => (journal_file.header.initial_proposal, journal_file.entries),
+            None => (planning::ProposalID::new(), Vec::new()),
+        };
+
+        let journal = journal::spawn(
+            world,
+            arg_matches.value_of("journal"),
+            initial_proposal_id,
+            to_replay,
+        );
+
+        let maybe_projection = arg_matches.value_of("geo-origin").map(|origin| {
+            planning::exchange::geojson::GeoProjection::parse(origin)
+                .unwrap_or_else(|err| panic!("Invalid geo origin {}: {}", origin, err))
+        });
 
-        let plan_manager = planning::spawn(world);
-        construction::spawn(world);
+        let plan_manager = planning::spawn(world, initial_proposal_id);
+        let construction = construction::spawn(world);
+        let max_crews = arg_matches
+            .value_of("construction-crews")
+            .unwrap()
+            .parse()
+            .expect("Number of construction crews should be a number");
+        construction.set_max_crews(max_crews, world);
+        let initial_funds = arg_matches
+            .value_of("initial-funds")
+            .unwrap()
+            .parse()
+            .expect("Initial funds should be a number");
+        let income_per_hour = arg_matches
+            .value_of("income-per-hour")
+            .unwrap()
+            .parse()
+            .expect("Income per hour should be a number");
+        let treasury = governance::spawn(world, initial_funds, income_per_hour);
         transport::spawn(world, simulation);
         economy::spawn(world, simulation, plan_manager);
+        let query_api = api::spawn(
+            world,
+            query_receiver
+                .take()
+                .expect("Simulation thread should only be started once"),
+            maybe_projection.unwrap_or_default(),
+        );
+        construction.subscribe(query_api.into(), world);
+        if let Some(log_path) = arg_matches.value_of("construction-log") {
+            let log = construction::events::spawn_log(world, log_path);
+            construction.subscribe(log.into(), world);
+        }
         system.process_all_messages();
 
-        let mut frame_counter = util::init::FrameCounter::new();
-        let mut skip_turns = 0;
+        let mut maybe_saved_policy = None;
+        if let Some(load_path) = arg_matches.value_of("load") {
+            match persistence::SaveGame::read_from(load_path) {
+                Ok(save_game) => {
+                    println!("Restoring city from {}", load_path);
+                    maybe_saved_policy = Some(save_game.planning.policy);
+                    persistence::restore(world, save_game, simulation, plan_manager, treasury);
+                    system.process_all_messages();
+                }
+                Err(err) => panic!("Couldn't load city from {}: {}", load_path, err),
+            }
+        }
 
-        loop {
-            frame_counter.start_frame();
+        if let Some(import_paths) = arg_matches.values_of("import-proposal") {
+            for import_path in import_paths {
+                match planning::exchange::import(world, import_path, query_api.into()) {
+                    Ok(proposal_id) => {
+                        println!("Imported {} as proposal {}", import_path, proposal_id.0)
+                    }
+                    Err(err) => panic!("Couldn't import proposal {}: {}", import_path, err),
+                }
+            }
+            system.process_all_messages();
+        }
 
+        if let Some(import_paths) = arg_matches.values_of("import-geojson") {
+            for import_path in import_paths {
+                match planning::exchange::geojson::import(
+                    world,
+                    import_path,
+                    maybe_projection,
+                    query_api.into(),
+                ) {
+                    Ok(proposal_id) => {
+                        println!("Imported {} as proposal {}", import_path, proposal_id.0)
+                    }
+                    Err(err) => panic!("Couldn't import GeoJSON {}: {}", import_path, err),
+                }
+            }
             system.process_all_messages();
+        }
+
+        if let Some(layout_name) = arg_matches.value_of("generate-city") {
+            use planning::generation::{GeneratorSettings, StreetLayout};
+            let layout = StreetLayout::parse(layout_name).unwrap();
+            let seed = arg_matches
+                .value_of("generation-seed")
+                .unwrap()
+                .parse()
+                .expect("Generation seed should be a number");
+            let proposal_id = planning::ProposalID::new();
+            plan_manager.add_generated_plan(
+                proposal_id,
+                query_api.into(),
+                GeneratorSettings::new(layout, seed),
+                planning::StepID::new(),
+                world,
+            );
+            plan_manager.implement(proposal_id, query_api.into(), world);
+            system.process_all_messages();
+            println!("Generated a {} city with seed {}", layout_name, seed);
+        }
+
+        // only applies to players, not to the startup imports and generation above.
+        // A loaded city keeps its policy unless another one is given
+        let policy = match arg_matches.value_of("planning-policy") {
+            Some(policy_name) => planning::permissions::PlanningPolicy::parse(policy_name).unwrap(),
+            None => maybe_saved_policy.unwrap_or_default(),
+        };
+        plan_manager.set_policy(policy, world);
+        system.process_all_messages();
 
-            if system.shutting_down {
-                break;
+        if let Some(mut export_args) = arg_matches.values_of("export-proposal") {
+            let proposal_id = export_args.next().unwrap();
+            let export_path = export_args.next().unwrap();
+            match planning::ProposalID::parse(proposal_id) {
+                Ok(proposal_id) => planning::exchange::export(world, proposal_id, export_path),
+                Err(err) => panic!("Invalid proposal id {}: {}", proposal_id, err),
             }
+            system.process_all_messages();
+        }
+
+        if let Some(export_path) = arg_matches.value_of("export-geojson") {
+            planning::exchange::geojson::export(
+                world,
+                export_path,
+                maybe_projection.unwrap_or_default(),
+            );
+            system.process_all_messages();
+        }
+
+        if let Some(fast_forward_until) = arg_matches.value_of("fast-forward-until") {
+            let until = fast_forward_instant(fast_forward_until).expect("validated by clap");
+            simulation.fast_forward_to(until, world);
+        }
+
+        let save_path = arg_matches.value_of("save");
+        let autosave_interval = ::std::time::Duration::from_secs(
+            60 * arg_matches
+                .value_of("autosave-every")
+                .unwrap()
+                .parse::<u64>()
+                .unwrap(),
+        );
+        let mut last_save = ::std::time::Instant::now();
+        // snapshots are taken even without a save path, for emergency saves
+        let last_good_save = persistence::LastGoodSave::default();
+        persistence::save(
+            world,
+            None,
+            &last_good_save,
+            simulation,
+            plan_manager,
+            treasury,
+        );
+
+        let mut maybe_profiler = if arg_matches.is_present("profile") {
+            Some(profiler::Profiler::new(
+                ::std::time::Duration::from_secs(
+                    arg_matches
+                        .value_of("profile-every")
+                        .unwrap()
+                        .parse()
+                        .unwrap(),
+                ),
+                arg_matches.value_of("profile-to").map(|path| path.to_owned()),
+                latest_profile.clone(),
+            ))
+        } else {
+            None
+        };
+
+        let mut frame_counter = util::init::FrameCounter::new();
+        let mut skip_turns = 0;
 
-            if skip_turns == 0 {
-                simulation.progress(world);
+        // panics are caught here so that we can still attempt an emergency save
+        let main_loop = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+            loop {
+                frame_counter.start_frame();
 
                 system.process_all_messages();
-            }
 
-            system.networking_send_and_receive();
+                // a crashed actor might have left the rest of the world inconsistent
+                if system.shutting_down || util::crash_report::crash_happened() {
+                    break;
+                }
 
-            system.process_all_messages();
+                query_api.process_queries(world);
+
+                // while fast-forwarding, we skip networking turns and frame pacing
+                let fast_forwarding =
+                    fast_forwarding_flag.load(::std::sync::atomic::Ordering::Relaxed);
+
+                if skip_turns == 0 || fast_forwarding {
+                    simulation.progress(world);
+
+                    system.process_all_messages();
+
+                    journal.end_frame(world);
+
+                    if util::crash_report::crash_happened() {
+                        break;
+                    }
+                }
+
+                if !fast_forwarding {
+                    system.networking_send_and_receive();
+
+                    system.process_all_messages();
 
-            if skip_turns > 0 {
-                skip_turns -= 1;
-            //println!("Skipping! {} left", skip_turns);
-            } else {
-                let maybe_should_skip = system.networking_finish_turn();
-                if let Some(should_skip) = maybe_should_skip {
-                    skip_turns = should_skip.min(100);
+                    if skip_turns > 0 {
+                        skip_turns -= 1;
+                    //println!("Skipping! {} left", skip_turns);
+                    } else {
+                        let maybe_should_skip = system.networking_finish_turn();
+                        if let Some(should_skip) = maybe_should_skip {
+                            skip_turns = should_skip.min(100);
+                        }
+                    }
+                }
+
+                if last_save.elapsed() > autosave_interval {
+                    persistence::save(
+                        world,
+                        save_path,
+                        &last_good_save,
+                        simulation,
+                        plan_manager,
+                        treasury,
+                    );
+                    last_save = ::std::time::Instant::now();
                 }
+
+                if let Some(ref mut profiler) = maybe_profiler {
+                    profiler.end_frame(&mut system);
+                }
+
+                //frame_counter.print_fps();
+                if !fast_forwarding {
+                    frame_counter.sleep_if_faster_than(120);
+                }
+            }
+        }));
+
+        if main_loop.is_err() || util::crash_report::crash_happened() {
+            let emergency_save_path = match save_path {
+                Some(save_path) => format!("{}.emergency", save_path),
+                None => "cb_emergency_save.json".to_owned(),
+            };
+            println!("Attempting emergency save to {}", emergency_save_path);
+
+            // only uses the last complete save, the actors might be in a broken state
+            match persistence::write_last_good_save(&last_good_save, &emergency_save_path) {
+                Ok(()) => println!("Emergency save written"),
+                Err(err) => println!("Emergency save failed: {}", err),
             }
 
-            //frame_counter.print_fps();
-            frame_counter.sleep_if_faster_than(120);
+            ::std::process::exit(1);
         }
     });
 }
+
+fn fast_forward_instant(date: &str) -> Result<simulation::Instant, String> {
+    let date = simulation::Date::parse(date)?;
+    date.instant()
+        .ok_or_else(|| "The simulation starts on day 0 at 7:00".to_owned())
+}
@@ -0,0 +1,146 @@
+use kay::ActorSystem;
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use util::profiling::{self, Measurement};
+
+// The latest report as JSON, shared with the query API thread
+pub type LatestReport = Arc<Mutex<Option<String>>>;
+
+#[derive(Serialize)]
+pub struct ActorTiming {
+    label: &'static str,
+    count: usize,
+    total_ms: f64,
+    max_ms: f64,
+}
+
+#[derive(Serialize)]
+pub struct ProfilingReport {
+    seconds: f64,
+    frames: usize,
+    // time spent in ticks and wake-ups, per actor type
+    actors: Vec<ActorTiming>,
+    // number of handled messages, per message type
+    messages: Vec<(String, usize)>,
+}
+
+fn as_ms(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + f64::from(duration.subsec_nanos()) / 1_000_000.0
+}
+
+impl ProfilingReport {
+    fn print(&self) {
+        println!(
+            "PROFILE over {:.1}s ({} frames)",
+            self.seconds, self.frames
+        );
+        for timing in &self.actors {
+            println!(
+                "  {:<40} {:>10} calls {:>10.1}ms total {:>8.2}ms max",
+                timing.label, timing.count, timing.total_ms, timing.max_ms
+            );
+        }
+        for &(ref message, count) in &self.messages {
+            println!("  {:<40} {:>10} msgs", message, count);
+        }
+    }
+}
+
+pub struct Profiler {
+    report_every: Duration,
+    report_path: Option<String>,
+    period_start: Instant,
+    frames: usize,
+    measurements: HashMap<&'static str, Measurement>,
+    message_counts: HashMap<String, usize>,
+    latest_report: LatestReport,
+}
+
+impl Profiler {
+    pub fn new(
+        report_every: Duration,
+        report_path: Option<String>,
+        latest_report: LatestReport,
+    ) -> Profiler {
+        profiling::enable();
+        Profiler {
+            report_every,
+            report_path,
+            period_start: Instant::now(),
+            frames: 0,
+            measurements: HashMap::new(),
+            message_counts: HashMap::new(),
+            latest_report,
+        }
+    }
+
+    pub fn end_frame(&mut self, system: &mut ActorSystem) {
+        self.frames += 1;
+
+        for (label, measurement) in profiling::take_measurements() {
+            self.measurements
+                .entry(label)
+                .or_insert_with(Measurement::default)
+                .add(&measurement);
+        }
+
+        for (message, count) in system.get_message_statistics() {
+            *self.message_counts.entry(message).or_insert(0) += count;
+        }
+        system.reset_message_statistics();
+
+        if self.period_start.elapsed() > self.report_every {
+            self.report();
+        }
+    }
+
+    fn report(&mut self) {
+        let mut actors = self
+            .measurements
+            .drain()
+            .map(|(label, measurement)| ActorTiming {
+                label,
+                count: measurement.count,
+                total_ms: as_ms(measurement.total),
+                max_ms: as_ms(measurement.max),
+            }).collect::<Vec<_>>();
+        actors.sort_by(|a, b| b.total_ms.partial_cmp(&a.total_ms).unwrap());
+
+        let mut messages = self.message_counts.drain().collect::<Vec<_>>();
+        messages.sort_by_key(|&(_, count)| ::std::cmp::Reverse(count));
+
+        let report = ProfilingReport {
+            seconds: as_ms(self.period_start.elapsed()) / 1000.0,
+            frames: self.frames,
+            actors,
+            messages,
+        };
+
+        let json = ::serde_json::to_string(&report).expect("Should be able to serialize report");
+
+        if let Some(ref path) = self.report_path {
+            let written = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| writeln!(file, "{}", json));
+
+            if let Err(err) = written {
+                println!("Couldn't write profiling report to {}: {}", path, err);
+            }
+        } else {
+            report.print();
+        }
+
+        *self
+            .latest_report
+            .lock()
+            .expect("Latest report should be available") = Some(json);
+
+        self.period_start = Instant::now();
+        self.frames = 0;
+    }
+}